@@ -0,0 +1,2 @@
+pub mod lockdep;
+pub mod sync;
\ No newline at end of file
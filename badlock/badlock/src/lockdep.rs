@@ -0,0 +1,399 @@
+//! Lock dependency tracking for the `sync` wrappers, in debug builds. Each thread keeps the locks
+//! it holds, and every lock taken while others are held adds a dependency to a global lock order
+//! graph. A new dependency from `held` to `lock` closes a cycle when the graph already leads from
+//! `lock` back to `held`, which is reported as an `Inversion` of the static analysis for two locks
+//! and as a `Cycle` for more. Threads remember the dependencies they added, so the graph is only
+//! locked for dependencies that are new to the thread. Dropped locks are forgotten, so the graph only
+//! keeps the locks that still exist.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::panic::Location;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use lock_detection::report::{Cycle, Finding, Inversion, Report, Step};
+use lock_detection::util::lock_graph::LockGraph;
+
+static NEXT_ID : AtomicUsize = AtomicUsize::new(1);
+
+/// Identifies a lock for as long as it lives. It is assigned when the lock is first taken, since
+/// until then the lock may still be moved.
+#[derive(Debug, Default)]
+pub struct LockId(AtomicUsize);
+
+impl LockId {
+
+    pub const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    pub fn get(&self) -> usize {
+
+        let id = self.0.load(Ordering::Relaxed);
+        if id != 0 {
+            return id;
+        }
+
+        let fresh = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        match self.0.compare_exchange(0, fresh, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => fresh,
+            Err(id) => id
+        }
+
+    }
+
+}
+
+/// How a lock is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Exclusive,
+    // a read lock, which other read locks do not wait for
+    Shared
+}
+
+/// A lock the current thread holds.
+#[derive(Debug, Clone, Copy)]
+struct Held {
+    lock : usize,
+    // names the lock once it is part of a finding
+    name : fn(usize) -> String,
+    site : &'static Location<'static>,
+    access : Access
+}
+
+/// The lock order graph, and what was reported of it.
+#[derive(Debug, Default)]
+struct Graph {
+    // human readable names of the locks in dependencies
+    names : HashMap<usize, String>,
+    // per dependency, the sites of the held lock and of the lock taken, as first seen
+    dependencies : LockGraph<usize, (&'static Location<'static>, &'static Location<'static>)>,
+    report : Report
+}
+
+impl Graph {
+
+    fn name(&self, lock : usize) -> String {
+        self.names.get(&lock).cloned().unwrap_or_else(|| format!("#{}", lock))
+    }
+
+    /// Adds the finding unless it was reported before, printing it when it is new.
+    fn found(&mut self, finding : Finding) {
+
+        if self.report.findings.contains(&finding) {
+            return;
+        }
+
+        eprint!("{}", Report::new(vec![finding.clone()]));
+        self.report.findings.push(finding);
+
+    }
+
+    fn step(&self, held : usize, acquired : usize) -> Step {
+        let (held_at, acquired_at) = self.dependencies.get(&held, &acquired).unwrap();
+        Step {
+            held : held_at.to_string(),
+            resource : self.name(held),
+            acquired : acquired_at.to_string()
+        }
+    }
+
+    /// Records that `held` was held while `lock` was taken at `site`, and reports the cycle the
+    /// dependency closes, if any.
+    fn depend(&mut self, held : &Held, lock : usize, name : fn(usize) -> String, site : &'static Location<'static>) {
+
+        if self.dependencies.get(&held.lock, &lock).is_some() {
+            return;
+        }
+
+        self.names.entry(held.lock).or_insert_with(|| (held.name)(held.lock));
+        self.names.entry(lock).or_insert_with(|| name(lock));
+
+        let steps : Vec<Step> = match self.dependencies.insert(held.lock, lock, (held.site, site)) {
+            Some(path) => std::iter::once(self.step(held.lock, lock))
+            .chain(path.into_iter().map(|(from, to)| self.step(from, to)))
+            .collect(),
+            None => return
+        };
+
+        let report = match steps.as_slice() {
+            [first, second] => Report::default().with_inversions(vec![Inversion {
+                first_held : first.held.clone(),
+                first_resource : first.resource.clone(),
+                first_acquired : first.acquired.clone(),
+                second_held : second.held.clone(),
+                second_resource : second.resource.clone(),
+                second_acquired : second.acquired.clone()
+            }]),
+            _ => Report::default().with_cycles(vec![Cycle { steps }])
+        };
+
+        eprint!("{}", report);
+        self.report.inversions.extend(report.inversions);
+        self.report.cycles.extend(report.cycles);
+
+    }
+
+    /// Drops the lock from the graph, once it no longer exists.
+    fn forget(&mut self, lock : usize) {
+        self.dependencies.remove(&lock);
+        self.names.remove(&lock);
+    }
+
+}
+
+thread_local! {
+    static HELD : RefCell<Vec<Held>> = const { RefCell::new(Vec::new()) };
+    // the dependencies this thread knows to be in the graph, as of the number of locks forgotten
+    static KNOWN : RefCell<(usize, HashSet<(usize, usize)>)> = RefCell::new((0, HashSet::new()));
+}
+
+// the number of locks forgotten, after which threads no longer trust what they know of the graph
+static FORGOTTEN : AtomicUsize = AtomicUsize::new(0);
+
+static GRAPH : Mutex<Option<Graph>> = Mutex::new(None);
+
+fn graph<Output>(f : impl FnOnce(&mut Graph) -> Output) -> Output {
+    let mut graph = GRAPH.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(graph.get_or_insert_with(Graph::default))
+}
+
+/// Everything reported so far, by any thread.
+pub fn report() -> Report {
+    graph(|graph| graph.report.clone())
+}
+
+/// Records that the current thread takes the lock `id` at `site`, named by `name` once it is part
+/// of a finding. A blocking acquisition reports the lock if the thread already holds it, and panics
+/// rather than deadlocking when either is exclusive, and adds a dependency on each lock the thread
+/// holds. Returns the lock the guard releases, none in release builds.
+pub fn acquire(id : &LockId, name : fn(usize) -> String, site : &'static Location<'static>, access : Access, blocking : bool) -> usize {
+
+    if !cfg!(debug_assertions) {
+        return 0;
+    }
+
+    let lock = id.get();
+
+    if blocking {
+
+        let (first, new) = HELD.with(|held| {
+            let held = held.borrow();
+            let first = held.iter().rev().find(|first| first.lock == lock).copied();
+            let new : Vec<Held> = KNOWN.with(|known| {
+                let (ref mut forgotten, ref mut known) = *known.borrow_mut();
+                let now = FORGOTTEN.load(Ordering::Relaxed);
+                if *forgotten != now {
+                    *forgotten = now;
+                    known.clear();
+                }
+                held.iter().filter(|other| other.lock != lock && known.insert((other.lock, lock))).copied().collect()
+            });
+            (first, new)
+        });
+
+        if !new.is_empty() {
+            graph(|graph| {
+                for other in new.iter() {
+                    graph.depend(other, lock, name, site);
+                }
+            });
+        }
+
+        if let Some(first) = first {
+
+            let finding = Finding {
+                first_lock : first.site.to_string(),
+                resource : name(lock),
+                second_lock : site.to_string(),
+                ..Finding::default()
+            };
+            graph(|graph| graph.found(finding.clone()));
+
+            if first.access == Access::Exclusive || access == Access::Exclusive {
+                panic!(
+                    "badlock: {} is locked again at {} while this thread still holds it since {}",
+                    finding.resource, finding.second_lock, finding.first_lock
+                );
+            }
+
+        }
+
+    }
+
+    HELD.with(|held| held.borrow_mut().push(Held { lock, name, site, access }));
+    lock
+
+}
+
+/// Records that the current thread released `lock`, as returned by `acquire`.
+pub fn release(lock : usize) {
+
+    if lock == 0 {
+        return;
+    }
+
+    // guards may be dropped while the thread's locals are torn down
+    let _ = HELD.try_with(|held| {
+        let mut held = held.borrow_mut();
+        if let Some(index) = held.iter().rposition(|entry| entry.lock == lock) {
+            held.remove(index);
+        }
+    });
+
+}
+
+/// Forgets the lock `id` is of, which is being dropped, so that the graph only keeps the locks
+/// that still exist.
+pub fn forget(id : &LockId) {
+
+    let lock = id.0.load(Ordering::Relaxed);
+    if lock == 0 || !cfg!(debug_assertions) {
+        return;
+    }
+
+    graph(|graph| graph.forget(lock));
+    FORGOTTEN.fetch_add(1, Ordering::Relaxed);
+
+}
+
+impl Drop for LockId {
+    fn drop(&mut self) {
+        forget(self);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub fn findings_on(resource : &str) -> Vec<Finding> {
+        report().findings.into_iter().filter(|finding| finding.resource == resource).collect()
+    }
+
+    #[test]
+    pub fn test_reports_inversions_once() {
+
+        // nothing is tracked in release builds
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let (a, b) = (LockId::new(), LockId::new());
+        let name = |lock| format!("lock #{}", lock);
+
+        let first = acquire(&a, name, Location::caller(), Access::Exclusive, true);
+        let second = acquire(&b, name, Location::caller(), Access::Exclusive, true);
+        release(second);
+        release(first);
+
+        let resources = [name(a.get()), name(b.get())];
+        let inversion = |report : Report| report.inversions.into_iter()
+            .filter(|inversion| resources.contains(&inversion.first_resource) && resources.contains(&inversion.second_resource))
+            .count();
+        assert_eq!(inversion(report()), 0);
+
+        for _ in 0..2 {
+            let second = acquire(&b, name, Location::caller(), Access::Exclusive, true);
+            let first = acquire(&a, name, Location::caller(), Access::Exclusive, true);
+            release(first);
+            release(second);
+        }
+        assert_eq!(inversion(report()), 1);
+
+    }
+
+    #[test]
+    pub fn test_forgets_dropped_locks() {
+
+        // nothing is tracked in release builds
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let (a, b) = (LockId::new(), LockId::new());
+        let name = |lock| format!("dropped #{}", lock);
+
+        let first = acquire(&a, name, Location::caller(), Access::Exclusive, true);
+        release(acquire(&b, name, Location::caller(), Access::Exclusive, true));
+        release(first);
+
+        let (a, dropped) = (a.get(), b.get());
+        assert!(graph(|graph| graph.dependencies.get(&a, &dropped).is_some() && graph.names.contains_key(&dropped)));
+
+        drop(b);
+        assert!(graph(|graph| graph.dependencies.get(&a, &dropped).is_none() && !graph.names.contains_key(&dropped)));
+        assert!(KNOWN.with(|known| known.borrow().1.contains(&(a, dropped))));
+
+        // the thread no longer trusts what it knew of the graph once it takes a lock again
+        let c = LockId::new();
+        let first = acquire(&c, name, Location::caller(), Access::Exclusive, true);
+        release(first);
+        assert!(KNOWN.with(|known| !known.borrow().1.contains(&(a, dropped))));
+
+    }
+
+    #[test]
+    pub fn test_shared_reentrancy_is_reported_without_panicking() {
+
+        // nothing is tracked in release builds
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let lock = LockId::new();
+        let name = |lock| format!("shared #{}", lock);
+
+        let first = acquire(&lock, name, Location::caller(), Access::Shared, true);
+        let second = acquire(&lock, name, Location::caller(), Access::Shared, true);
+        assert_eq!(findings_on(&name(lock.get())).len(), 1);
+
+        // a try lock neither waits nor is reported
+        let third = acquire(&lock, name, Location::caller(), Access::Exclusive, false);
+        assert_eq!(findings_on(&name(lock.get())).len(), 1);
+
+        let panic = std::panic::catch_unwind(|| acquire(&lock, name, Location::caller(), Access::Exclusive, true));
+        assert!(panic.is_err());
+        assert_eq!(findings_on(&name(lock.get())).len(), 2);
+
+        release(third);
+        release(second);
+        release(first);
+        release(acquire(&lock, name, Location::caller(), Access::Exclusive, true));
+        assert_eq!(findings_on(&name(lock.get())).len(), 2);
+
+    }
+
+    #[test]
+    pub fn test_reports_cycles_through_several_locks() {
+
+        // nothing is tracked in release builds
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let locks = [LockId::new(), LockId::new(), LockId::new()];
+        let name = |lock| format!("cycle #{}", lock);
+
+        // a before b, b before c, and then c before a
+        for (held, next) in [(0, 1), (1, 2), (2, 0)] {
+            let first = acquire(&locks[held], name, Location::caller(), Access::Exclusive, true);
+            release(acquire(&locks[next], name, Location::caller(), Access::Exclusive, true));
+            release(first);
+        }
+
+        let resources : Vec<String> = locks.iter().map(|lock| name(lock.get())).collect();
+        let cycles : Vec<Cycle> = report().cycles.into_iter()
+        .filter(|cycle| cycle.steps.iter().all(|step| resources.contains(&step.resource)))
+        .collect();
+
+        assert_eq!(cycles.len(), 1);
+        let order : Vec<&str> = cycles[0].steps.iter().map(|step| step.resource.as_str()).collect();
+        assert_eq!(order, vec![resources[2].as_str(), resources[0].as_str(), resources[1].as_str()]);
+        assert!(cycles[0].steps.iter().all(|step| step.held.starts_with(file!())));
+
+    }
+
+}
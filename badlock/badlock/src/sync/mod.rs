@@ -0,0 +1,25 @@
+//! Drop-in replacements for `std::sync::{Mutex, RwLock}` whose debug builds report the reentrant
+//! locks and lock order inversions they run into, see `lockdep`. Release builds only wrap std.
+
+pub mod mutex;
+pub mod rwlock;
+pub use mutex::{Mutex, MutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// Wraps the guard of a std result, poisoned or not.
+pub(crate) fn map_result<Guard, Wrapped>(result : LockResult<Guard>, wrap : impl FnOnce(Guard) -> Wrapped) -> LockResult<Wrapped> {
+    match result {
+        Ok(guard) => Ok(wrap(guard)),
+        Err(poisoned) => Err(PoisonError::new(wrap(poisoned.into_inner())))
+    }
+}
+
+/// Wraps the guard of a std try result, poisoned or not.
+pub(crate) fn map_try_result<Guard, Wrapped>(result : TryLockResult<Guard>, wrap : impl FnOnce(Guard) -> Wrapped) -> TryLockResult<Wrapped> {
+    match result {
+        Ok(guard) => Ok(wrap(guard)),
+        Err(TryLockError::Poisoned(poisoned)) => Err(TryLockError::Poisoned(PoisonError::new(wrap(poisoned.into_inner())))),
+        Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock)
+    }
+}
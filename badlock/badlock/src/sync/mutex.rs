@@ -0,0 +1,165 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use crate::lockdep::{self, Access, LockId};
+use super::{map_result, map_try_result, LockResult, TryLockResult};
+
+/// `std::sync::Mutex`, tracked by `lockdep` in debug builds.
+#[derive(Default)]
+pub struct Mutex<T : ?Sized> {
+    id : LockId,
+    inner : std::sync::Mutex<T>
+}
+
+impl <T> Mutex<T> {
+
+    pub const fn new(value : T) -> Self {
+        Self {
+            id : LockId::new(),
+            inner : std::sync::Mutex::new(value)
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+
+}
+
+impl <T : ?Sized> Mutex<T> {
+
+    fn name(lock : usize) -> String {
+        format!("Mutex<{}> #{}", std::any::type_name::<T>(), lock)
+    }
+
+    /// Blocks until the lock is held. In debug builds, panics instead when the current thread
+    /// already holds it.
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let lock = lockdep::acquire(&self.id, Self::name, Location::caller(), Access::Exclusive, true);
+        map_result(self.inner.lock(), |inner| MutexGuard { inner, lock })
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        map_try_result(self.inner.try_lock(), |inner| MutexGuard {
+            inner,
+            lock : lockdep::acquire(&self.id, Self::name, Location::caller(), Access::Exclusive, false)
+        })
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+
+}
+
+impl <T> From<T> for Mutex<T> {
+    fn from(value : T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl <T : ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// `std::sync::MutexGuard`, which tells `lockdep` the lock is released when dropped.
+pub struct MutexGuard<'a, T : ?Sized + 'a> {
+    inner : std::sync::MutexGuard<'a, T>,
+    lock : usize
+}
+
+impl <T : ?Sized> Deref for MutexGuard<'_, T> {
+
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+
+}
+
+impl <T : ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl <T : ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        lockdep::release(self.lock);
+    }
+}
+
+impl <T : ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl <T : ?Sized + fmt::Display> fmt::Display for MutexGuard<'_, T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    pub fn test_mutex_behaves_as_std() {
+
+        let mut mutex = Mutex::from(vec![1]);
+        mutex.lock().unwrap().push(2);
+        mutex.get_mut().unwrap().push(3);
+
+        {
+            let guard = mutex.try_lock().unwrap();
+            assert_eq!(format!("{:?}", guard), "[1, 2, 3]");
+            std::thread::scope(|scope| {
+                scope.spawn(|| assert!(matches!(mutex.try_lock(), Err(crate::sync::TryLockError::WouldBlock))));
+            });
+        }
+
+        assert!(!mutex.is_poisoned());
+        assert_eq!(mutex.into_inner().unwrap(), vec![1, 2, 3]);
+
+    }
+
+    #[test]
+    pub fn test_mutex_reentrancy_panics_in_debug_builds() {
+
+        // release builds would deadlock
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let mutex = Mutex::new(0_u8);
+
+        let reentrant = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _first = mutex.lock().unwrap();
+            let _second = mutex.lock();
+        }));
+        assert!(reentrant.is_err());
+
+        let findings = crate::lockdep::test::findings_on(&Mutex::<u8>::name(mutex.id.get()));
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].first_lock.starts_with(file!()));
+        assert!(findings[0].second_lock.starts_with(file!()));
+
+        // the first guard was dropped while unwinding
+        assert!(mutex.is_poisoned());
+        assert!(mutex.lock().is_err());
+
+    }
+
+}
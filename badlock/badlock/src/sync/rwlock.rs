@@ -0,0 +1,202 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use crate::lockdep::{self, Access, LockId};
+use super::{map_result, map_try_result, LockResult, TryLockResult};
+
+/// `std::sync::RwLock`, tracked by `lockdep` in debug builds.
+#[derive(Default)]
+pub struct RwLock<T : ?Sized> {
+    id : LockId,
+    inner : std::sync::RwLock<T>
+}
+
+impl <T> RwLock<T> {
+
+    pub const fn new(value : T) -> Self {
+        Self {
+            id : LockId::new(),
+            inner : std::sync::RwLock::new(value)
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+
+}
+
+impl <T : ?Sized> RwLock<T> {
+
+    fn name(lock : usize) -> String {
+        format!("RwLock<{}> #{}", std::any::type_name::<T>(), lock)
+    }
+
+    /// Blocks until the lock is held for reading. In debug builds, a read lock the current thread
+    /// already holds is reported, as it deadlocks once a writer waits in between, and a write lock
+    /// panics instead.
+    #[track_caller]
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let lock = lockdep::acquire(&self.id, Self::name, Location::caller(), Access::Shared, true);
+        map_result(self.inner.read(), |inner| RwLockReadGuard { inner, lock })
+    }
+
+    /// Blocks until the lock is held for writing. In debug builds, panics instead when the current
+    /// thread already holds it.
+    #[track_caller]
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let lock = lockdep::acquire(&self.id, Self::name, Location::caller(), Access::Exclusive, true);
+        map_result(self.inner.write(), |inner| RwLockWriteGuard { inner, lock })
+    }
+
+    #[track_caller]
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        map_try_result(self.inner.try_read(), |inner| RwLockReadGuard {
+            inner,
+            lock : lockdep::acquire(&self.id, Self::name, Location::caller(), Access::Shared, false)
+        })
+    }
+
+    #[track_caller]
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        map_try_result(self.inner.try_write(), |inner| RwLockWriteGuard {
+            inner,
+            lock : lockdep::acquire(&self.id, Self::name, Location::caller(), Access::Exclusive, false)
+        })
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+
+}
+
+impl <T> From<T> for RwLock<T> {
+    fn from(value : T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl <T : ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// `std::sync::RwLockReadGuard`, which tells `lockdep` the lock is released when dropped.
+pub struct RwLockReadGuard<'a, T : ?Sized + 'a> {
+    inner : std::sync::RwLockReadGuard<'a, T>,
+    lock : usize
+}
+
+impl <T : ?Sized> Deref for RwLockReadGuard<'_, T> {
+
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+
+}
+
+impl <T : ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        lockdep::release(self.lock);
+    }
+}
+
+impl <T : ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl <T : ?Sized + fmt::Display> fmt::Display for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// `std::sync::RwLockWriteGuard`, which tells `lockdep` the lock is released when dropped.
+pub struct RwLockWriteGuard<'a, T : ?Sized + 'a> {
+    inner : std::sync::RwLockWriteGuard<'a, T>,
+    lock : usize
+}
+
+impl <T : ?Sized> Deref for RwLockWriteGuard<'_, T> {
+
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+
+}
+
+impl <T : ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl <T : ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        lockdep::release(self.lock);
+    }
+}
+
+impl <T : ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl <T : ?Sized + fmt::Display> fmt::Display for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    pub fn test_rwlock_reports_inversions_between_threads() {
+
+        let (a, b) = (RwLock::new(String::from("a")), RwLock::new(String::from("b")));
+
+        // run one after the other, so the inversion is only ever possible
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let a = a.read().unwrap();
+                let b = b.write().unwrap();
+                assert_eq!(format!("{}{}", a, b), "ab");
+            });
+        });
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let _b = b.read().unwrap();
+                a.write().unwrap().push('!');
+            });
+        });
+
+        let names = [RwLock::<String>::name(a.id.get()), RwLock::<String>::name(b.id.get())];
+        assert_eq!(a.into_inner().unwrap(), "a!");
+
+        if cfg!(debug_assertions) {
+            let inversions : Vec<_> = crate::lockdep::report().inversions.into_iter()
+            .filter(|inversion| names.contains(&inversion.first_resource) && names.contains(&inversion.second_resource))
+            .collect();
+            assert_eq!(inversions.len(), 1);
+            assert!(inversions[0].first_held.starts_with(file!()));
+        }
+
+    }
+
+}
@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+use lock_detection::report::Report;
+use crate::build::IrModule;
+use crate::opt::Opt;
+
+/// The module the crates are linked into when analysing them through `opt`.
+pub const LINKED : &str = "badlock-linked.bc";
+
+/// Runs the reentrant lock analysis over modules, in process or through `opt`.
+#[derive(Debug)]
+pub enum Analyzer {
+    #[cfg(feature = "llvm")]
+    InProcess(llvm_lock_detection::catalog::Catalog),
+    Opt(Opt)
+}
+
+impl Analyzer {
+
+    /// Analyses in process when built with LLVM, unless `opt` or the plugin are asked for explicitly.
+    pub fn new(
+        opt : Option<PathBuf>,
+        plugin : Option<PathBuf>,
+        catalog : Option<PathBuf>
+    ) -> Result<Self, anyhow::Error> {
+
+        #[cfg(feature = "llvm")]
+        {
+            let explicit = opt.is_some() || plugin.is_some()
+                || std::env::var_os("BADLOCK_OPT").is_some()
+                || std::env::var_os("BADLOCK_PLUGIN").is_some();
+
+            if !explicit {
+                let mut extended = llvm_lock_detection::catalog::Catalog::default();
+                if let Some(catalog) = catalog {
+                    extended.extend(llvm_lock_detection::catalog::Catalog::from_file(&catalog)?);
+                }
+                return Ok(Analyzer::InProcess(extended));
+            }
+        }
+
+        Ok(Analyzer::Opt(Opt::new(opt, plugin, catalog)?))
+
+    }
+
+    /// Analyses the modules of several crates, linked into one. Through `opt`, the linked module
+    /// is written next to the first one and sites are not attributed to their crates.
+    pub fn analyze_crates(&self, modules : &[IrModule]) -> Result<Report, anyhow::Error> {
+        match self {
+            #[cfg(feature = "llvm")]
+            Analyzer::InProcess(catalog) => {
+                let modules : Vec<_> = modules.iter()
+                    .map(|module| (module.crate_name.clone(), module.path.clone()))
+                    .collect();
+                llvm_lock_detection::analysis::analyze_crates(&modules, catalog.clone())
+            },
+            Analyzer::Opt(opt) => {
+                let paths : Vec<_> = modules.iter().map(|module| module.path.clone()).collect();
+                let linked = paths.first()
+                    .and_then(|path| path.parent())
+                    .ok_or(anyhow::anyhow!("no modules to link"))?
+                    .join(LINKED);
+                opt.link(&paths, &linked)?;
+                opt.analyze(&linked)
+            }
+        }
+    }
+
+    pub fn analyze(&self, module : &Path) -> Result<Report, anyhow::Error> {
+        match self {
+            #[cfg(feature = "llvm")]
+            Analyzer::InProcess(catalog) => llvm_lock_detection::analysis::analyze_file(module, catalog.clone()),
+            Analyzer::Opt(opt) => opt.analyze(module)
+        }
+    }
+
+}
@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+use lock_detection::report::Severity;
+
+pub const USAGE : &str = "\
+Detects reentrant deadlocks in the crates of a cargo workspace.
+
+USAGE:
+    cargo badlock [OPTIONS] [-- <CARGO BUILD ARGS>...]
+    cargo badlock analyze [OPTIONS] <MODULE>...
+
+COMMANDS:
+    analyze                   Analyses the given `.ll` or `.bc` modules instead of building the workspace
+
+OPTIONS:
+    --format <human|json>     How findings are printed [default: human]
+    --opt <PATH>              Run the pass with this opt binary rather than in process [env: BADLOCK_OPT, default: opt-16]
+    --plugin <PATH>           Run the pass from this plugin rather than in process [env: BADLOCK_PLUGIN, default: libllvm_lock_detection.so next to this binary]
+    --catalog <PATH>          A lock API catalog to extend the built-in one with
+    --manifest-path <PATH>    The Cargo.toml of the crate or workspace to analyse
+    --target-dir <PATH>       Where IR is built [default: <target dir>/badlock]
+    --per-crate               Analyse each crate on its own rather than linking them
+    --fail-on <warning|error|never>
+                              The least severe finding that fails the run [default: warning]
+    -h, --help                Prints this message
+
+Through opt, the crates are linked with the llvm-link of BADLOCK_LLVM_LINK, or llvm-link-16.
+Reentrant locks are errors and lock order inversions warnings. Exits with 1 if a finding at least as
+severe as `--fail-on` may deadlock and 2 if the analysis could not run.";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Human,
+    Json
+}
+
+impl std::str::FromStr for Format {
+
+    type Err = anyhow::Error;
+
+    fn from_str(format : &str) -> Result<Self, Self::Err> {
+        match format {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow::anyhow!("unknown format `{}`, expected `human` or `json`", format))
+        }
+    }
+
+}
+
+/// The least severe finding that makes the run fail, so CI can be gated on errors only.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailOn {
+    #[default]
+    Warning,
+    Error,
+    Never
+}
+
+impl FailOn {
+
+    /// Whether a run whose most severe finding is `severity` fails.
+    pub fn fails(&self, severity : Option<Severity>) -> bool {
+        match (self, severity) {
+            (FailOn::Warning, Some(_)) => true,
+            (FailOn::Error, Some(severity)) => severity >= Severity::Error,
+            _ => false
+        }
+    }
+
+}
+
+impl std::str::FromStr for FailOn {
+
+    type Err = anyhow::Error;
+
+    fn from_str(fail_on : &str) -> Result<Self, Self::Err> {
+        match fail_on {
+            "warning" => Ok(FailOn::Warning),
+            "error" => Ok(FailOn::Error),
+            "never" => Ok(FailOn::Never),
+            _ => Err(anyhow::anyhow!("unknown severity `{}`, expected `warning`, `error` or `never`", fail_on))
+        }
+    }
+
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Subcommand {
+    // build the workspace and analyse the module of each crate
+    #[default]
+    Workspace,
+    // analyse modules that were already emitted
+    Analyze(Vec<PathBuf>)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Args {
+    pub subcommand : Subcommand,
+    pub format : Format,
+    pub opt : Option<PathBuf>,
+    pub plugin : Option<PathBuf>,
+    pub catalog : Option<PathBuf>,
+    pub manifest_path : Option<PathBuf>,
+    pub target_dir : Option<PathBuf>,
+    // analyse every module on its own rather than linked together
+    pub per_crate : bool,
+    pub fail_on : FailOn,
+    // forwarded to `cargo build` as is
+    pub cargo_args : Vec<String>,
+    pub help : bool
+}
+
+impl Args {
+
+    /// Parses the arguments after the binary name, which cargo follows with `badlock` when run as `cargo badlock`.
+    pub fn parse(args : impl IntoIterator<Item = String>) -> Result<Self, anyhow::Error> {
+
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+
+        if args.peek().map(String::as_str) == Some("badlock") {
+            args.next();
+        }
+
+        while let Some(arg) = args.next() {
+
+            // accept both `--flag value` and `--flag=value`
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg.clone(), None)
+            };
+            let mut value = || inline.clone().or_else(|| args.next()).ok_or(
+                anyhow::anyhow!("`{}` expects a value", flag)
+            );
+
+            match flag.as_str() {
+                "--format" => parsed.format = value()?.parse()?,
+                "--opt" => parsed.opt = Some(value()?.into()),
+                "--plugin" => parsed.plugin = Some(value()?.into()),
+                "--catalog" => parsed.catalog = Some(value()?.into()),
+                "--manifest-path" => parsed.manifest_path = Some(value()?.into()),
+                "--target-dir" => parsed.target_dir = Some(value()?.into()),
+                "--per-crate" => parsed.per_crate = true,
+                "--fail-on" => parsed.fail_on = value()?.parse()?,
+                "-h" | "--help" => parsed.help = true,
+                "--" => {
+                    parsed.cargo_args.extend(args.by_ref());
+                },
+                "analyze" if parsed.subcommand == Subcommand::Workspace => {
+                    parsed.subcommand = Subcommand::Analyze(Vec::new());
+                },
+                _ => match &mut parsed.subcommand {
+                    Subcommand::Analyze(modules) if !arg.starts_with('-') => modules.push(arg.into()),
+                    _ => anyhow::bail!("unexpected argument `{}`\n\n{}", arg, USAGE)
+                }
+            }
+
+        }
+
+        if parsed.subcommand == Subcommand::Analyze(Vec::new()) && !parsed.help {
+            anyhow::bail!("`analyze` expects at least one module\n\n{}", USAGE);
+        }
+
+        Ok(parsed)
+
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub fn args(args : &[&str]) -> Result<Args, anyhow::Error> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    pub fn test_parses_flags_and_forwards_cargo_args() -> Result<(), anyhow::Error> {
+
+        let parsed = args(&[
+            "badlock", "--format=json", "--catalog", "locks.toml",
+            "--", "--release", "-p", "service"
+        ])?;
+
+        assert_eq!(parsed.format, Format::Json);
+        assert_eq!(parsed.catalog, Some(PathBuf::from("locks.toml")));
+        assert_eq!(parsed.cargo_args, vec!["--release", "-p", "service"]);
+        assert_eq!(parsed.opt, None);
+
+        assert_eq!(parsed.subcommand, Subcommand::Workspace);
+        assert_eq!(args(&[])?, Args::default());
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_parses_analyze_modules() -> Result<(), anyhow::Error> {
+
+        let parsed = args(&["analyze", "main.ll", "--format", "json", "lib.bc", "--per-crate"])?;
+        assert_eq!(parsed.subcommand, Subcommand::Analyze(vec!["main.ll".into(), "lib.bc".into()]));
+        assert_eq!(parsed.format, Format::Json);
+        assert!(parsed.per_crate);
+
+        assert!(args(&["analyze"]).is_err());
+        assert!(args(&["main.ll"]).is_err());
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_rejects_unknown_flags_and_missing_values() {
+
+        assert!(args(&["--release"]).is_err());
+        assert!(args(&["--format", "xml"]).is_err());
+        assert!(args(&["--plugin"]).is_err());
+        assert!(args(&["--fail-on", "note"]).is_err());
+
+    }
+
+    #[test]
+    pub fn test_fail_on_thresholds_severities() -> Result<(), anyhow::Error> {
+
+        assert_eq!(args(&[])?.fail_on, FailOn::Warning);
+        assert_eq!(args(&["--fail-on=error"])?.fail_on, FailOn::Error);
+
+        assert!(FailOn::Warning.fails(Some(Severity::Warning)));
+        assert!(!FailOn::Warning.fails(None));
+        assert!(!FailOn::Error.fails(Some(Severity::Warning)));
+        assert!(FailOn::Error.fails(Some(Severity::Error)));
+        assert!(!FailOn::Never.fails(Some(Severity::Error)));
+
+        Ok(())
+
+    }
+
+}
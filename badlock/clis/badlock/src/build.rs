@@ -0,0 +1,320 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use serde::Deserialize;
+
+// a single codegen unit so that each crate is emitted as one module
+const EMIT_IR_FLAGS : &str = "--emit=llvm-ir,link -C codegen-units=1 -C debuginfo=2";
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages : Vec<Package>,
+    target_directory : PathBuf
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name : String,
+    targets : Vec<Target>
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name : String,
+    kind : Vec<String>
+}
+
+/// The crates of a workspace, as `cargo metadata` sees them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Workspace {
+    pub packages : Vec<String>,
+    // crate names as rustc spells them, i.e. with underscores
+    pub crates : Vec<String>,
+    pub target_directory : PathBuf
+}
+
+/// A module emitted for one of the workspace's crates.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IrModule {
+    pub crate_name : String,
+    pub path : PathBuf
+}
+
+impl Workspace {
+
+    pub fn from_metadata(json : &str) -> Result<Self, anyhow::Error> {
+
+        let metadata : Metadata = serde_json::from_str(json)?;
+
+        let mut packages = Vec::new();
+        let mut crates = Vec::new();
+        for package in metadata.packages {
+            packages.push(package.name);
+            for target in package.targets {
+                // build scripts are not part of the program
+                if target.kind.iter().any(|kind| kind == "custom-build") {
+                    continue;
+                }
+                let name = target.name.replace('-', "_");
+                if !crates.contains(&name) {
+                    crates.push(name);
+                }
+            }
+        }
+
+        Ok(Self {
+            packages,
+            crates,
+            target_directory : metadata.target_directory
+        })
+
+    }
+
+    pub fn load(manifest_path : Option<&Path>) -> Result<Self, anyhow::Error> {
+
+        let mut command = Command::new(cargo());
+        command.args(["metadata", "--no-deps", "--format-version", "1"]);
+        if let Some(manifest_path) = manifest_path {
+            command.arg("--manifest-path").arg(manifest_path);
+        }
+
+        let output = command.stderr(Stdio::inherit()).output()?;
+        if !output.status.success() {
+            anyhow::bail!("`cargo metadata` failed with {}", output.status);
+        }
+
+        Self::from_metadata(&String::from_utf8(output.stdout)?)
+
+    }
+
+    /// The crate a module emitted into `deps` belongs to, if it is one of ours.
+    pub fn crate_of(&self, path : &Path) -> Option<String> {
+
+        if path.extension()? != "ll" {
+            return None;
+        }
+
+        // rustc names them `<crate>-<metadata hash>.ll`
+        let (name, _hash) = path.file_stem()?.to_str()?.rsplit_once('-')?;
+        self.crates.iter().find(|crate_name| *crate_name == name).cloned()
+
+    }
+
+    /// The modules of our crates found in the `deps` directory of a profile.
+    pub fn modules(&self, deps : &Path) -> Result<Vec<IrModule>, anyhow::Error> {
+
+        let mut modules = Vec::new();
+        if !deps.is_dir() {
+            return Ok(modules);
+        }
+
+        for entry in std::fs::read_dir(deps)? {
+            let path = entry?.path();
+            if let Some(crate_name) = self.crate_of(&path) {
+                modules.push(IrModule { crate_name, path });
+            }
+        }
+
+        modules.sort();
+        Ok(modules)
+
+    }
+
+    /// Builds the workspace with IR emission and returns the module of each crate.
+    ///
+    /// The build goes to its own target directory so it does not invalidate the regular one. Our packages
+    /// are cleaned first, so every crate is rebuilt rather than analysed from stale IR, while dependencies
+    /// stay cached.
+    pub fn emit_ir(
+        &self,
+        manifest_path : Option<&Path>,
+        target_dir : &Path,
+        cargo_args : &[String]
+    ) -> Result<Vec<IrModule>, anyhow::Error> {
+
+        let deps = target_dir.join(profile(cargo_args)).join("deps");
+        for module in self.modules(&deps)? {
+            std::fs::remove_file(&module.path)?;
+        }
+
+        let mut clean = self.cargo("clean", manifest_path, target_dir);
+        for package in self.packages.iter() {
+            clean.arg("-p").arg(package);
+        }
+        clean.args(profile_args(cargo_args));
+        let status = clean.status()?;
+        if !status.success() {
+            anyhow::bail!("`cargo clean` failed with {}", status);
+        }
+
+        let mut rustflags = std::env::var("RUSTFLAGS").unwrap_or_default();
+        if !rustflags.is_empty() {
+            rustflags.push(' ');
+        }
+        rustflags.push_str(EMIT_IR_FLAGS);
+
+        let mut command = self.cargo("build", manifest_path, target_dir);
+        command.args(cargo_args).env("RUSTFLAGS", rustflags);
+
+        let status = command.status()?;
+        if !status.success() {
+            anyhow::bail!("`cargo build` failed with {}", status);
+        }
+
+        let modules = self.modules(&deps)?;
+        if modules.is_empty() {
+            anyhow::bail!("the build did not emit any IR under {}", deps.display());
+        }
+
+        Ok(modules)
+
+    }
+
+    fn cargo(&self, subcommand : &str, manifest_path : Option<&Path>, target_dir : &Path) -> Command {
+
+        let mut command = Command::new(cargo());
+        command.arg(subcommand).arg("--target-dir").arg(target_dir);
+        if let Some(manifest_path) = manifest_path {
+            command.arg("--manifest-path").arg(manifest_path);
+        }
+
+        command
+
+    }
+
+}
+
+impl IrModule {
+
+    /// A module given by path, named after its crate when it follows rustc's `<crate>-<hash>` naming.
+    pub fn from_path(path : PathBuf) -> Self {
+
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let crate_name = match stem.rsplit_once('-') {
+            Some((name, hash)) if !name.is_empty() && hash.len() == 16
+                && hash.chars().all(|c| c.is_ascii_hexdigit()) => name.to_string(),
+            _ => stem
+        };
+
+        Self { crate_name, path }
+
+    }
+
+}
+
+fn cargo() -> String {
+    std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string())
+}
+
+/// The arguments selecting the profile, which `cargo clean` needs to clean the right directory.
+pub fn profile_args(cargo_args : &[String]) -> Vec<String> {
+
+    let mut args = cargo_args.iter();
+    let mut selected = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--release" | "-r" => selected.push(arg.clone()),
+            "--profile" => {
+                selected.push(arg.clone());
+                selected.extend(args.next().cloned());
+            },
+            _ => if arg.starts_with("--profile=") {
+                selected.push(arg.clone());
+            }
+        }
+    }
+
+    selected
+
+}
+
+/// The profile directory a build with these arguments writes to.
+pub fn profile(cargo_args : &[String]) -> String {
+
+    let mut args = cargo_args.iter();
+    let mut profile = "debug".to_string();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--release" | "-r" => profile = "release".to_string(),
+            "--profile" => if let Some(name) = args.next() {
+                profile = name.clone();
+            },
+            _ => if let Some(name) = arg.strip_prefix("--profile=") {
+                profile = name.to_string();
+            }
+        }
+    }
+
+    // the built-in profiles write to directories of other names
+    match profile.as_str() {
+        "dev" | "test" => "debug".to_string(),
+        "bench" => "release".to_string(),
+        _ => profile
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub const METADATA : &str = r#"{
+        "packages" : [
+            {
+                "name" : "service",
+                "targets" : [
+                    { "name" : "service", "kind" : ["lib"] },
+                    { "name" : "service-cli", "kind" : ["bin"] },
+                    { "name" : "build-script-build", "kind" : ["custom-build"] }
+                ]
+            },
+            {
+                "name" : "storage",
+                "targets" : [ { "name" : "storage", "kind" : ["lib"] } ]
+            }
+        ],
+        "target_directory" : "/work/target"
+    }"#;
+
+    #[test]
+    pub fn test_workspace_matches_modules_of_its_crates() -> Result<(), anyhow::Error> {
+
+        let workspace = Workspace::from_metadata(METADATA)?;
+        assert_eq!(workspace.packages, vec!["service", "storage"]);
+        assert_eq!(workspace.crates, vec!["service", "service_cli", "storage"]);
+        assert_eq!(workspace.target_directory, PathBuf::from("/work/target"));
+
+        assert_eq!(workspace.crate_of(Path::new("deps/service_cli-0f3a.ll")), Some("service_cli".to_string()));
+        assert_eq!(workspace.crate_of(Path::new("deps/storage-9c1d.ll")), Some("storage".to_string()));
+        assert_eq!(workspace.crate_of(Path::new("deps/storage-9c1d.rlib")), None);
+        assert_eq!(workspace.crate_of(Path::new("deps/serde-1b2c.ll")), None);
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_modules_given_by_path_are_named_after_their_crate() {
+
+        assert_eq!(IrModule::from_path("deps/storage-528f7ea777853cbb.ll".into()).crate_name, "storage");
+        assert_eq!(IrModule::from_path("out/main.bc".into()).crate_name, "main");
+        assert_eq!(IrModule::from_path("my-tool.ll".into()).crate_name, "my-tool");
+
+    }
+
+    #[test]
+    pub fn test_profile_follows_cargo_args() {
+
+        let args = |args : &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(profile(&args(&[])), "debug");
+        assert_eq!(profile(&args(&["-p", "service", "--release"])), "release");
+        assert_eq!(profile(&args(&["--profile", "ci"])), "ci");
+        assert_eq!(profile(&args(&["--profile=dev"])), "debug");
+        assert_eq!(profile_args(&args(&["-p", "service", "--profile", "ci"])), vec!["--profile", "ci"]);
+
+    }
+
+}
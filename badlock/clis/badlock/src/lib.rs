@@ -1 +1,5 @@
-// todo: nothing here yet
\ No newline at end of file
+pub mod args;
+pub mod analyzer;
+pub mod build;
+pub mod opt;
+pub mod summary;
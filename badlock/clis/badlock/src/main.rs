@@ -0,0 +1,84 @@
+use cargo_badlock::args::{Args, Format, Subcommand, USAGE};
+use cargo_badlock::analyzer::Analyzer;
+use cargo_badlock::build::{IrModule, Workspace};
+use cargo_badlock::summary::Summary;
+
+fn analyze(analyzer : &Analyzer, modules : Vec<IrModule>, per_crate : bool) -> Result<Summary, anyhow::Error> {
+
+    let mut summary = Summary::new();
+
+    if per_crate || modules.len() < 2 {
+        for module in modules {
+            eprintln!("    Analyzing {} ({})", module.crate_name, module.path.display());
+            let report = analyzer.analyze(&module.path)?;
+            summary.add(vec![module.crate_name], vec![module.path], report);
+        }
+        return Ok(summary);
+    }
+
+    let crates : Vec<_> = modules.iter().map(|module| module.crate_name.clone()).collect();
+    eprintln!("    Analyzing {} linked together", crates.join(", "));
+    let report = analyzer.analyze_crates(&modules)?;
+    summary.add(crates, modules.into_iter().map(|module| module.path).collect(), report);
+
+    Ok(summary)
+
+}
+
+fn run(args : Args) -> Result<Summary, anyhow::Error> {
+
+    let analyzer = Analyzer::new(args.opt, args.plugin, args.catalog)?;
+
+    let modules = match args.subcommand {
+        Subcommand::Analyze(paths) => paths.into_iter().map(IrModule::from_path).collect(),
+        Subcommand::Workspace => {
+            let workspace = Workspace::load(args.manifest_path.as_deref())?;
+            let target_dir = args.target_dir.unwrap_or_else(|| workspace.target_directory.join("badlock"));
+            workspace.emit_ir(args.manifest_path.as_deref(), &target_dir, &args.cargo_args)?
+        }
+    };
+
+    analyze(&analyzer, modules, args.per_crate)
+
+}
+
+fn main() {
+
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let format = args.format;
+    let fail_on = args.fail_on;
+    match run(args) {
+        Ok(summary) => {
+            match format {
+                Format::Human => println!("{}", summary),
+                Format::Json => match summary.to_json() {
+                    Ok(json) => println!("{}", json),
+                    Err(error) => {
+                        eprintln!("error: {}", error);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            if fail_on.fails(summary.severity) {
+                std::process::exit(1);
+            }
+        },
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    }
+
+}
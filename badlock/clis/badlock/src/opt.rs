@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use lock_detection::report::Report;
+
+pub const PLUGIN : &str = "libllvm_lock_detection.so";
+
+/// Runs the `reentrant-lock-detection` pass through `opt`, linking modules with `llvm-link`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Opt {
+    pub opt : PathBuf,
+    pub llvm_link : PathBuf,
+    pub plugin : PathBuf,
+    pub catalog : Option<PathBuf>
+}
+
+impl Opt {
+
+    /// Falls back on `BADLOCK_OPT` and `BADLOCK_PLUGIN`, then on `opt-16` and the plugin built next to this binary.
+    /// `llvm-link` is `BADLOCK_LLVM_LINK`, or `llvm-link-16`.
+    pub fn new(
+        opt : Option<PathBuf>,
+        plugin : Option<PathBuf>,
+        catalog : Option<PathBuf>
+    ) -> Result<Self, anyhow::Error> {
+
+        let opt = opt
+            .or_else(|| std::env::var_os("BADLOCK_OPT").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("opt-16"));
+
+        let llvm_link = std::env::var_os("BADLOCK_LLVM_LINK").map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("llvm-link-16"));
+
+        let plugin = match plugin.or_else(|| std::env::var_os("BADLOCK_PLUGIN").map(PathBuf::from)) {
+            Some(plugin) => plugin,
+            None => {
+                let exe = std::env::current_exe()?;
+                exe.parent().map(|dir| dir.join(PLUGIN)).ok_or(
+                    anyhow::anyhow!("cannot locate {}, pass --plugin", PLUGIN)
+                )?
+            }
+        };
+        if !plugin.is_file() {
+            anyhow::bail!("the pass plugin {} does not exist, build llvm-lock-detection or pass --plugin", plugin.display());
+        }
+
+        Ok(Self { opt, llvm_link, plugin, catalog })
+
+    }
+
+    /// The pipeline, which prints the report as JSON for `analyze` to read back.
+    pub fn passes(&self) -> String {
+        match &self.catalog {
+            Some(catalog) => format!("reentrant-lock-detection<format=json;catalog={}>", catalog.display()),
+            None => "reentrant-lock-detection<format=json>".to_string()
+        }
+    }
+
+    pub fn analyze(&self, module : &Path) -> Result<Report, anyhow::Error> {
+
+        let output = Command::new(&self.opt)
+            .arg(format!("--load-pass-plugin={}", self.plugin.display()))
+            .arg(format!("--passes={}", self.passes()))
+            .arg("-disable-output")
+            .arg(module)
+            .output()
+            .map_err(|error| anyhow::anyhow!("cannot run {}: {}", self.opt.display(), error))?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} failed on {} with {}\n{}",
+                self.opt.display(), module.display(), output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        serde_json::from_slice::<Report>(&output.stdout).map_err(
+            |error| anyhow::anyhow!("cannot read the analysis of {}: {}", module.display(), error)
+        )
+
+    }
+
+    /// Links `modules` into `linked` with `llvm-link`. Definitions of the same name in several
+    /// modules, e.g. the `main` of every binary, cannot be linked.
+    pub fn link(&self, modules : &[PathBuf], linked : &Path) -> Result<(), anyhow::Error> {
+
+        let output = Command::new(&self.llvm_link)
+            .arg("-o")
+            .arg(linked)
+            .args(modules)
+            .output()
+            .map_err(|error| anyhow::anyhow!("cannot run {}: {}", self.llvm_link.display(), error))?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} could not link the crates, pass --per-crate to analyse them one by one\n{}",
+                self.llvm_link.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
+
+    }
+
+}
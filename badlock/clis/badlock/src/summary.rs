@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use lock_detection::report::{Finding, Inversion, Report, Severity};
+
+/// The findings of one analysis, over a single crate's module or over several crates linked together.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnalysisSummary {
+    pub crates : Vec<String>,
+    pub modules : Vec<PathBuf>,
+    pub findings : Vec<Finding>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inversions : Vec<Inversion>
+}
+
+/// The findings of every analysis.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Summary {
+    pub deadlocks : usize,
+    // deadlocks whose sites belong to more than one crate
+    pub cross_crate : usize,
+    // the most severe finding of any analysis
+    pub severity : Option<Severity>,
+    pub analyses : Vec<AnalysisSummary>
+}
+
+impl Summary {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, crates : Vec<String>, modules : Vec<PathBuf>, report : Report) {
+        self.deadlocks += report.findings.len() + report.inversions.len();
+        self.cross_crate += report.findings.iter().filter(|finding| finding.crosses_crates()).count();
+        self.severity = self.severity.max(report.severity());
+        self.analyses.push(AnalysisSummary {
+            crates,
+            modules,
+            findings : report.findings,
+            inversions : report.inversions
+        });
+    }
+
+    pub fn may_deadlock(&self) -> bool {
+        self.deadlocks > 0
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+}
+
+impl std::fmt::Display for Summary {
+
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        for analysis in self.analyses.iter().filter(|analysis| !analysis.findings.is_empty() || !analysis.inversions.is_empty()) {
+            match analysis.modules.as_slice() {
+                [module] => writeln!(f, "{} ({})", analysis.crates.join(", "), module.display())?,
+                modules => writeln!(f, "{} ({} modules linked)", analysis.crates.join(", "), modules.len())?
+            }
+            write!(f, "{}", Report::new(analysis.findings.clone()).with_inversions(analysis.inversions.clone()))?;
+            writeln!(f)?;
+        }
+
+        let crates : usize = self.analyses.iter().map(|analysis| analysis.crates.len()).sum();
+        write!(f, "badlock: {} potential deadlock(s) in {} crate(s)", self.deadlocks, crates)?;
+        if self.cross_crate > 0 {
+            write!(f, ", {} across crates", self.cross_crate)?;
+        }
+
+        Ok(())
+
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub fn finding(first : &str, second : &str) -> Finding {
+        Finding {
+            first_lock : "service::main bb1 #2 `call void @lock(ptr @STATE)`".to_string(),
+            resource : "@STATE".to_string(),
+            second_lock : "storage::put bb0 #1 `call void @lock(ptr @STATE)`".to_string(),
+            first_lock_crate : Some(first.to_string()),
+            resource_crate : Some(first.to_string()),
+            second_lock_crate : Some(second.to_string())
+        }
+    }
+
+    #[test]
+    pub fn test_summary_aggregates_analyses() -> Result<(), anyhow::Error> {
+
+        let mut summary = Summary::new();
+        summary.add(vec!["service".to_string()], vec![PathBuf::from("deps/service-1.ll")], Report::default());
+        assert!(!summary.may_deadlock());
+        assert_eq!(summary.severity, None);
+
+        summary.add(
+            vec!["storage".to_string()],
+            vec![PathBuf::from("deps/storage-2.ll")],
+            Report::new(vec![finding("storage", "storage")])
+        );
+        assert!(summary.may_deadlock());
+
+        let human = summary.to_string();
+        assert!(human.starts_with("storage (deps/storage-2.ll)\nMAY DEADLOCK!\n"));
+        assert!(!human.contains("service (deps"));
+        assert!(human.ends_with("badlock: 1 potential deadlock(s) in 2 crate(s)"));
+
+        let json : serde_json::Value = serde_json::from_str(&summary.to_json()?)?;
+        assert_eq!(json["deadlocks"], 1);
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["analyses"][1]["crates"][0], "storage");
+        assert_eq!(json["analyses"][1]["findings"][0]["resource"], "@STATE");
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_summary_counts_cross_crate_findings() {
+
+        let mut summary = Summary::new();
+        summary.add(
+            vec!["service".to_string(), "storage".to_string()],
+            vec![PathBuf::from("deps/service-1.ll"), PathBuf::from("deps/storage-2.ll")],
+            Report::new(vec![finding("service", "storage"), finding("storage", "storage")])
+        );
+
+        assert_eq!(summary.cross_crate, 1);
+        let human = summary.to_string();
+        assert!(human.starts_with("service, storage (2 modules linked)\n"));
+        assert!(human.contains("\tSECOND_LOCK (storage): storage::put"));
+        assert!(human.ends_with("badlock: 2 potential deadlock(s) in 2 crate(s), 1 across crates"));
+
+    }
+
+}
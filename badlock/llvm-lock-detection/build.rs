@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+// the LLVM major version `llvm-plugin` is built against
+const LLVM_MAJOR : &str = "16";
+const LLVM_PREFIX : &str = "LLVM_SYS_160_PREFIX";
+
+/// Finds `llvm-config` the way `llvm-sys` and `llvm-plugin` do: only in `$LLVM_SYS_160_PREFIX/bin`
+/// when it is set, and on the `PATH` otherwise.
+fn llvm_config() -> PathBuf {
+
+    let prefix = std::env::var_os(LLVM_PREFIX)
+    .map(|prefix| PathBuf::from(prefix).join("bin"))
+    .unwrap_or_default();
+
+    let names = [
+        "llvm-config".to_string(),
+        format!("llvm-config-{}", LLVM_MAJOR),
+        format!("llvm{}-config", LLVM_MAJOR)
+    ];
+
+    names.iter().map(|name| prefix.join(name))
+    .find(|binary| match query(binary, &["--version"]) {
+        Some(version) => version.split('.').next() == Some(LLVM_MAJOR),
+        None => false
+    })
+    .unwrap_or_else(|| panic!("Could not find llvm-config for LLVM {}, set {}", LLVM_MAJOR, LLVM_PREFIX))
+
+}
+
+fn query(llvm_config : &PathBuf, args : &[&str]) -> Option<String> {
+
+    let output = Command::new(llvm_config).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+
+}
+
+/// Links the shared LLVM into whatever links this crate, for the `link-llvm` feature, as the
+/// plugin's own FFI expects a single copy of LLVM in the process. The plugin is loaded into `opt`,
+/// which has LLVM already, so it is built without the feature.
+fn link_llvm(llvm_config : &PathBuf) {
+
+    let libs = query(llvm_config, &["--link-shared", "--libs"]).expect("llvm-config --libs failed");
+    let libdir = query(llvm_config, &["--libdir"]).expect("llvm-config --libdir failed");
+
+    println!("cargo:rustc-link-search=native={}", libdir);
+    for lib in libs.split_whitespace().filter_map(|lib| lib.strip_prefix("-l")) {
+        println!("cargo:rustc-link-lib=dylib={}", lib);
+    }
+
+}
+
+fn main() {
+
+    let llvm_config = llvm_config();
+    let include = query(&llvm_config, &["--includedir"]).expect("llvm-config --includedir failed");
+
+    let mut build = cc::Build::new();
+    build
+        .cpp(true)
+        .include(include)
+        .file("cpp/diagnostics.cc")
+        .flag_if_supported("-std=c++17");
+
+    // the shim subclasses `DiagnosticInfo`, so its vtable has to agree with LLVM's on RTTI
+    if query(&llvm_config, &["--has-rtti"]).as_deref() != Some("YES") {
+        build.flag_if_supported("-fno-rtti");
+    }
+
+    build.warnings(false);
+    build.compile("llvm-lock-detection-cpp");
+
+    if std::env::var_os("CARGO_FEATURE_LINK_LLVM").is_some() {
+        link_llvm(&llvm_config);
+    }
+
+    println!("cargo:rerun-if-changed=cpp");
+    println!("cargo:rerun-if-env-changed={}", LLVM_PREFIX);
+
+}
@@ -0,0 +1,45 @@
+// LLVM's C API can install and query diagnostic handlers but not report a diagnostic, so the
+// pass reports its findings through this shim.
+#include <llvm-c/Core.h>
+#include <llvm/IR/DiagnosticInfo.h>
+#include <llvm/IR/DiagnosticPrinter.h>
+#include <llvm/IR/LLVMContext.h>
+#include <llvm/IR/Module.h>
+
+namespace {
+
+// a finding about a module, printed by the default handler as `<severity>: <module>: <message>`
+class LockDiagnostic : public llvm::DiagnosticInfo {
+  const llvm::Module &Module;
+  const char *Message;
+
+public:
+  LockDiagnostic(const llvm::Module &Module, const char *Message, llvm::DiagnosticSeverity Severity)
+      : llvm::DiagnosticInfo(kind(), Severity), Module(Module), Message(Message) {}
+
+  static int kind() {
+    static const int Kind = llvm::getNextAvailablePluginDiagnosticKind();
+    return Kind;
+  }
+
+  void print(llvm::DiagnosticPrinter &Printer) const override {
+    Printer << Module.getModuleIdentifier() << ": " << Message;
+  }
+};
+
+} // namespace
+
+extern "C" void badlock_diagnose(LLVMModuleRef Module, const char *Message, LLVMDiagnosticSeverity Severity) {
+
+  llvm::DiagnosticSeverity Translated = llvm::DS_Error;
+  switch (Severity) {
+  case LLVMDSError: Translated = llvm::DS_Error; break;
+  case LLVMDSWarning: Translated = llvm::DS_Warning; break;
+  case LLVMDSRemark: Translated = llvm::DS_Remark; break;
+  case LLVMDSNote: Translated = llvm::DS_Note; break;
+  }
+
+  const llvm::Module *Unwrapped = llvm::unwrap(Module);
+  Unwrapped->getContext().diagnose(LockDiagnostic(*Unwrapped, Message, Translated));
+
+}
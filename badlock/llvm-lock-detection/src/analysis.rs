@@ -0,0 +1,860 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use llvm_plugin::inkwell::context::Context;
+use llvm_plugin::inkwell::memory_buffer::MemoryBuffer;
+use llvm_plugin::inkwell::module::{Linkage, Module};
+use llvm_plugin::inkwell::values::GlobalValue;
+use lock_detection::report::Report;
+use crate::catalog::Catalog;
+use crate::reentrant_lock_detection::pass::ReentrantLockPass;
+
+/// Runs the reentrant lock analysis over `module` without going through `opt`.
+pub fn analyze_module(module : &Module, catalog : Catalog) -> Report {
+    ReentrantLockPass::with_catalog(catalog).analyze(module)
+}
+
+/// Parses a textual (`.ll`) or bitcode (`.bc`) module.
+pub fn parse_file<'ctx>(context : &'ctx Context, path : &Path) -> Result<Module<'ctx>, anyhow::Error> {
+
+    let buffer = MemoryBuffer::create_from_file(path)
+    .map_err(|error| anyhow::anyhow!("Could not read module {}: {}", path.display(), error))?;
+
+    // the IR parser reads bitcode as well, telling them apart by their magic number
+    context.create_module_from_ir(buffer)
+    .map_err(|error| anyhow::anyhow!("Invalid module {}: {}", path.display(), error))
+
+}
+
+/// Parses a module and analyses it.
+pub fn analyze_file(path : &Path, catalog : Catalog) -> Result<Report, anyhow::Error> {
+
+    let context = Context::create();
+    let module = parse_file(&context, path)?;
+
+    Ok(analyze_module(&module, catalog))
+
+}
+
+/// Links the modules of several crates, given as crate name and path, into one and analyses it,
+/// so that a lock taken in one crate and taken again in another is found. Findings name the crate
+/// of each of their sites.
+pub fn analyze_crates(modules : &[(String, PathBuf)], catalog : Catalog) -> Result<Report, anyhow::Error> {
+
+    let context = Context::create();
+
+    let mut parsed = Vec::new();
+    for (crate_name, path) in modules {
+        parsed.push((crate_name.clone(), parse_file(&context, path)?));
+    }
+
+    let (linked, crates) = link_crates(parsed)?;
+    Ok(ReentrantLockPass::with_catalog(catalog).with_crates(crates).analyze(&linked))
+
+}
+
+/// Links the modules of several crates into the first, returning it with the crate defining each
+/// function and global.
+pub fn link_crates(
+    modules : Vec<(String, Module<'_>)>
+) -> Result<(Module<'_>, HashMap<String, String>), anyhow::Error> {
+
+    let mut crates = HashMap::new();
+    let mut linked : Option<Module> = None;
+
+    for (crate_name, module) in modules {
+
+        // definitions that would clash are made unique, e.g. the `main` of every binary
+        for global in globals(&module) {
+            let name = global.get_name().to_string_lossy().into_owned();
+            if global.is_declaration() || name.is_empty() {
+                continue;
+            }
+            if crates.contains_key(&name) && !merges(global.get_linkage()) {
+                let unique = format!("{}.{}", name, crate_name);
+                global.set_name(&unique);
+                global.set_linkage(Linkage::Internal);
+                crates.insert(unique, crate_name.clone());
+            } else {
+                crates.entry(name).or_insert_with(|| crate_name.clone());
+            }
+        }
+
+        match &linked {
+            Some(linked) => linked.link_in_module(module)
+            .map_err(|error| anyhow::anyhow!("Could not link crate {}: {}", crate_name, error))?,
+            None => linked = Some(module)
+        }
+
+    }
+
+    let linked = linked.ok_or(anyhow::anyhow!("No modules to link"))?;
+    Ok((linked, crates))
+
+}
+
+fn globals<'ctx>(module : &Module<'ctx>) -> Vec<GlobalValue<'ctx>> {
+    module.get_functions().map(|function| function.as_global_value())
+    .chain(module.get_globals())
+    .collect()
+}
+
+/// Whether the linker merges definitions of this linkage rather than rejecting duplicates.
+fn merges(linkage : Linkage) -> bool {
+    matches!(
+        linkage,
+        Linkage::LinkOnceAny | Linkage::LinkOnceODR | Linkage::LinkOnceODRAutoHide
+        | Linkage::WeakAny | Linkage::WeakODR | Linkage::Common | Linkage::Appending
+        | Linkage::AvailableExternally | Linkage::ExternalWeak
+    )
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    // `main` locks the same mutex twice while the first guard is still alive
+    pub const DOUBLE_LOCK : &str = r#"
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %first = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %first, ptr align 8 %m)
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+"#;
+
+    // `main` holds the guard of a `try_lock` while it locks the mutex, then try-locks it again
+    pub const TRY_LOCK : &str = r#"
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %tried = alloca [32 x i8], align 8
+  %locked = alloca [24 x i8], align 8
+  %again = alloca [32 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$8try_lock17h3c1f0e9a7b2d4c6eE"(ptr sret([32 x i8]) %tried, ptr align 8 %m)
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %locked, ptr align 8 %m)
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$8try_lock17h3c1f0e9a7b2d4c6eE"(ptr sret([32 x i8]) %again, ptr align 8 %m)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$8try_lock17h3c1f0e9a7b2d4c6eE"(ptr sret([32 x i8]), ptr align 8)
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+"#;
+
+    // `if let Ok(g) = m.try_lock() { m.lock(); }`, the `Ok` tag being 0
+    pub const TRY_LOCK_BRANCH : &str = r#"
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %tried = alloca [32 x i8], align 8
+  %locked = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$8try_lock17h3c1f0e9a7b2d4c6eE"(ptr sret([32 x i8]) %tried, ptr align 8 %m)
+  %tag = load i64, ptr %tried, align 8
+  %ok = icmp eq i64 %tag, 0
+  br i1 %ok, label %acquired, label %busy
+
+acquired:
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %locked, ptr align 8 %m)
+  ret void
+
+busy:
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$8try_lock17h3c1f0e9a7b2d4c6eE"(ptr sret([32 x i8]), ptr align 8)
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+"#;
+
+    // `main` holds a lock on the global and calls into `storage`, which locks it again, each
+    // dropping its guard before returning
+    pub const SERVICE : &str = r#"
+@STATE = global [16 x i8] zeroinitializer, align 8
+
+define void @main() {
+start:
+  %guard = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %guard, ptr align 8 @STATE)
+  call void @put()
+  call void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr %guard)
+  ret void
+}
+
+declare void @put()
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr)
+"#;
+
+    pub const STORAGE : &str = r#"
+@STATE = external global [16 x i8], align 8
+
+define void @put() {
+start:
+  %guard = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %guard, ptr align 8 @STATE)
+  call void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr %guard)
+  ret void
+}
+
+define void @main() {
+start:
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr)
+"#;
+
+    // `f` holds `@A` while locking `@B`, `g` holds `@B` while locking `@A`
+    pub const INVERSION : &str = r#"
+@A = global [16 x i8] zeroinitializer, align 8
+@B = global [16 x i8] zeroinitializer, align 8
+
+define void @f() {
+start:
+  %a = alloca [24 x i8], align 8
+  %b = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %a, ptr align 8 @A)
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %b, ptr align 8 @B)
+  ret void
+}
+
+define void @g() {
+start:
+  %b = alloca [24 x i8], align 8
+  %a = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %b, ptr align 8 @B)
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %a, ptr align 8 @A)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+"#;
+
+    // `let c = false; let g = m.lock(); if c { drop(g) } m.lock();` with `drop` inlined, where `c`
+    // is a `bool` of the source, so the branch on it is no drop flag and `g` may still be held at
+    // the second lock
+    pub const BOOL_DROP : &str = r#"
+define void @main() !dbg !4 {
+start:
+  %m = alloca [16 x i8], align 8
+  %g = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  %c = alloca i8, align 1
+  call void @llvm.dbg.declare(metadata ptr %c, metadata !7, metadata !DIExpression()), !dbg !9
+  store i8 0, ptr %c, align 1
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %g, ptr align 8 %m)
+  %flag = load i8, ptr %c, align 1
+  %bit = trunc i8 %flag to i1
+  br i1 %bit, label %dropped, label %kept
+
+dropped:
+  call void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr %g)
+  br label %relock
+
+kept:
+  br label %relock
+
+relock:
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr)
+declare void @llvm.dbg.declare(metadata, metadata, metadata)
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !1, producer: "rustc", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "src/main.rs", directory: "/work")
+!2 = !{i32 2, !"Debug Info Version", i32 3}
+!4 = distinct !DISubprogram(name: "main", scope: !1, file: !1, line: 1, type: !5, scopeLine: 1, spFlags: DISPFlagDefinition, unit: !0)
+!5 = !DISubroutineType(types: !6)
+!6 = !{null}
+!7 = !DILocalVariable(name: "c", scope: !4, file: !1, line: 2, type: !8)
+!8 = !DIBasicType(name: "bool", size: 8, encoding: DW_ATE_boolean)
+!9 = !DILocation(line: 2, column: 9, scope: !4)
+"#;
+
+    // let g = m.lock(); let woken = cv.wait(g); let second = m.lock(); with the lock released
+    // while waiting, it is `woken` that holds it again
+    pub const CONDVAR_WAIT : &str = r#"
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %cv = alloca [16 x i8], align 8
+  %g = alloca [24 x i8], align 8
+  %woken = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %g, ptr align 8 %m)
+  call void @_ZN3std4sync7condvar7Condvar4wait17h5c2d7e9a1b3f4c6dE(ptr sret([24 x i8]) %woken, ptr align 8 %cv, ptr %g)
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @_ZN3std4sync7condvar7Condvar4wait17h5c2d7e9a1b3f4c6dE(ptr sret([24 x i8]), ptr align 8, ptr)
+declare void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr)
+"#;
+
+    // DOUBLE_LOCK, with `m` declared a `Mutex<i32>` of `main` by debug info
+    pub const NAMED_LOCK : &str = r#"
+define void @main() !dbg !4 {
+start:
+  %m = alloca [16 x i8], align 8
+  %first = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  call void @llvm.dbg.declare(metadata ptr %m, metadata !7, metadata !DIExpression()), !dbg !9
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %first, ptr align 8 %m), !dbg !10
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %second, ptr align 8 %m), !dbg !11
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @llvm.dbg.declare(metadata, metadata, metadata)
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !1, producer: "rustc", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "src/main.rs", directory: "/work")
+!2 = !{i32 2, !"Debug Info Version", i32 3}
+!4 = distinct !DISubprogram(name: "main", scope: !1, file: !1, line: 1, type: !5, scopeLine: 1, spFlags: DISPFlagDefinition, unit: !0)
+!5 = !DISubroutineType(types: !6)
+!6 = !{null}
+!7 = !DILocalVariable(name: "m", scope: !4, file: !1, line: 2, type: !8)
+!8 = !DICompositeType(tag: DW_TAG_structure_type, name: "Mutex<i32>", file: !1, size: 128, align: 64, elements: !12, identifier: "mutex_i32")
+!9 = !DILocation(line: 2, column: 9, scope: !4)
+!10 = !DILocation(line: 3, column: 15, scope: !4)
+!11 = !DILocation(line: 4, column: 15, scope: !4)
+!12 = !{}
+"#;
+
+    // `main` locks `m` and switches on `x`: the default drops the guard before `bb1` locks `m`
+    // again, `bb10` only drops it, and `bb20`, only reached through the switch table, locks `m`
+    // while still holding it. Only `start` and `bb2` branch to labels starting with `%bb1`.
+    pub const SWITCH : &str = r#"
+define void @main(i32 %x) {
+start:
+  %m = alloca [16 x i8], align 8
+  %first = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  %third = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %first, ptr align 8 %m)
+  switch i32 %x, label %bb2 [
+    i32 1, label %bb10
+    i32 2, label %bb20
+  ]
+
+bb2:
+  call void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr %first)
+  br label %bb1
+
+bb1:
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+
+bb10:
+  call void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr %first)
+  ret void
+
+bb20:
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %third, ptr align 8 %m)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr)
+"#;
+
+    // `main` locks `m` through the quoted symbol and then again through an alias of it, aliases
+    // only being allowed to definitions
+    pub const ALIASED_LOCK : &str = r#"
+@lock_alias = alias void (ptr, ptr), ptr @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"
+
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %first = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %first, ptr align 8 %m)
+  call void @lock_alias(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+}
+
+define void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %guard, ptr align 8 %self) {
+start:
+  ret void
+}
+"#;
+
+    // `main` locks `m` directly and then again through a cast of the callee, which opaque pointers
+    // only leave for casts between address spaces
+    pub const CAST_LOCK : &str = r#"
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %first = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  call addrspace(1) void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %first, ptr align 8 %m)
+  call void addrspacecast (ptr addrspace(1) @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E" to ptr)(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8) addrspace(1)
+"#;
+
+    // the body of `async fn work(m : &tokio::sync::Mutex<i32>) { let g = m.lock().await; let h =
+    // m.lock().await; }`, each `.await` polling the future `lock` returned, which stands for `m`,
+    // once it was turned into a future
+    pub const TOKIO_LOCK : &str = r#"
+define void @"_ZN4main4work28_$u7b$$u7b$closure$u7d$$u7d$17h4e1f2a3b5c6d7e8fE"(ptr %m, ptr %cx) {
+start:
+  %lock = alloca [64 x i8], align 8
+  %fut = alloca [64 x i8], align 8
+  %g = alloca [16 x i8], align 8
+  %relock = alloca [64 x i8], align 8
+  %refut = alloca [64 x i8], align 8
+  %h = alloca [16 x i8], align 8
+  call void @"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock17h1a2b3c4d5e6f7a8bE"(ptr sret([64 x i8]) %lock, ptr align 8 %m)
+  call void @"_ZN59_$LT$F$u20$as$u20$core..future..into_future..IntoFuture$GT$11into_future17h2b3c4d5e6f7a8b9cE"(ptr sret([64 x i8]) %fut, ptr %lock)
+  call void @"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock28_$u7b$$u7b$closure$u7d$$u7d$17h3c4d5e6f7a8b9c0dE"(ptr sret([16 x i8]) %g, ptr align 8 %fut, ptr %cx)
+  call void @"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock17h1a2b3c4d5e6f7a8bE"(ptr sret([64 x i8]) %relock, ptr align 8 %m)
+  call void @"_ZN59_$LT$F$u20$as$u20$core..future..into_future..IntoFuture$GT$11into_future17h2b3c4d5e6f7a8b9cE"(ptr sret([64 x i8]) %refut, ptr %relock)
+  call void @"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock28_$u7b$$u7b$closure$u7d$$u7d$17h3c4d5e6f7a8b9c0dE"(ptr sret([16 x i8]) %h, ptr align 8 %refut, ptr %cx)
+  ret void
+}
+
+declare void @"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock17h1a2b3c4d5e6f7a8bE"(ptr sret([64 x i8]), ptr align 8)
+declare void @"_ZN59_$LT$F$u20$as$u20$core..future..into_future..IntoFuture$GT$11into_future17h2b3c4d5e6f7a8b9cE"(ptr sret([64 x i8]), ptr)
+declare void @"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock28_$u7b$$u7b$closure$u7d$$u7d$17h3c4d5e6f7a8b9c0dE"(ptr sret([16 x i8]), ptr align 8, ptr)
+declare void @"_ZN4core3ptr62drop_in_place$LT$tokio..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h4d5e6f7a8b9c0d1eE"(ptr)
+"#;
+
+    // `let g = m.lock(); drop(g); m.lock();`, `drop` releasing the guard it was moved into
+    // through its own drop glue
+    pub const EXPLICIT_DROP : &str = r#"
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %g = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %g, ptr align 8 %m)
+  call void @_ZN4core3mem4drop17h5e6f7a8b9c0d1e2fE(ptr %g)
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+}
+
+define internal void @_ZN4core3mem4drop17h5e6f7a8b9c0d1e2fE(ptr %_x) {
+start:
+  call void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr %_x)
+  ret void
+}
+
+define internal void @_ZN4core3mem6forget17h6f7a8b9c0d1e2f3aE(ptr %t) {
+start:
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr)
+"#;
+
+    // `fn state(&self) -> MutexGuard<i32> { self.lock() }`, called twice by `main` while the
+    // guard of the first call is alive
+    pub const RETURNED_GUARD : &str = r#"
+define void @main() {
+start:
+  %m = alloca [16 x i8], align 8
+  %first = alloca [24 x i8], align 8
+  %second = alloca [24 x i8], align 8
+  call void @state(ptr sret([24 x i8]) %first, ptr align 8 %m)
+  call void @state(ptr sret([24 x i8]) %second, ptr align 8 %m)
+  ret void
+}
+
+define internal void @state(ptr sret([24 x i8]) %guard, ptr align 8 %self) {
+start:
+  call void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]) %guard, ptr align 8 %self)
+  ret void
+}
+
+declare void @"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E"(ptr sret([24 x i8]), ptr align 8)
+declare void @"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE"(ptr)
+"#;
+
+    pub fn parse<'ctx>(context : &'ctx Context, name : &str, ir : &str) -> Result<Module<'ctx>, anyhow::Error> {
+        let buffer = MemoryBuffer::create_from_memory_range_copy(ir.as_bytes(), name);
+        context.create_module_from_ir(buffer).map_err(|error| anyhow::anyhow!("{}", error))
+    }
+
+    #[test]
+    fn test_analyze_module_reports_findings() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "double_lock", DOUBLE_LOCK)?;
+
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert!(report.findings[0].first_lock.contains("%first"));
+        assert!(report.findings[0].second_lock.contains("%second"));
+
+        // a pass can be reused, every run starts from empty facts
+        let pass = ReentrantLockPass::with_catalog(Catalog::default());
+        assert_eq!(pass.analyze(&module), report);
+        assert_eq!(pass.analyze(&module), report);
+        assert!(!ReentrantLockPass::with_catalog(Catalog::new()).analyze(&module).may_deadlock());
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_resources_are_named_after_source_variables() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "named_lock", NAMED_LOCK)?;
+
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].resource, "main bb0 #0 `m: Mutex<i32> in main`");
+
+        // without debug info the resource falls back to its IR name
+        let module = parse(&context, "double_lock", DOUBLE_LOCK)?;
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings[0].resource, "main bb0 #0 `%m in main`");
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_runs_agree_on_symbols() -> Result<(), anyhow::Error> {
+
+        let pass = configured("analyses=reentrant,lock-order")?;
+
+        for (name, ir) in [("double_lock", DOUBLE_LOCK), ("inversion", INVERSION)] {
+
+            // each run parses the module anew, so its values live at other addresses
+            let runs : Vec<Report> = (0..2).map(|_| {
+                let context = Context::create();
+                parse(&context, name, ir).map(|module| pass.analyze(&module))
+            }).collect::<Result<_, _>>()?;
+
+            assert!(runs[0].may_deadlock());
+            assert_eq!(runs[0], runs[1]);
+            assert!(!format!("{:?}", runs[0]).contains("0x"));
+
+        }
+
+        // sites are named by function, block and index
+        let context = Context::create();
+        let report = analyze_module(&parse(&context, "double_lock", DOUBLE_LOCK)?, Catalog::default());
+        assert!(report.findings[0].first_lock.starts_with("main bb0 #3 `"));
+        assert!(report.findings[0].second_lock.starts_with("main bb0 #4 `"));
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_control_flows_along_terminator_successors() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "switch", SWITCH)?;
+
+        // `start` branches to `bb10`, not to `bb1`, so `first` is released before `second`
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert!(report.findings[0].first_lock.contains("%first"));
+        assert!(report.findings[0].second_lock.starts_with("main bb4 #0"));
+        assert!(report.findings[0].second_lock.contains("%third"));
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_callees_resolve_through_aliases_and_casts() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+
+        for (name, ir, second) in [("aliased_lock", ALIASED_LOCK, "@lock_alias("), ("cast_lock", CAST_LOCK, "addrspacecast")] {
+
+            let module = parse(&context, name, ir)?;
+            let call = module.get_function("main").and_then(|main| main.get_first_basic_block())
+            .and_then(|block| llvm_plugin::utils::InstructionIterator::new(&block).find(|inst| inst.to_string().contains(second)))
+            .ok_or(anyhow::anyhow!("no second call"))?;
+
+            let callee = crate::util::llvm_ir::InstructionCallSites::callee(&call)?.function().ok_or(anyhow::anyhow!("no callee"))?;
+            assert_eq!(callee.get_name().to_str()?, "_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E");
+
+            let report = analyze_module(&module, Catalog::default());
+            assert_eq!(report.findings.len(), 1, "{}", name);
+            assert!(report.findings[0].first_lock.contains("%first"));
+            assert!(report.findings[0].second_lock.contains(second));
+
+        }
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_tokio_locks_are_acquired_at_the_await() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "tokio_lock", TOKIO_LOCK)?;
+
+        // `g` is still held when the second `.await` acquires `m`
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert!(report.findings[0].first_lock.starts_with("main::work::{{closure}} bb0 #8"));
+        assert!(report.findings[0].first_lock.contains("%g"));
+        assert!(report.findings[0].second_lock.contains("%h"));
+        assert!(report.findings[0].resource.contains("%m"));
+
+        // dropping `g` before the second `.await` releases `m`
+        let dropped = TOKIO_LOCK.replace(
+            "  call void @\"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock17h1a2b3c4d5e6f7a8bE\"(ptr sret([64 x i8]) %relock",
+            "  call void @\"_ZN4core3ptr62drop_in_place$LT$tokio..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h4d5e6f7a8b9c0d1eE\"(ptr %g)\n  call void @\"_ZN5tokio4sync5mutex14Mutex$LT$T$GT$4lock17h1a2b3c4d5e6f7a8bE\"(ptr sret([64 x i8]) %relock"
+        );
+        let module = parse(&context, "tokio_lock_dropped", &dropped)?;
+        assert!(!analyze_module(&module, Catalog::default()).may_deadlock());
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_guards_are_released_by_drop_and_never_by_forget() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "explicit_drop", EXPLICIT_DROP)?;
+        assert!(!analyze_module(&module, Catalog::default()).may_deadlock());
+
+        // `mem::forget(g)` leaks the guard, so `m` is still held at the second lock
+        let forgotten = EXPLICIT_DROP.replace(
+            "call void @_ZN4core3mem4drop17h5e6f7a8b9c0d1e2fE(ptr %g)",
+            "call void @_ZN4core3mem6forget17h6f7a8b9c0d1e2f3aE(ptr %g)"
+        );
+        let module = parse(&context, "forget", &forgotten)?;
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert!(report.findings[0].first_lock.contains("%g"));
+        assert!(report.findings[0].second_lock.contains("%second"));
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_returned_guards_are_held_by_the_caller() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "returned_guard", RETURNED_GUARD)?;
+
+        // the first call returns `m` held and the second locks it again in `state`, once for `m`
+        // and once for the object of `self` that stands for callers outside the module, but no
+        // call holds the guard it returns while `state` still runs
+        let report = analyze_module(&module, Catalog::default());
+        let resources : Vec<&str> = report.findings.iter().map(|finding| finding.resource.as_str()).collect();
+        assert_eq!(resources, vec!["main bb0 #0 `%m in main`", "state arg1 `%self in state`"]);
+        assert!(report.findings.iter().all(|finding| finding.first_lock.starts_with("main bb0 #3 `")));
+        assert!(report.findings.iter().all(|finding| finding.second_lock.starts_with("state bb0 #0 `")));
+
+        // the caller's drop of the first guard releases `m`
+        let dropped = RETURNED_GUARD.replace(
+            "  call void @state(ptr sret([24 x i8]) %second",
+            "  call void @\"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE\"(ptr %first)\n  call void @state(ptr sret([24 x i8]) %second"
+        );
+        let module = parse(&context, "returned_guard_dropped", &dropped)?;
+        assert!(!analyze_module(&module, Catalog::default()).may_deadlock());
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_wait_releases_and_reacquires() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "condvar_wait", CONDVAR_WAIT)?;
+
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert!(report.findings[0].first_lock.contains("%woken"));
+        assert!(report.findings[0].second_lock.contains("%second"));
+
+        // dropping the guard `wait` returned releases the lock again
+        let dropped = CONDVAR_WAIT.replace(
+            "  call void @\"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E\"(ptr sret([24 x i8]) %second",
+            "  call void @\"_ZN4core3ptr60drop_in_place$LT$std..sync..mutex..MutexGuard$LT$i32$GT$$GT$17h0f3e2a1b4c5d6e7fE\"(ptr %woken)\n  call void @\"_ZN3std4sync5mutex14Mutex$LT$T$GT$4lock17h8dad7da268b6d1f1E\"(ptr sret([24 x i8]) %second"
+        );
+        let module = parse(&context, "condvar_wait_dropped", &dropped)?;
+        assert!(!analyze_module(&module, Catalog::default()).may_deadlock());
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_try_lock_holds_but_never_blocks() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "try_lock", TRY_LOCK)?;
+
+        // the guard of the first `try_lock` is held at `lock`, which blocks, but the second `try_lock` gives up
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert!(report.findings[0].first_lock.contains("%tried"));
+        assert!(report.findings[0].second_lock.contains("%locked"));
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_source_bools_are_not_drop_flags() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+
+        // `g` is dropped on one path only, so it is still held at the second lock on the other
+        let module = parse(&context, "bool_drop", BOOL_DROP)?;
+        let report = analyze_module(&module, Catalog::default());
+        assert_eq!(report.findings.len(), 1);
+        assert!(report.findings[0].first_lock.contains("%g"));
+        assert!(report.findings[0].second_lock.contains("%second"));
+
+        // without debug info the same byte is taken for rustc's drop flag of `g`, which is only
+        // clear once `g` was moved out
+        let flag = BOOL_DROP.replace("  call void @llvm.dbg.declare(metadata ptr %c, metadata !7, metadata !DIExpression()), !dbg !9\n", "");
+        let module = parse(&context, "drop_flag", &flag)?;
+        assert!(!analyze_module(&module, Catalog::default()).may_deadlock());
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_analyze_crates_finds_deadlocks_across_crates() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let (linked, crates) = link_crates(vec![
+            ("service".to_string(), parse(&context, "service", SERVICE)?),
+            ("storage".to_string(), parse(&context, "storage", STORAGE)?)
+        ])?;
+
+        // both binaries keep their `main`
+        assert_eq!(crates.get("main").map(String::as_str), Some("service"));
+        assert_eq!(crates.get("main.storage").map(String::as_str), Some("storage"));
+        assert_eq!(crates.get("put").map(String::as_str), Some("storage"));
+
+        let report = ReentrantLockPass::with_catalog(Catalog::default()).with_crates(crates).analyze(&linked);
+
+        assert_eq!(report.findings.len(), 1);
+        let finding = &report.findings[0];
+        assert_eq!(finding.first_lock_crate.as_deref(), Some("service"));
+        assert_eq!(finding.second_lock_crate.as_deref(), Some("storage"));
+        assert!(finding.crosses_crates());
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_excluded_site_suppresses_finding() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let (linked, crates) = link_crates(vec![
+            ("service".to_string(), parse(&context, "service", SERVICE)?),
+            ("storage".to_string(), parse(&context, "storage", STORAGE)?)
+        ])?;
+
+        // the first lock is in `main` and the second in `put`, including either reports the finding
+        assert_eq!(configured("include=^main$")?.with_crates(crates.clone()).analyze(&linked).findings.len(), 1);
+        assert_eq!(configured("include=^put$")?.with_crates(crates.clone()).analyze(&linked).findings.len(), 1);
+
+        // but excluding either suppresses it, even when the other is included
+        assert!(!configured("exclude=^put$")?.with_crates(crates.clone()).analyze(&linked).may_deadlock());
+        assert!(!configured("include=^main$;exclude=^put$")?.with_crates(crates).analyze(&linked).may_deadlock());
+
+        Ok(())
+
+    }
+
+    pub fn configured(parameters : &str) -> Result<ReentrantLockPass, anyhow::Error> {
+        ReentrantLockPass::from_pipeline_name(&format!("reentrant-lock-detection<{}>", parameters))
+        .ok_or(anyhow::anyhow!("not the pass"))?
+    }
+
+    #[test]
+    fn test_config_selects_analyses_and_filters_findings() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "inversion", INVERSION)?;
+
+        // only the reentrant analysis runs by default, and no lock is taken twice
+        assert!(!ReentrantLockPass::new().analyze(&module).may_deadlock());
+
+        let pass = configured("analyses=reentrant,lock-order")?;
+        let report = pass.analyze(&module);
+        assert!(report.findings.is_empty());
+        assert_eq!(report.inversions.len(), 1);
+        assert!(report.inversions[0].first_resource.contains("@A"));
+        assert!(report.inversions[0].second_resource.contains("@B"));
+
+        let pass = configured("analyses=lock-order;exclude=^[fg]$")?;
+        assert!(!pass.analyze(&module).may_deadlock());
+
+        let module = parse(&context, "double_lock", DOUBLE_LOCK)?;
+        assert!(configured("analyses=lock-order")?.analyze_locks(&module).deadlocks.is_empty());
+        assert!(ReentrantLockPass::new().analyze_locks(&module).held.is_empty());
+
+        let pass = configured("include=^my_crate::")?;
+        assert!(!pass.analyze(&module).may_deadlock());
+        let pass = configured("include=^main$")?;
+        assert_eq!(pass.analyze(&module).findings.len(), 1);
+
+        assert!(ReentrantLockPass::from_pipeline_name("reentrant-lock-detection<verbose>").is_some_and(|pass| pass.is_err()));
+        assert!(ReentrantLockPass::from_pipeline_name("other-pass").is_none());
+
+        Ok(())
+
+    }
+
+    #[test]
+    fn test_lock_analysis_answers_queries_by_instruction() -> Result<(), anyhow::Error> {
+
+        let context = Context::create();
+        let module = parse(&context, "double_lock", DOUBLE_LOCK)?;
+        let result = configured("analyses=reentrant,lock-order")?.analyze_locks(&module);
+
+        let calls : Vec<_> = module.get_function("main").and_then(|main| main.get_first_basic_block())
+        .map(|block| llvm_plugin::utils::InstructionIterator::new(&block)
+            .filter(|inst| inst.get_opcode() == llvm_plugin::inkwell::values::InstructionOpcode::Call)
+            .collect())
+        .unwrap_or_default();
+        assert_eq!(calls.len(), 2);
+
+        // nothing is held at the first lock, the mutex is at the second
+        assert!(result.held_at(&calls[0]).is_empty());
+        let held = result.held_at(&calls[1]);
+        assert_eq!(held.len(), 1);
+        assert!(result.display(held.iter().next().unwrap()).contains("%m"));
+
+        assert!(result.deadlocks_at(&calls[0]).is_empty());
+        assert_eq!(result.deadlocks_at(&calls[1]).len(), 1);
+        assert_eq!(result.report(&ReentrantLockPass::new().config), analyze_module(&module, Catalog::default()));
+
+        Ok(())
+
+    }
+
+}
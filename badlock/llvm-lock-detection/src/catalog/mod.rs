@@ -0,0 +1,367 @@
+use std::path::Path;
+use serde::Deserialize;
+use crate::util::demangled_path::RustPath;
+
+/// The catalog every pass starts from, covering `std::sync`, `parking_lot` and `tokio::sync`.
+pub const DEFAULT_CATALOG : &str = include_str!("default.toml");
+
+/// What a call does to the lock it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    New,
+    Lock,
+    ReadLock,
+    TryLock,
+    ReentrantLock,
+    Release,
+    Wait,
+    Alias,
+    Forget
+}
+
+impl Role {
+
+    /// Whether the call holds the lock once it succeeds, and until the guard is released.
+    pub fn acquires(&self) -> bool {
+        matches!(self, Role::Lock | Role::ReadLock | Role::TryLock | Role::ReentrantLock)
+    }
+
+    /// Whether the call waits for a lock another guard holds, and so can deadlock on it. Try-locks
+    /// give up instead, and reentrant locks only wait for other threads.
+    pub fn blocks(&self) -> bool {
+        matches!(self, Role::Lock | Role::ReadLock)
+    }
+
+}
+
+/// An argument of the callee's Rust signature, or its returned value (in registers or through
+/// an `sret` out pointer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(untagged)]
+pub enum Operand {
+    Argument(u32),
+    Return(ReturnValue)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum ReturnValue {
+    #[serde(rename = "return")]
+    Return
+}
+
+/// One `[[api]]` entry of a catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockApi {
+    #[serde(default)]
+    pub path : Option<String>,
+    #[serde(default)]
+    pub drops : Option<String>,
+    pub role : Role,
+    #[serde(default)]
+    pub lock : Option<Operand>,
+    #[serde(default)]
+    pub guard : Option<Operand>,
+    // `lock` points to a guard or future that holds the lock, rather than to the lock itself
+    #[serde(default)]
+    pub indirect : bool
+}
+
+impl LockApi {
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+
+        let name = self.path.as_deref().or(self.drops.as_deref()).unwrap_or("<unnamed>");
+
+        match (&self.path, &self.drops) {
+            (Some(_), None) => {},
+            (None, Some(_)) if self.role == Role::Release => {},
+            (None, Some(_)) => {
+                return Err(anyhow::anyhow!("`{}`: only `release` entries can match by `drops`", name));
+            },
+            _ => {
+                return Err(anyhow::anyhow!("`{}`: exactly one of `path` and `drops` should be set", name));
+            }
+        }
+
+        let (needs_lock, needs_guard) = match self.role {
+            Role::New => (true, false),
+            Role::Lock | Role::ReadLock | Role::TryLock | Role::ReentrantLock => (true, false),
+            Role::Release => (false, true),
+            Role::Wait | Role::Alias => (true, true),
+            Role::Forget => (false, true)
+        };
+
+        if needs_lock && self.lock.is_none() {
+            return Err(anyhow::anyhow!("`{}`: role {:?} needs a `lock` operand", name, self.role));
+        }
+
+        if needs_guard && self.guard.is_none() {
+            return Err(anyhow::anyhow!("`{}`: role {:?} needs a `guard` operand", name, self.role));
+        }
+
+        Ok(())
+
+    }
+
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogFile {
+    #[serde(default, rename = "api")]
+    apis : Vec<LockApi>
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Call(RustPath),
+    Drop(RustPath)
+}
+
+#[derive(Debug, Clone)]
+struct CatalogEntry {
+    api : LockApi,
+    pattern : Pattern
+}
+
+/// Lock APIs the pass recognises, matched against demangled callee paths.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    entries : Vec<CatalogEntry>
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::from_toml(DEFAULT_CATALOG).expect("the built-in catalog should be valid")
+    }
+}
+
+impl Catalog {
+
+    pub fn new() -> Self {
+        Self {
+            entries : Vec::new()
+        }
+    }
+
+    pub fn from_apis(apis : Vec<LockApi>) -> Result<Self, anyhow::Error> {
+
+        let mut entries = Vec::new();
+
+        for api in apis {
+
+            api.validate()?;
+
+            let pattern = match (&api.path, &api.drops) {
+                (Some(path), _) => Pattern::Call(RustPath::parse(path)?),
+                (None, Some(drops)) => Pattern::Drop(RustPath::parse(drops)?),
+                (None, None) => unreachable!("validated entries have a path or drops")
+            };
+
+            entries.push(CatalogEntry {
+                api,
+                pattern
+            });
+
+        }
+
+        Ok(Self {
+            entries
+        })
+
+    }
+
+    pub fn from_toml(text : &str) -> Result<Self, anyhow::Error> {
+
+        let file : CatalogFile = toml::from_str(text)?;
+        Self::from_apis(file.apis)
+
+    }
+
+    pub fn from_json(text : &str) -> Result<Self, anyhow::Error> {
+
+        let file : CatalogFile = serde_json::from_str(text)?;
+        Self::from_apis(file.apis)
+
+    }
+
+    /// Reads a `.json` catalog as JSON and anything else as TOML.
+    pub fn from_file(path : &Path) -> Result<Self, anyhow::Error> {
+
+        let text = std::fs::read_to_string(path)
+        .map_err(|error| anyhow::anyhow!("Could not read catalog {}: {}", path.display(), error))?;
+
+        let catalog = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&text),
+            _ => Self::from_toml(&text)
+        };
+
+        catalog.map_err(|error| anyhow::anyhow!("Invalid catalog {}: {}", path.display(), error))
+
+    }
+
+    /// Adds the entries of `other`, which take precedence over the ones already present.
+    pub fn extend(&mut self, other : Catalog) {
+
+        let mut entries = other.entries;
+        entries.append(&mut self.entries);
+        self.entries = entries;
+
+    }
+
+    /// The first entry matching the demangled callee.
+    pub fn classify(&self, path : &RustPath) -> Option<&LockApi> {
+
+        self.entries.iter().find(|entry| match &entry.pattern {
+            Pattern::Call(pattern) => path.matches(pattern),
+            Pattern::Drop(guard) => path.drops(guard)
+        }).map(|entry| &entry.api)
+
+    }
+
+    /// Names of the types `new` entries construct, e.g. `Mutex` for `std::sync::mutex::Mutex::new`.
+    pub fn lock_types(&self) -> Vec<String> {
+
+        let mut lock_types = Vec::new();
+
+        for entry in self.entries.iter().filter(|entry| entry.api.role == Role::New) {
+            if let Pattern::Call(path) = &entry.pattern {
+                let names = path.names();
+                if names.len() >= 2 && !lock_types.iter().any(|lock_type| lock_type == names[names.len() - 2]) {
+                    lock_types.push(names[names.len() - 2].to_string());
+                }
+            }
+        }
+
+        lock_types
+
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub fn classify(catalog : &Catalog, demangled : &str) -> Option<Role> {
+        catalog.classify(&RustPath::parse(demangled).unwrap()).map(|api| api.role)
+    }
+
+    #[test]
+    fn test_default_catalog_classifies_known_apis() {
+
+        let catalog = Catalog::default();
+
+        assert_eq!(classify(&catalog, "std::sync::mutex::Mutex<T>::lock::h8dad7da268b6d1f1"), Some(Role::Lock));
+        assert_eq!(classify(&catalog, "std::sync::mutex::Mutex<T>::new"), Some(Role::New));
+        assert_eq!(
+            classify(&catalog, "core::ptr::drop_in_place<std::sync::mutex::MutexGuard<alloc::string::String>>"),
+            Some(Role::Release)
+        );
+        assert_eq!(classify(&catalog, "<lock_api::remutex::ReentrantMutex<R, G, T>>::lock"), Some(Role::ReentrantLock));
+        assert_eq!(classify(&catalog, "tokio::sync::mutex::Mutex<T>::lock"), Some(Role::Alias));
+        assert_eq!(classify(&catalog, "tokio::sync::mutex::Mutex<T>::lock::{{closure}}"), Some(Role::Lock));
+        assert_eq!(classify(&catalog, "alloc::vec::Vec<T, A>::push"), None);
+
+        // the poll closure is handed the future, which holds the lock
+        let poll = catalog.classify(&RustPath::parse("tokio::sync::mutex::Mutex<T>::lock::{{closure}}").unwrap()).unwrap();
+        assert!(poll.indirect);
+
+        let lock_types = catalog.lock_types();
+        assert!(lock_types.contains(&"Mutex".to_string()));
+        assert!(lock_types.contains(&"RwLock".to_string()));
+        assert!(!lock_types.contains(&"MutexGuard".to_string()));
+
+    }
+
+    #[test]
+    fn test_default_catalog_sees_through_shared_pointers_and_statics() {
+
+        let catalog = Catalog::default();
+
+        assert_eq!(classify(&catalog, "alloc::sync::Arc<T>::new"), Some(Role::New));
+        assert_eq!(classify(&catalog, "<alloc::rc::Rc<T>>::new"), Some(Role::New));
+
+        for demangled in [
+            "<alloc::sync::Arc<std::sync::mutex::Mutex<i32>> as core::clone::Clone>::clone",
+            "<alloc::sync::Arc<T, A> as core::ops::deref::Deref>::deref",
+            "<alloc::sync::Arc<T, A> as core::convert::AsRef<T>>::as_ref",
+            "<alloc::rc::Rc<T, A> as core::clone::Clone>::clone"
+        ] {
+            let api = catalog.classify(&RustPath::parse(demangled).unwrap()).unwrap();
+            assert_eq!(api.role, Role::Alias, "{}", demangled);
+            assert!(api.indirect, "{}", demangled);
+        }
+
+        assert_eq!(classify(&catalog, "std::sync::once_lock::OnceLock<T>::get_or_init"), Some(Role::Alias));
+        assert_eq!(
+            classify(&catalog, "<std::sync::lazy_lock::LazyLock<T, F> as core::ops::deref::Deref>::deref"),
+            Some(Role::Alias)
+        );
+        assert_eq!(classify(&catalog, "lazy_static::lazy::Lazy<T>::get"), Some(Role::Alias));
+
+        assert_eq!(classify(&catalog, "core::mem::forget"), Some(Role::Forget));
+        assert_eq!(classify(&catalog, "core::mem::drop"), None);
+
+        // cloning anything else is not a lock API
+        assert_eq!(classify(&catalog, "<alloc::vec::Vec<T, A> as core::clone::Clone>::clone"), None);
+
+    }
+
+    #[test]
+    fn test_custom_entries_take_precedence() {
+
+        let mut catalog = Catalog::default();
+        catalog.extend(Catalog::from_toml(r#"
+            [[api]]
+            path = "my_crate::sync::TracedMutex::lock"
+            role = "lock"
+            lock = 0
+            guard = "return"
+
+            [[api]]
+            path = "std::sync::mutex::Mutex::lock"
+            role = "try-lock"
+            lock = 0
+        "#).unwrap());
+
+        assert_eq!(classify(&catalog, "my_crate::sync::TracedMutex<T>::lock"), Some(Role::Lock));
+        assert_eq!(classify(&catalog, "std::sync::mutex::Mutex<T>::lock"), Some(Role::TryLock));
+
+        let api = catalog.classify(&RustPath::parse("my_crate::sync::TracedMutex<T>::lock").unwrap()).unwrap();
+        assert_eq!(api.lock, Some(Operand::Argument(0)));
+        assert_eq!(api.guard, Some(Operand::Return(ReturnValue::Return)));
+
+    }
+
+    #[test]
+    fn test_reads_json_and_rejects_incomplete_entries() {
+
+        let catalog = Catalog::from_json(r#"{
+            "api": [{ "drops": "my_crate::sync::TracedGuard", "role": "release", "guard": 0 }]
+        }"#).unwrap();
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(
+            classify(&catalog, "core::ptr::drop_in_place<(my_crate::sync::TracedGuard<u8>, u8)>"),
+            Some(Role::Release)
+        );
+
+        assert!(Catalog::from_toml("[[api]]\npath = \"a::lock\"\nrole = \"lock\"").is_err());
+        assert!(Catalog::from_toml("[[api]]\ndrops = \"a::Guard\"\nrole = \"lock\"\nlock = 0").is_err());
+        assert!(Catalog::from_toml("[[api]]\npath = \"a::lock\"\nrole = \"grab\"\nlock = 0").is_err());
+        assert!(Catalog::from_toml("[[api]]\npath = \"a::leak\"\nrole = \"forget\"").is_err());
+
+    }
+
+}
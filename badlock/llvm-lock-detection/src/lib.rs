@@ -1,2 +1,4 @@
 pub mod util;
+pub mod catalog;
+pub mod analysis;
 pub mod reentrant_lock_detection;
\ No newline at end of file
@@ -0,0 +1,289 @@
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use lock_detection::report::Severity;
+use crate::catalog::Catalog;
+
+/// How the pass prints its report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    // the `MAY DEADLOCK!` blocks `Report::parse` reads back
+    #[default]
+    Human,
+    Json
+}
+
+/// Which programs run over the facts of a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Analyses {
+    // a lock taken again while the same thread still holds it
+    pub reentrant : bool,
+    // two locks taken in both orders
+    pub lock_order : bool
+}
+
+impl Default for Analyses {
+    fn default() -> Self {
+        Self {
+            reentrant : true,
+            lock_order : false
+        }
+    }
+}
+
+impl Analyses {
+
+    /// The analyses either selects.
+    pub fn union(self, other : Analyses) -> Self {
+        Self {
+            reentrant : self.reentrant || other.reentrant,
+            lock_order : self.lock_order || other.lock_order
+        }
+    }
+
+}
+
+/// The options of a `reentrant-lock-detection<...>` pipeline element, e.g.
+/// `reentrant-lock-detection<format=json;out=report.json;include=^my_crate::;strict>`.
+#[derive(Debug, Clone, Default)]
+pub struct PassConfig {
+    // lock APIs to recognise, the built-in catalog extended by the `catalog` parameters
+    pub catalog : Catalog,
+    // the paths of the `catalog` parameters, in order
+    pub catalogs : Vec<PathBuf>,
+    pub format : OutputFormat,
+    // where the report is written, stdout when unset
+    pub out : Option<PathBuf>,
+    // findings are only reported when a lock site is in a function matching one of `include`,
+    // or any function when there are none, and in no function matching one of `exclude`
+    pub include : Vec<Regex>,
+    pub exclude : Vec<Regex>,
+    pub analyses : Analyses,
+    // findings are also reported through LLVM's diagnostic handler, and errors fail the run
+    pub strict : bool,
+    // warnings are reported as errors
+    pub werror : bool
+}
+
+impl PassConfig {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The config of `pass` or `pass<PARAMETERS>`, see `parse`. Returns `None` for any other pass name.
+    pub fn from_pipeline_name(name : &str, pass : &str) -> Option<Result<Self, anyhow::Error>> {
+
+        let parameters = name.strip_prefix(pass)?;
+        if parameters.is_empty() {
+            return Some(Ok(Self::new()));
+        }
+
+        let parameters = parameters.strip_prefix('<')?.strip_suffix('>')?;
+
+        Some(Self::parse(parameters))
+
+    }
+
+    /// Parses the `;` separated parameters between the angle brackets of the pass name.
+    pub fn parse(parameters : &str) -> Result<Self, anyhow::Error> {
+
+        let mut config = Self::new();
+
+        for parameter in parameters.split(';').filter(|parameter| !parameter.is_empty()) {
+            match parameter.split_once('=') {
+                Some(("catalog", path)) => {
+                    config.catalog.extend(Catalog::from_file(Path::new(path))?);
+                    config.catalogs.push(PathBuf::from(path));
+                },
+                Some(("format", format)) => config.format = match format {
+                    "human" => OutputFormat::Human,
+                    "json" => OutputFormat::Json,
+                    _ => anyhow::bail!("Unknown format `{}`, expected `human` or `json`", format)
+                },
+                Some(("out", path)) => config.out = Some(PathBuf::from(path)),
+                Some(("include", pattern)) => config.include.push(Self::regex(pattern)?),
+                Some(("exclude", pattern)) => config.exclude.push(Self::regex(pattern)?),
+                Some(("analyses", analyses)) => config.analyses = Self::analyses(analyses)?,
+                None if parameter == "strict" => config.strict = true,
+                None if parameter == "no-strict" => config.strict = false,
+                None if parameter == "werror" => config.werror = true,
+                _ => anyhow::bail!("Unknown parameter `{}`", parameter)
+            }
+        }
+
+        Ok(config)
+
+    }
+
+    fn regex(pattern : &str) -> Result<Regex, anyhow::Error> {
+        Regex::new(pattern).map_err(|error| anyhow::anyhow!("Invalid pattern `{}`: {}", pattern, error))
+    }
+
+    /// Parses a `,` separated list of `reentrant` and `lock-order`.
+    fn analyses(analyses : &str) -> Result<Analyses, anyhow::Error> {
+
+        let mut parsed = Analyses {
+            reentrant : false,
+            lock_order : false
+        };
+
+        for analysis in analyses.split(',').map(str::trim) {
+            match analysis {
+                "reentrant" => parsed.reentrant = true,
+                "lock-order" => parsed.lock_order = true,
+                _ => anyhow::bail!("Unknown analysis `{}`, expected `reentrant` or `lock-order`", analysis)
+            }
+        }
+
+        Ok(parsed)
+
+    }
+
+    /// The severity findings of `severity` are reported with.
+    pub fn promote(&self, severity : Severity) -> Severity {
+        match severity {
+            Severity::Warning if self.werror => Severity::Error,
+            severity => severity
+        }
+    }
+
+    /// Whether findings with a lock site in the demangled `function` are reported.
+    pub fn reports(&self, function : &str) -> bool {
+        self.includes(function) && !self.excludes(function)
+    }
+
+    /// Whether the demangled `function` matches one of `include`, or there are none.
+    pub fn includes(&self, function : &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|include| include.is_match(function))
+    }
+
+    /// Whether the demangled `function` matches one of `exclude`, which suppresses any finding
+    /// with a lock site in it.
+    pub fn excludes(&self, function : &str) -> bool {
+        self.exclude.iter().any(|exclude| exclude.is_match(function))
+    }
+
+}
+
+/// The catalog the lock analysis runs with, shared between the analysis and the pipeline parsing
+/// callback, as `opt` registers analyses before it parses the pipeline. The analysis runs once per
+/// module for every element of the pipeline, so they all have to give the same catalog, and it
+/// runs the analyses any of them selects.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineCatalog {
+    // the `catalog` parameters of the first element parsed, and the catalog they make up
+    parsed : Option<(Vec<PathBuf>, Catalog)>,
+    analyses : Option<Analyses>
+}
+
+impl PipelineCatalog {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the catalog of a pipeline element, failing when an earlier element gave another one.
+    pub fn set(&mut self, config : &PassConfig) -> Result<(), anyhow::Error> {
+
+        self.analyses = Some(match self.analyses {
+            Some(analyses) => analyses.union(config.analyses),
+            None => config.analyses
+        });
+
+        match &self.parsed {
+            Some((catalogs, _)) if *catalogs != config.catalogs => anyhow::bail!(
+                "The `catalog` parameters {:?} differ from the {:?} of an earlier element, the pipeline can only use one catalog",
+                config.catalogs, catalogs
+            ),
+            Some(_) => Ok(()),
+            None => {
+                self.parsed = Some((config.catalogs.clone(), config.catalog.clone()));
+                Ok(())
+            }
+        }
+
+    }
+
+    pub fn catalog(&self) -> Catalog {
+        self.parsed.as_ref().map(|(_, catalog)| catalog.clone()).unwrap_or_default()
+    }
+
+    /// The config the lock analysis runs with.
+    pub fn config(&self) -> PassConfig {
+        PassConfig {
+            catalog : self.catalog(),
+            analyses : self.analyses.unwrap_or_default(),
+            ..PassConfig::default()
+        }
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    pub fn test_parses_pipeline_parameters() -> Result<(), anyhow::Error> {
+
+        let config = PassConfig::parse("format=json;out=report.json;include=^my_crate::;exclude=::tests::;strict")?;
+
+        assert_eq!(config.format, OutputFormat::Json);
+        assert_eq!(config.out, Some(PathBuf::from("report.json")));
+        assert!(config.strict);
+        assert_eq!(config.analyses, Analyses::default());
+
+        assert!(config.reports("my_crate::service::put"));
+        assert!(!config.reports("my_crate::tests::put"));
+        assert!(!config.reports("std::sync::mutex::Mutex<T>::lock"));
+        assert!(PassConfig::new().reports("std::sync::mutex::Mutex<T>::lock"));
+
+        let config = PassConfig::parse("analyses=lock-order,reentrant")?;
+        assert_eq!(config.analyses, Analyses { reentrant : true, lock_order : true });
+        assert_eq!(config.format, OutputFormat::Human);
+        assert!(!config.strict);
+        assert_eq!(config.promote(Severity::Warning), Severity::Warning);
+
+        let config = PassConfig::parse("strict;werror")?;
+        assert!(config.strict);
+        assert_eq!(config.promote(Severity::Warning), Severity::Error);
+        assert_eq!(config.promote(Severity::Error), Severity::Error);
+
+        assert!(PassConfig::parse("format=xml").is_err());
+        assert!(PassConfig::parse("include=(").is_err());
+        assert!(PassConfig::parse("analyses=races").is_err());
+        assert!(PassConfig::parse("verbose").is_err());
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_pipeline_elements_share_one_catalog() -> Result<(), anyhow::Error> {
+
+        let path = std::env::temp_dir().join("pipeline_catalog_test.toml");
+        std::fs::write(&path, "")?;
+        let with_catalog = PassConfig::parse(&format!("catalog={}", path.display()))?;
+        assert_eq!(with_catalog.catalogs, vec![path.clone()]);
+
+        let mut catalog = PipelineCatalog::new();
+        catalog.set(&with_catalog)?;
+        catalog.set(&PassConfig::parse(&format!("catalog={};strict", path.display()))?)?;
+        assert!(catalog.set(&PassConfig::new()).is_err());
+
+        // the analysis runs what any element selects
+        assert_eq!(catalog.config().analyses, Analyses::default());
+        catalog.set(&PassConfig::parse(&format!("catalog={};analyses=lock-order", path.display()))?)?;
+        assert_eq!(catalog.config().analyses, Analyses { reentrant : true, lock_order : true });
+
+        let mut catalog = PipelineCatalog::new();
+        catalog.set(&PassConfig::new())?;
+        assert!(catalog.set(&with_catalog).is_err());
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+
+    }
+
+}
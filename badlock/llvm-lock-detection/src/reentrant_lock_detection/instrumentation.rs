@@ -0,0 +1,401 @@
+use std::collections::{BTreeMap, HashMap};
+use llvm_plugin::inkwell::AddressSpace;
+use llvm_plugin::inkwell::attributes::{Attribute, AttributeLoc};
+use llvm_plugin::inkwell::builder::Builder;
+use llvm_plugin::inkwell::module::Module;
+use llvm_plugin::inkwell::types::{AsTypeRef, FunctionType, PointerType};
+use llvm_plugin::inkwell::values::{AsValueRef, FunctionValue, InstructionOpcode, InstructionValue};
+use llvm_plugin::inkwell::llvm_sys::core::{
+    LLVMBuildCall2, LLVMBuildPointerCast, LLVMConstInt, LLVMConstPointerNull, LLVMGetTypeKind,
+    LLVMSetCurrentDebugLocation2, LLVMTypeOf,
+};
+use llvm_plugin::inkwell::llvm_sys::debuginfo::LLVMInstructionGetDebugLoc;
+use llvm_plugin::inkwell::llvm_sys::LLVMTypeKind;
+use llvm_plugin::inkwell::llvm_sys::prelude::{LLVMTypeRef, LLVMValueRef};
+use llvm_plugin::utils::InstructionIterator;
+use llvm_plugin::{LlvmModulePass, ModuleAnalysisManager, PreservedAnalyses};
+use crate::util::llvm_ir::{Cfg, RawValue, TryLocks};
+use super::config::PassConfig;
+use super::lock_analysis::{Deadlock, LockAnalysis, LockAnalysisResult};
+use super::pass::Symbol;
+
+// the functions of `badlock-runtime` the probes call
+pub const ACQUIRED : &str = "badlock_acquired";
+pub const RELEASED : &str = "badlock_released";
+pub const CHECK : &str = "badlock_check";
+
+/// A call into the runtime inserted before an instruction, in the order they are inserted.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Probe {
+    // the instruction releases a lock of the resource
+    Released(u64),
+    // the instruction locks the resource again while a finding says it may still be held
+    Check(u64, String),
+    // the instruction acquires a lock of the resource, at the site described
+    Acquired(u64, String)
+}
+
+/// The calls into the runtime for the lock site `site`, inserted before `at`: the site itself or,
+/// for the acquisition of a try-lock, the start of the block it continues in once it succeeded.
+#[derive(Debug, Clone)]
+pub struct Probes<'ctx> {
+    pub site : InstructionValue<'ctx>,
+    pub at : InstructionValue<'ctx>,
+    pub probes : Vec<Probe>
+}
+
+/// Inserts calls into `badlock-runtime` so that a thread about to block on a lock it holds at a
+/// reported deadlock panics instead. Resources are tracked from every site acquiring or releasing
+/// them, and identified by a hash of their symbol, so that modules instrumented apart agree.
+#[derive(Debug, Clone, Default)]
+pub struct LockInstrumentationPass {
+    // the filters select the findings that are instrumented
+    pub config : PassConfig
+}
+
+impl LockInstrumentationPass {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(config : PassConfig) -> Self {
+        Self { config }
+    }
+
+    /// Builds the pass for `reentrant-lock-instrumentation` or `reentrant-lock-instrumentation<PARAMETERS>`,
+    /// see `PassConfig::parse`. Returns `None` for any other pass name.
+    pub fn from_pipeline_name(name : &str) -> Option<Result<Self, anyhow::Error>> {
+        PassConfig::from_pipeline_name(name, "reentrant-lock-instrumentation")
+        .map(|config| config.map(Self::with_config))
+    }
+
+    /// The probes for the deadlocks the config reports, per lock site of `module`. A try-lock only
+    /// holds the lock once it got it, so its acquisition is probed at the start of the block it then
+    /// continues in, and not at all when that block cannot be told apart.
+    pub fn probes<'ctx>(&self, module : &Module<'ctx>, result : &LockAnalysisResult) -> Vec<Probes<'ctx>> {
+
+        let deadlocks : Vec<&Deadlock> = result.reported_deadlocks(&self.config).collect();
+        let resources : BTreeMap<&Symbol, u64> = deadlocks.iter()
+        .map(|deadlock| (&deadlock.1, Self::resource_id(&deadlock.1)))
+        .collect();
+
+        let acquisitions = |symbol : &Symbol| -> Vec<Probe> {
+            result.acquisitions.get(symbol).into_iter().flatten()
+            .filter_map(|var| resources.get(var).map(|resource| Probe::Acquired(*resource, result.display(symbol))))
+            .collect()
+        };
+
+        let mut probes = Vec::new();
+
+        for function in module.get_functions() {
+
+            let cfg = Cfg::from_function(function);
+
+            // the acquisitions of try-locks, by the instruction they are inserted before
+            let mut succeeded : HashMap<InstructionValue, Vec<Probes>> = HashMap::new();
+            for basic_block in function.get_basic_blocks() {
+                for inst in InstructionIterator::new(&basic_block) {
+
+                    let symbol = result.instruction(&inst);
+                    let acquired = acquisitions(&symbol);
+                    if !result.try_locks.contains(&symbol) || acquired.is_empty() {
+                        continue;
+                    }
+
+                    let at = TryLocks::success_block(&inst, &cfg)
+                    .and_then(|success| success.get_first_instruction())
+                    .map(|first| {
+                        let mut at = first;
+                        while at.get_opcode() == InstructionOpcode::Phi {
+                            at = at.get_next_instruction().unwrap_or(at);
+                        }
+                        at
+                    });
+
+                    if let Some(at) = at {
+                        succeeded.entry(at).or_default().push(Probes { site : inst, at, probes : acquired });
+                    }
+
+                }
+            }
+
+            for basic_block in function.get_basic_blocks() {
+                for inst in InstructionIterator::new(&basic_block) {
+
+                    // held before anything the block does
+                    probes.extend(succeeded.remove(&inst).unwrap_or_default());
+
+                    let symbol = result.instruction(&inst);
+                    let mut at = Vec::new();
+
+                    for var in result.releases.get(&symbol).into_iter().flatten() {
+                        at.extend(resources.get(var).map(|resource| Probe::Released(*resource)));
+                    }
+
+                    for deadlock in deadlocks.iter().filter(|deadlock| deadlock.2 == symbol) {
+                        at.push(Probe::Check(
+                            resources[&deadlock.1],
+                            format!("{} is locked again at {}", result.display(&deadlock.1), result.display(&symbol))
+                        ));
+                    }
+
+                    if !result.try_locks.contains(&symbol) {
+                        at.extend(acquisitions(&symbol));
+                    }
+
+                    if !at.is_empty() {
+                        at.sort();
+                        at.dedup();
+                        probes.push(Probes { site : inst, at : inst, probes : at });
+                    }
+
+                }
+            }
+
+        }
+
+        probes
+
+    }
+
+    /// Inserts the probes into `module`, which `result` was computed for. Returns the number of checks.
+    pub fn instrument(&self, module : &Module, result : &LockAnalysisResult) -> usize {
+
+        let probes = self.probes(module, result);
+        if probes.is_empty() {
+            return 0;
+        }
+
+        let context = module.get_context();
+        let builder = context.create_builder();
+        let resource_type = context.i64_type();
+        let pointer_type = context.i8_type().ptr_type(AddressSpace::default());
+
+        let site_type = context.void_type().fn_type(&[resource_type.into(), pointer_type.into(), pointer_type.into()], false);
+        let acquired = Self::declare(module, ACQUIRED, site_type, false);
+        let released = Self::declare(module, RELEASED, context.void_type().fn_type(&[resource_type.into()], false), false);
+        let check = Self::declare(module, CHECK, site_type, true);
+
+        // one constant per text, whichever function it was first built in
+        let mut strings : HashMap<String, LLVMValueRef> = HashMap::new();
+        let mut checks = 0;
+
+        for Probes { site, at, probes } in probes {
+
+            builder.position_before(&at);
+            // the calls are attributed to the source line of the lock
+            unsafe { LLVMSetCurrentDebugLocation2(builder.as_mut_ptr(), LLVMInstructionGetDebugLoc(site.as_value_ref())) };
+
+            let lock = Self::lock(&builder, pointer_type, result.lock_operands.get(&result.instruction(&site)));
+            let mut string = |text : String| *strings.entry(text).or_insert_with_key(|text| {
+                builder.build_global_string_ptr(text, "badlock.site").as_pointer_value().as_value_ref()
+            });
+
+            for probe in probes {
+                let (function, mut arguments) = match probe {
+                    Probe::Released(resource) => (released, vec![Self::resource(resource_type.as_type_ref(), resource)]),
+                    Probe::Check(resource, message) => {
+                        checks += 1;
+                        (check, vec![Self::resource(resource_type.as_type_ref(), resource), lock, string(message)])
+                    },
+                    Probe::Acquired(resource, site) => (acquired, vec![Self::resource(resource_type.as_type_ref(), resource), lock, string(site)])
+                };
+                Self::call(&builder, function, &mut arguments);
+            }
+
+        }
+
+        checks
+
+    }
+
+    /// Declares a function of the runtime. The calls are plain calls even where the lock is taken
+    /// by an `invoke`, so only the check, which panics on a hit, may unwind: past the frame's
+    /// landing pads, whose cleanups do not run.
+    fn declare<'ctx>(module : &Module<'ctx>, name : &str, function_type : FunctionType<'ctx>, unwinds : bool) -> FunctionValue<'ctx> {
+
+        let function = module.get_function(name).unwrap_or_else(|| module.add_function(name, function_type, None));
+
+        if !unwinds {
+            let nounwind = Attribute::get_named_enum_kind_id("nounwind");
+            function.add_attribute(AttributeLoc::Function, module.get_context().create_enum_attribute(nounwind, 0));
+        }
+
+        function
+
+    }
+
+    /// The FNV-1a hash of the resource's symbol, which names the same resource alike in every module.
+    pub fn resource_id(resource : &Symbol) -> u64 {
+        resource.id().bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    fn resource(resource_type : LLVMTypeRef, resource : u64) -> LLVMValueRef {
+        unsafe { LLVMConstInt(resource_type, resource, 0) }
+    }
+
+    /// The lock the instruction is handed, or null when it is not handed one directly.
+    fn lock(builder : &Builder, pointer_type : PointerType, lock : Option<&RawValue>) -> LLVMValueRef {
+
+        unsafe {
+            match lock {
+                Some(lock) if LLVMGetTypeKind(LLVMTypeOf(lock.0)) == LLVMTypeKind::LLVMPointerTypeKind => {
+                    LLVMBuildPointerCast(builder.as_mut_ptr(), lock.0, pointer_type.as_type_ref(), b"\0".as_ptr() as *const _)
+                },
+                _ => LLVMConstPointerNull(pointer_type.as_type_ref())
+            }
+        }
+
+    }
+
+    fn call(builder : &Builder, function : FunctionValue, arguments : &mut [LLVMValueRef]) {
+
+        unsafe {
+            LLVMBuildCall2(
+                builder.as_mut_ptr(),
+                function.get_type().as_type_ref(),
+                function.as_value_ref(),
+                arguments.as_mut_ptr(),
+                arguments.len() as u32,
+                b"\0".as_ptr() as *const _
+            );
+        }
+
+    }
+
+}
+
+impl LlvmModulePass for LockInstrumentationPass {
+    fn run_pass(
+        &self,
+        module: &mut Module,
+        manager: &ModuleAnalysisManager
+    ) -> PreservedAnalyses {
+
+        let result = manager.get_result::<LockAnalysis>(module);
+
+        if self.instrument(module, result) == 0 {
+            return PreservedAnalyses::All;
+        }
+
+        PreservedAnalyses::None
+
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    pub fn test_instruments_second_lock_sites() -> Result<(), anyhow::Error> {
+
+        let context = llvm_plugin::inkwell::context::Context::create();
+        let module = crate::analysis::test::parse(&context, "double_lock", crate::analysis::test::DOUBLE_LOCK)?;
+        let result = crate::reentrant_lock_detection::pass::ReentrantLockPass::new().analyze_locks(&module);
+        let pass = LockInstrumentationPass::new();
+
+        // the resource is named by its symbol rather than numbered, so a module instrumented
+        // on its own gives it the same id
+        let resource = LockInstrumentationPass::resource_id(&result.deadlocks[0].1);
+        let again = crate::analysis::test::parse(&context, "double_lock_again", crate::analysis::test::DOUBLE_LOCK)?;
+        let again = crate::reentrant_lock_detection::pass::ReentrantLockPass::new().analyze_locks(&again);
+        assert_eq!(LockInstrumentationPass::resource_id(&again.deadlocks[0].1), resource);
+        assert_ne!(LockInstrumentationPass::resource_id(&result.deadlocks[0].0), resource);
+
+        // both locks are recorded, the second is checked first
+        let probes = pass.probes(&module, &result);
+        assert_eq!(probes.len(), 2);
+        assert!(matches!(probes[0].probes.as_slice(), [Probe::Acquired(id, _)] if *id == resource));
+        assert!(matches!(
+            probes[1].probes.as_slice(),
+            [Probe::Check(id, message), Probe::Acquired(_, _)] if *id == resource && message.contains("%m")
+        ));
+
+        assert_eq!(pass.instrument(&module, &result), 1);
+        module.verify().map_err(|error| anyhow::anyhow!("{}", error))?;
+
+        let ir = module.print_to_string().to_string();
+        assert_eq!(ir.matches(&format!("call void @badlock_acquired(i64 {}, ptr %m", resource as i64)).count(), 2);
+        assert_eq!(ir.matches(&format!("call void @badlock_check(i64 {}, ptr %m", resource as i64)).count(), 1);
+
+        // only the check unwinds, with the panic of a hit
+        let nounwind = Attribute::get_named_enum_kind_id("nounwind");
+        for name in [ACQUIRED, RELEASED] {
+            let function = module.get_function(name).unwrap();
+            assert!(function.get_enum_attribute(AttributeLoc::Function, nounwind).is_some());
+        }
+        assert!(module.get_function(CHECK).unwrap().get_enum_attribute(AttributeLoc::Function, nounwind).is_none());
+
+        // without findings nothing is inserted
+        let module = crate::analysis::test::parse(&context, "inversion", crate::analysis::test::INVERSION)?;
+        let result = crate::reentrant_lock_detection::pass::ReentrantLockPass::new().analyze_locks(&module);
+        assert_eq!(pass.instrument(&module, &result), 0);
+        assert!(module.get_function(CHECK).is_none());
+
+        let pass = LockInstrumentationPass::from_pipeline_name("reentrant-lock-instrumentation<exclude=^main$>");
+        assert!(pass.is_some_and(|pass| pass.is_ok_and(|pass| !pass.config.reports("main"))));
+        assert!(LockInstrumentationPass::from_pipeline_name("reentrant-lock-detection").is_none());
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_probes_try_lock_acquisitions_on_success() -> Result<(), anyhow::Error> {
+
+        let context = llvm_plugin::inkwell::context::Context::create();
+        let pass = LockInstrumentationPass::new();
+
+        // the block each test enters once the try-lock got the lock
+        let branches = [
+            ("br i1 %ok, label %acquired, label %busy", "acquired"),
+            ("br i1 %ok, label %busy, label %acquired", "busy"),
+            ("switch i64 %tag, label %busy [ i64 0, label %acquired ]", "acquired"),
+            ("switch i64 %tag, label %acquired [ i64 0, label %busy ]", "busy")
+        ];
+
+        for (branch, success) in branches {
+
+            let ir = crate::analysis::test::TRY_LOCK_BRANCH.replace("br i1 %ok, label %acquired, label %busy", branch);
+            let module = crate::analysis::test::parse(&context, "try_lock_branch", &ir)?;
+            let result = crate::reentrant_lock_detection::pass::ReentrantLockPass::new().analyze_locks(&module);
+            let probes = pass.probes(&module, &result);
+            assert_eq!(probes.len(), 2);
+
+            // the try-lock is never probed itself, only the block entered once it got the lock
+            let tried = probes.iter().find(|probes| probes.site.to_string().contains("try_lock")).unwrap();
+            assert_eq!(tried.at.get_parent().unwrap().get_name().to_str()?, success, "{}", branch);
+            assert!(matches!(tried.probes.as_slice(), [Probe::Acquired(_, site)] if site.contains("%tried")));
+
+            let locked = probes.iter().find(|probes| probes.site.to_string().contains("4lock")).unwrap();
+            assert_eq!(locked.at, locked.site);
+            assert!(matches!(locked.probes.as_slice(), [Probe::Check(_, _), Probe::Acquired(_, _)]));
+
+            pass.instrument(&module, &result);
+            module.verify().map_err(|error| anyhow::anyhow!("{}", error))?;
+
+        }
+
+        // a block also entered on failure, or by whatever other tag a default takes, is not probed
+        let unknown = [
+            crate::analysis::test::TRY_LOCK_BRANCH.replace("busy:\n  ret void", "busy:\n  br label %acquired"),
+            crate::analysis::test::TRY_LOCK_BRANCH.replace(
+                "br i1 %ok, label %acquired, label %busy",
+                "switch i64 %tag, label %acquired [ i64 1, label %busy ]"
+            )
+        ];
+
+        for ir in unknown {
+            let module = crate::analysis::test::parse(&context, "try_lock_unknown", &ir)?;
+            let result = crate::reentrant_lock_detection::pass::ReentrantLockPass::new().analyze_locks(&module);
+            assert!(pass.probes(&module, &result).iter().all(|probes| probes.at == probes.site));
+        }
+
+        Ok(())
+
+    }
+
+}
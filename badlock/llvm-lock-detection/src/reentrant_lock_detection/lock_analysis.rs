@@ -0,0 +1,189 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+use llvm_plugin::inkwell::module::Module;
+use llvm_plugin::inkwell::values::InstructionValue;
+use llvm_plugin::{AnalysisKey, LlvmModuleAnalysis, ModuleAnalysisManager};
+use lock_detection::report::{Finding, Inversion, Report};
+use crate::util::llvm_ir::{RawValue, ValueIds};
+use super::config::{PassConfig, PipelineCatalog};
+use super::pass::{ReentrantLockPass, Symbol};
+
+/// `Deadlock(first lock, resource, second lock)`.
+pub type Deadlock = (Symbol, Symbol, Symbol);
+
+/// `Inversion(first held, first resource, first acquired, second held, second resource, second acquired)`.
+pub type LockOrderInversion = (Symbol, Symbol, Symbol, Symbol, Symbol, Symbol);
+
+/// Everything the lock analysis found in a module, for passes to query through the
+/// `ModuleAnalysisManager`. Findings are kept by symbol and unfiltered, `report` renders them.
+/// `inversions` and `held` are only computed when the lock order analysis runs.
+#[derive(Debug, Clone, Default)]
+pub struct LockAnalysisResult {
+    // sorted, so that reports over the same module can be diffed
+    pub deadlocks : Vec<Deadlock>,
+    pub inversions : Vec<LockOrderInversion>,
+    // per instruction, the resources held when it runs and the instructions that acquired them
+    pub held : HashMap<Symbol, BTreeMap<Symbol, BTreeSet<Symbol>>>,
+    // per instruction, the resources it acquires and those it releases
+    pub acquisitions : HashMap<Symbol, BTreeSet<Symbol>>,
+    pub releases : HashMap<Symbol, BTreeSet<Symbol>>,
+    // per call to a lock API acquiring the lock, the lock it is handed, unless it is handed a guard
+    pub lock_operands : HashMap<Symbol, RawValue>,
+    // the calls to try-locks, which only hold the lock if they got it
+    pub try_locks : HashSet<Symbol>,
+    // human readable text for symbols
+    pub symbol_display : HashMap<Symbol, String>,
+    // per function or global name, the crate defining it when several crates were linked together
+    pub crates : HashMap<String, String>,
+    value_ids : ValueIds
+}
+
+impl LockAnalysisResult {
+
+    pub fn new(value_ids : ValueIds) -> Self {
+        Self {
+            value_ids,
+            ..Self::default()
+        }
+    }
+
+    /// The symbol of an instruction of the analysed module.
+    pub fn instruction(&self, inst : &InstructionValue) -> Symbol {
+        Symbol::Instruction(self.value_ids.instruction_id(inst))
+    }
+
+    /// The resources held when `inst` runs.
+    pub fn held_at(&self, inst : &InstructionValue) -> BTreeSet<&Symbol> {
+        self.held.get(&self.instruction(inst))
+        .map(|held| held.keys().collect())
+        .unwrap_or_default()
+    }
+
+    /// The findings whose second lock is `inst`, i.e. where a thread may block on a lock it holds.
+    pub fn deadlocks_at(&self, inst : &InstructionValue) -> Vec<&Deadlock> {
+        let symbol = self.instruction(inst);
+        self.deadlocks.iter().filter(|deadlock| deadlock.2 == symbol).collect()
+    }
+
+    pub fn display(&self, symbol : &Symbol) -> String {
+
+        match self.symbol_display.get(symbol) {
+            Some(text) => format!("{} `{}`", symbol.id(), text),
+            None => symbol.id()
+        }
+
+    }
+
+    pub fn crate_of(&self, symbol : &Symbol) -> Option<String> {
+
+        let owner = match symbol {
+            Symbol::Instruction(id) => Some(id.function.as_str()),
+            Symbol::Value(id) | Symbol::Object(id) | Symbol::Location(id, _) => id.owner()
+        };
+
+        self.crates.get(owner?).cloned()
+
+    }
+
+    pub fn finding(&self, deadlock : &Deadlock) -> Finding {
+        Finding {
+            first_lock : self.display(&deadlock.0),
+            resource : self.display(&deadlock.1),
+            second_lock : self.display(&deadlock.2),
+            first_lock_crate : self.crate_of(&deadlock.0),
+            resource_crate : self.crate_of(&deadlock.1),
+            second_lock_crate : self.crate_of(&deadlock.2)
+        }
+    }
+
+    pub fn inversion(&self, inversion : &LockOrderInversion) -> Inversion {
+        Inversion {
+            first_held : self.display(&inversion.0),
+            first_resource : self.display(&inversion.1),
+            first_acquired : self.display(&inversion.2),
+            second_held : self.display(&inversion.3),
+            second_resource : self.display(&inversion.4),
+            second_acquired : self.display(&inversion.5)
+        }
+    }
+
+    /// The findings of the analyses `config` selects that its filters let through.
+    pub fn report(&self, config : &PassConfig) -> Report {
+
+        let mut report = Report::default();
+
+        if config.analyses.reentrant {
+            report.findings = self.reported_deadlocks(config)
+            .map(|deadlock| self.finding(deadlock))
+            .collect();
+        }
+
+        if config.analyses.lock_order {
+            report.inversions = self.inversions.iter()
+            .filter(|inversion| Self::reports(config, &[&inversion.0, &inversion.2, &inversion.3, &inversion.5]))
+            .map(|inversion| self.inversion(inversion))
+            .collect();
+        }
+
+        report
+
+    }
+
+    /// The deadlocks the filters of the config let through.
+    pub fn reported_deadlocks<'a>(&'a self, config : &'a PassConfig) -> impl Iterator<Item = &'a Deadlock> {
+        self.deadlocks.iter().filter(|deadlock| Self::reports(config, &[&deadlock.0, &deadlock.2]))
+    }
+
+    /// Whether the filters of the config let through a finding with these lock sites: one of them
+    /// has to be included, and none of them excluded.
+    fn reports(config : &PassConfig, sites : &[&Symbol]) -> bool {
+
+        if config.include.is_empty() && config.exclude.is_empty() {
+            return true;
+        }
+
+        let functions : Vec<String> = sites.iter().filter_map(|site| match site {
+            Symbol::Instruction(id) => Some(format!("{:#}", rustc_demangle::demangle(&id.function))),
+            _ => None
+        })
+        .collect();
+
+        functions.iter().any(|function| config.includes(function))
+        && !functions.iter().any(|function| config.excludes(function))
+
+    }
+
+}
+
+/// The lock analysis as an LLVM module analysis, so passes of the plugin can query its results
+/// with `manager.get_result::<LockAnalysis>(module)`.
+#[derive(Debug, Clone, Default)]
+pub struct LockAnalysis {
+    // shared with the pipeline parsing callback, as `opt` registers analyses before it parses
+    // the pipeline whose `catalog` and `analyses` parameters configure it
+    pub catalog : Rc<RefCell<PipelineCatalog>>
+}
+
+impl LockAnalysis {
+
+    pub fn new(catalog : Rc<RefCell<PipelineCatalog>>) -> Self {
+        Self { catalog }
+    }
+
+}
+
+impl LlvmModuleAnalysis for LockAnalysis {
+
+    type Result = LockAnalysisResult;
+
+    fn run_analysis(&self, module : &Module<'_>, _manager : &ModuleAnalysisManager) -> Self::Result {
+        ReentrantLockPass::with_config(self.catalog.borrow().config()).analyze_locks(module)
+    }
+
+    fn id() -> AnalysisKey {
+        static ID : u8 = 0;
+        &ID
+    }
+
+}
@@ -1 +1,4 @@
-pub mod pass;
\ No newline at end of file
+pub mod config;
+pub mod instrumentation;
+pub mod lock_analysis;
+pub mod pass;
@@ -1,86 +1,233 @@
-use llvm_plugin::inkwell::basic_block::{self, BasicBlock};
+use llvm_plugin::inkwell::basic_block::BasicBlock;
 use llvm_plugin::inkwell::module::Module;
 use llvm_plugin::{
     LlvmModulePass, ModuleAnalysisManager, PassBuilder, PipelineParsing, PreservedAnalyses,
 };
-use lock_detection::reentrant_lock_detection::{UseVar, Next};
+use lock_detection::reentrant_lock_detection::{UseVar, Next, Call};
 use lock_detection::reentrant_lock_detection::{
     self, 
     facts::Extendable,
     Lock, Release, Def
 };
-use std::borrow::BorrowMut;
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
-use llvm_plugin::inkwell::values::{InstructionValue, InstructionOpcode, AsValueRef, BasicValue};
+use std::rc::Rc;
+use std::io::Write;
+use lock_detection::points_to::{self, PointsToPosts};
+use lock_detection::lock_order;
+use lock_detection::report::{Report, Severity, END_ANALYSIS};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use llvm_plugin::inkwell::values::{InstructionValue, InstructionOpcode, AsValueRef, FunctionValue};
+use llvm_plugin::inkwell::llvm_sys::core::{
+    LLVMGetConstOpcode, LLVMGetNumOperands, LLVMGetOperand, LLVMGetTypeKind, LLVMIsAAllocaInst,
+    LLVMIsAArgument, LLVMIsAConstantExpr, LLVMIsAGlobalVariable, LLVMIsALoadInst, LLVMTypeOf,
+};
+use llvm_plugin::inkwell::llvm_sys::{LLVMOpcode, LLVMTypeKind};
+use llvm_plugin::inkwell::llvm_sys::prelude::LLVMValueRef;
 use llvm_plugin::utils::InstructionIterator;
 use crate::util::llvm_ir::{
-    KnownCallsites, InstructionCallSites, Predecessors, KnowAlloca, Var
+    InstructionCallSites, Callee, Predecessors, Cfg, DropFlags, Var, InstructionId, ValueId, ValueIds, RawValue, GetElementPtr
 };
+use crate::util::debug_info::{SourceVariable, SourceVariables};
+use crate::util::diagnostics;
+use crate::catalog::{Catalog, LockApi, Operand, ReturnValue, Role};
+use super::config::{OutputFormat, PassConfig, PipelineCatalog};
+use super::instrumentation::LockInstrumentationPass;
+use super::lock_analysis::{LockAnalysis, LockAnalysisResult};
 
 #[llvm_plugin::plugin(name = "reentrant_lock_detection", version = "0.1")]
 fn plugin_registrar(builder: &mut PassBuilder) {
 
-    builder.add_module_pipeline_parsing_callback(|name, manager| {
-        if name == "reentrant-lock-detection" {
+    // the analysis is registered before the pipeline is parsed, so it reads the catalog once it runs
+    let catalog = Rc::new(RefCell::new(PipelineCatalog::new()));
 
-            manager.add_pass(ReentrantLockPass::default());
+    let analysis_catalog = catalog.clone();
+    builder.add_module_analysis_registration_callback(move |manager| {
+        manager.register_pass(LockAnalysis::new(analysis_catalog.clone()));
+    });
 
-        
-            PipelineParsing::Parsed
-        } else {
-       
-            PipelineParsing::NotParsed
+    builder.add_module_pipeline_parsing_callback(move |name, manager| {
+        match ReentrantLockPass::from_pipeline_name(name) {
+            Some(Ok(pass)) => {
+
+                if let Err(error) = catalog.borrow_mut().set(&pass.config) {
+                    eprintln!("reentrant-lock-detection: {}", error);
+                    return PipelineParsing::NotParsed;
+                }
+                manager.add_pass(pass);
+
+                PipelineParsing::Parsed
+            },
+            Some(Err(error)) => {
+                eprintln!("reentrant-lock-detection: {}", error);
+                PipelineParsing::NotParsed
+            },
+            None => match LockInstrumentationPass::from_pipeline_name(name) {
+                Some(Ok(pass)) => {
+
+                    if let Err(error) = catalog.borrow_mut().set(&pass.config) {
+                        eprintln!("reentrant-lock-instrumentation: {}", error);
+                        return PipelineParsing::NotParsed;
+                    }
+                    manager.add_pass(pass);
+
+                    PipelineParsing::Parsed
+                },
+                Some(Err(error)) => {
+                    eprintln!("reentrant-lock-instrumentation: {}", error);
+                    PipelineParsing::NotParsed
+                },
+                None => PipelineParsing::NotParsed
+            }
         }
     });
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub struct Symbol(pub String);
+/// Keys of the Datalog programs. They are derived from positions in the module rather than from
+/// LLVM's in-memory addresses, so two runs over the same module produce the same symbols.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Symbol {
+    Instruction(InstructionId),
+    Value(ValueId),
+    // memory the points-to program tracks, e.g. the slot an alloca reserves
+    Object(ValueId),
+    // a byte offset into an object, which is what a lock resource is
+    Location(ValueId, i64)
+}
 
-#[derive(Debug, Clone)]
-pub struct MayAliasTable(HashMap<Symbol, Symbol>);
+impl Symbol {
+
+    /// Where the symbol is in the module, e.g. `main bb1 #2` or `@STATE +8`.
+    pub fn id(&self) -> String {
+        match self {
+            Symbol::Instruction(id) => id.to_string(),
+            Symbol::Value(id) => id.to_string(),
+            Symbol::Object(id) | Symbol::Location(id, 0) => id.to_string(),
+            Symbol::Location(id, offset) => format!("{} +{}", id, offset)
+        }
+    }
+
+}
+
+/// The results of the points-to program, indexed by pointer.
+#[derive(Debug, Clone, Default)]
+pub struct PointsToTable {
+    points_to : HashMap<Symbol, BTreeSet<(Symbol, i64)>>,
+    heap_points_to : BTreeMap<(Symbol, i64), BTreeSet<(Symbol, i64)>>
+}
+
+impl PointsToTable {
 
-impl MayAliasTable {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self::default()
+    }
+
+    pub fn from_posts(posts : &PointsToPosts<&Symbol>) -> Self {
+
+        let mut table = Self::new();
+
+        for points_to in posts.points_to.iter() {
+            table.points_to.entry(points_to.0.clone()).or_default()
+            .insert((points_to.1.clone(), points_to.2));
+        }
+
+        for heap_points_to in posts.heap_points_to.iter() {
+            table.heap_points_to.entry((heap_points_to.0.clone(), heap_points_to.1)).or_default()
+            .insert((heap_points_to.2.clone(), heap_points_to.3));
+        }
+
+        table
+
     }
 
-    pub fn insert(&mut self, key: Symbol, value: Symbol) {
-        self.0.insert(key, value);
+    /// The objects, and offsets into them, `pointer` may point to.
+    pub fn pointees(&self, pointer : &Symbol) -> BTreeSet<(Symbol, i64)> {
+        self.points_to.get(pointer).cloned().unwrap_or_default()
     }
 
-    pub fn get(&self, key: &Symbol) -> Option<&Symbol> {
-        self.0.get(key)
+    /// What a pointer loaded through `pointer` may point to.
+    pub fn contents(&self, pointer : &Symbol) -> BTreeSet<(Symbol, i64)> {
+
+        self.pointees(pointer).iter()
+        .filter_map(|pointee| self.heap_points_to.get(pointee))
+        .flat_map(|objects| objects.iter().cloned())
+        .collect()
+
     }
 
-    pub fn get_root_symbol(&self, key: &Symbol) -> Symbol {
-        let mut current = key;
-        while let Some(next) = self.get(current) {
-            if next == current {
-                break;
-            }
-            current = next;
-        }
-        current.clone()
+    /// What any pointer stored from where `pointer` points onwards may point to, e.g. the locks
+    /// held by all the guards in a struct or `Option` that is dropped through `pointer`.
+    pub fn owned_contents(&self, pointer : &Symbol) -> BTreeSet<(Symbol, i64)> {
+
+        self.pointees(pointer).iter()
+        .flat_map(|(object, offset)| self.heap_points_to.range((object.clone(), *offset)..=(object.clone(), i64::MAX)))
+        .flat_map(|(_, objects)| objects.iter().cloned())
+        .collect()
+
     }
 
 }
 
 
 #[derive(Debug, Clone)]
-struct ReentrantLockPass {
+pub struct ReentrantLockPass {
     // interior mutability on reentrant lock detection Program
     pub program: RefCell<reentrant_lock_detection::Program<Symbol>>,
 
-    pub may_alias_table: RefCell<MayAliasTable>,
+    // which objects lock operands and guards refer to
+    pub points_to: RefCell<points_to::Program<Symbol>>,
+
+    pub points_to_table: RefCell<PointsToTable>,
+
+    // source-level names of the values lock resources are derived from
+    pub source_variables: RefCell<SourceVariables>,
+
+    // the source variables objects are named after, whose fields name the locations in them
+    pub object_variables: RefCell<HashMap<Symbol, SourceVariable>>,
+
+    pub value_ids: RefCell<ValueIds>,
+
+    // human readable text for symbols, only used when reporting
+    pub symbol_display: RefCell<HashMap<Symbol, String>>,
+
+    // per function, the locations of guards it was handed and releases
+    pub inherited_releases: RefCell<HashMap<LLVMValueRef, BTreeSet<Symbol>>>,
+
+    // per function, the locations of guards it returns
+    pub returned_acquisitions: RefCell<HashMap<LLVMValueRef, BTreeSet<Symbol>>>,
+
+    // per call to a lock API acquiring the lock, the lock it is handed
+    pub lock_operands: RefCell<HashMap<Symbol, RawValue>>,
+
+    // the calls to lock APIs that wait for the lock
+    pub blocking: RefCell<HashSet<Symbol>>,
+
+    // the calls to try-locks, which only hold the lock if they got it
+    pub try_locks: RefCell<HashSet<Symbol>>,
+
+    // the catalog, output and filters the pipeline name asked for
+    pub config: PassConfig,
+
+    // per function or global name, the crate defining it when several crates were linked together
+    pub crates: HashMap<String, String>,
 }
 
 impl Default for ReentrantLockPass {
     fn default() -> Self {
         Self {
             program: RefCell::new(reentrant_lock_detection::Program::new()),
-            may_alias_table: RefCell::new(MayAliasTable::new())
+            points_to: RefCell::new(points_to::Program::new()),
+            points_to_table: RefCell::new(PointsToTable::new()),
+            source_variables: RefCell::new(SourceVariables::new()),
+            object_variables: RefCell::new(HashMap::new()),
+            value_ids: RefCell::new(ValueIds::new()),
+            symbol_display: RefCell::new(HashMap::new()),
+            inherited_releases: RefCell::new(HashMap::new()),
+            returned_acquisitions: RefCell::new(HashMap::new()),
+            lock_operands: RefCell::new(HashMap::new()),
+            blocking: RefCell::new(HashSet::new()),
+            try_locks: RefCell::new(HashSet::new()),
+            config: PassConfig::default(),
+            crates: HashMap::new()
         }
     }
 }
@@ -90,316 +237,1055 @@ impl ReentrantLockPass {
         Self::default()
     }
 
-    pub fn handle_alias_instructions(&self, inst : InstructionValue) {
-        match inst.get_opcode() {
-            InstructionOpcode::Call | InstructionOpcode::Invoke => {
-                match KnownCallsites::from_instr(&inst) {
-                    KnownCallsites::StdSyncMutexLock => {
-                        self.handle_std_sync_mutex_lock_alias(inst);
-                    },
-                    _ => {
+    pub fn with_catalog(catalog : Catalog) -> Self {
+        Self::with_config(PassConfig {
+            catalog,
+            ..PassConfig::default()
+        })
+    }
 
-                    }
+    pub fn with_config(config : PassConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Names the crate of each reported site after the crate defining its function or global.
+    pub fn with_crates(self, crates : HashMap<String, String>) -> Self {
+        Self {
+            crates,
+            ..self
+        }
+    }
+
+    /// Builds the pass for `reentrant-lock-detection` or `reentrant-lock-detection<PARAMETERS>`,
+    /// see `PassConfig::parse`. Returns `None` for any other pass name.
+    pub fn from_pipeline_name(name : &str) -> Option<Result<Self, anyhow::Error>> {
+        PassConfig::from_pipeline_name(name, "reentrant-lock-detection")
+        .map(|config| config.map(Self::with_config))
+    }
+
+    /// Runs the analysis over `module` and returns the findings the config selects.
+    pub fn analyze(&self, module : &Module) -> Report {
+        self.analyze_locks(module).report(&self.config)
+    }
+
+    /// Runs the analyses the config selects over `module`, which is what `LockAnalysis` computes
+    /// for other passes.
+    pub fn analyze_locks(&self, module : &Module) -> LockAnalysisResult {
+
+        // the pass may be run over several modules, each starts from empty facts
+        *self.program.borrow_mut() = reentrant_lock_detection::Program::new();
+        *self.points_to.borrow_mut() = points_to::Program::new();
+        self.object_variables.borrow_mut().clear();
+        self.symbol_display.borrow_mut().clear();
+        self.inherited_releases.borrow_mut().clear();
+        self.returned_acquisitions.borrow_mut().clear();
+        self.lock_operands.borrow_mut().clear();
+        self.blocking.borrow_mut().clear();
+        self.try_locks.borrow_mut().clear();
+
+        *self.source_variables.borrow_mut() = SourceVariables::from_module(module);
+        *self.value_ids.borrow_mut() = ValueIds::from_module(module);
+
+        self.handle_points_to_globals(module);
+
+        for function in module.get_functions() {
+
+            for basic_block in function.get_basic_blocks() {
+                for instr in InstructionIterator::new(&basic_block) {
+                    self.handle_points_to_instruction(instr);
                 }
+            }
+        }
+
+        // lock operands and guards are resolved against these while emitting the reentrant facts
+        *self.points_to_table.borrow_mut() = PointsToTable::from_posts(&self.points_to.borrow().compute());
+    
+        
+        for function in module.get_functions() {
+
+            // built once per function, predecessors are then plain lookups
+            let cfg = Cfg::from_function(function);
+
+            for basic_block in function.get_basic_blocks() {
+                for instr in InstructionIterator::new(&basic_block) {
+                    self.handle_instruction(instr, &cfg);
+                }
+            }
+        }
+
+        let program = self.program.borrow();
+        let mut result = LockAnalysisResult::new(self.value_ids.borrow().clone());
+
+        let priors = program.priors();
+        for lock in priors.locks.iter() {
+            result.acquisitions.entry(lock.0.clone()).or_default().insert(lock.1.clone());
+        }
+        for release in priors.releases.iter() {
+            result.releases.entry(release.0.clone()).or_default().insert(release.1.clone());
+        }
+
+        // a lock held since an earlier site only deadlocks a call that waits for it
+        let blocking = self.blocking.borrow();
+
+        if self.config.analyses.reentrant {
+            let posts = program.compute();
+            result.deadlocks = posts.deadlock.into_iter()
+            .filter(|deadlock| blocking.contains(deadlock.2))
+            .map(|deadlock| (deadlock.0.clone(), deadlock.1.clone(), deadlock.2.clone()))
+            .collect();
+            result.deadlocks.sort();
+        }
+
+        if self.config.analyses.lock_order {
+
+            // the lock order program runs over the control flow, locks and releases of the reentrant facts
+            let mut lock_order = lock_order::Program::new();
+
+            lock_order.extend(priors.nexts.iter().map(|next| lock_order::Next(next.0.clone(), next.1.clone())));
+            lock_order.extend(priors.calls.iter().map(|call| lock_order::Call(call.0.clone(), call.1.clone())));
+            lock_order.extend(priors.locks.iter().map(|lock| lock_order::Lock(lock.0.clone(), lock.1.clone())));
+            lock_order.extend(priors.releases.iter().map(|release| lock_order::Release(release.0.clone(), release.1.clone())));
+
+            let posts = lock_order.compute();
+            result.inversions = posts.inversion.into_iter()
+            .filter(|inversion| blocking.contains(inversion.2) && blocking.contains(inversion.5))
+            // each pair is found once, in the order its symbols were mapped, reported with the
+            // lesser resource first so that runs agree
+            .map(|inversion| if inversion.1 <= inversion.4 {
+                (
+                    inversion.0.clone(), inversion.1.clone(), inversion.2.clone(),
+                    inversion.3.clone(), inversion.4.clone(), inversion.5.clone()
+                )
+            } else {
+                (
+                    inversion.3.clone(), inversion.4.clone(), inversion.5.clone(),
+                    inversion.0.clone(), inversion.1.clone(), inversion.2.clone()
+                )
+            })
+            .collect();
+            result.inversions.sort();
+            result.inversions.dedup();
+
+            for held in posts.held.iter() {
+                result.held.entry(held.0.clone()).or_default()
+                .entry(held.1.clone()).or_default()
+                .insert(held.2.clone());
+            }
+
+        }
+
+        result.symbol_display = self.symbol_display.borrow().clone();
+        result.lock_operands = self.lock_operands.borrow().clone();
+        result.try_locks = self.try_locks.borrow().clone();
+        result.crates = self.crates.clone();
+
+        result
+
+    }
+
+    /// Reports every finding through LLVM's diagnostic handler, warnings first as the default
+    /// handler exits on the first error. Returns the most severe diagnostic.
+    pub fn diagnose(&self, module : &Module, report : &Report) -> Option<Severity> {
+
+        let mut diagnostics : Vec<(Severity, String)> = report.findings.iter().map(|finding| (
+            self.config.promote(finding.severity()),
+            format!(
+                "{} is locked again at {} while still held since {}",
+                finding.resource, finding.second_lock, finding.first_lock
+            )
+        ))
+        .chain(report.inversions.iter().map(|inversion| (
+            self.config.promote(inversion.severity()),
+            format!(
+                "lock order inversion: {} is held at {} while {} is locked at {}, and {} is held at {} while {} is locked at {}",
+                inversion.first_resource, inversion.first_held, inversion.second_resource, inversion.first_acquired,
+                inversion.second_resource, inversion.second_held, inversion.first_resource, inversion.second_acquired
+            )
+        )))
+        .collect();
+
+        diagnostics.sort_by_key(|(severity, _)| *severity);
+        for (severity, message) in diagnostics.iter() {
+            diagnostics::diagnose(module, message, *severity);
+        }
+
+        diagnostics.last().map(|(severity, _)| *severity)
+
+    }
+
+    /// Writes the report where and how the config asks for.
+    pub fn write_report(&self, report : &Report) -> Result<(), anyhow::Error> {
+
+        let text = match self.config.format {
+            OutputFormat::Human => format!("{}{}\n", report, END_ANALYSIS),
+            OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(report)?)
+        };
+
+        match &self.config.out {
+            Some(path) => std::fs::write(path, text)
+            .map_err(|error| anyhow::anyhow!("Could not write report {}: {}", path.display(), error)),
+            None => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(text.as_bytes())?;
+                Ok(stdout.flush()?)
+            }
+        }
+
+    }
+
+    fn instruction_symbol(&self, inst : InstructionValue) -> Symbol {
+
+        let symbol = Symbol::Instruction(self.value_ids.borrow().instruction_id(&inst));
+        self.symbol_display.borrow_mut().entry(symbol.clone())
+        .or_insert_with(|| inst.to_string().trim().trim_matches('"').to_string());
+        symbol
+
+    }
+
+    fn value_symbol(&self, value : &impl AsValueRef) -> Symbol {
+
+        let symbol = Symbol::Value(self.value_ids.borrow().location_id(value));
+        self.symbol_display.borrow_mut().entry(symbol.clone())
+        .or_insert_with(|| Var::get_ir_name(value));
+        symbol
+
+    }
+
+    /// The object `value` is the address of. Objects are what lock resources are reported as,
+    /// so they are named after their source variable.
+    fn object_symbol(&self, value : &impl AsValueRef) -> Symbol {
+
+        let symbol = Symbol::Object(self.value_ids.borrow().location_id(value));
+        self.symbol_display.borrow_mut().entry(symbol.clone())
+        .or_insert_with(|| Var::get_resource_name(value, &self.source_variables.borrow()));
+        if let Some(variable) = self.source_variables.borrow().get(value) {
+            self.object_variables.borrow_mut().entry(symbol.clone()).or_insert_with(|| variable.clone());
+        }
+        symbol
+
+    }
+
+    /// The object whatever is stored at `value` points to, for pointers the analysis knows nothing about.
+    fn loaded_object_symbol(&self, value : &impl AsValueRef) -> Symbol {
+
+        let location = self.value_ids.borrow().location_id(value);
+        let symbol = Symbol::Object(ValueId::Loaded(Box::new(location)));
+        self.symbol_display.borrow_mut().entry(symbol.clone())
+        .or_insert_with(|| format!("*{}", Var::get_resource_name(value, &self.source_variables.borrow())));
+        symbol
+
+    }
+
+    /// The lock resource at `offset` bytes into `object`, named after the field there when debug
+    /// info lays out the object's variable, e.g. `state.b` for `&state.b` and `&(*(&state)).b` alike.
+    fn location_symbol(&self, object : &Symbol, offset : i64) -> Symbol {
+
+        let id = match object {
+            Symbol::Object(id) => id.clone(),
+            _ => unreachable!("only objects are pointed to")
+        };
+
+        let symbol = Symbol::Location(id, offset);
+        let mut symbol_display = self.symbol_display.borrow_mut();
+        if !symbol_display.contains_key(&symbol) {
+            let field = self.object_variables.borrow().get(object)
+            .and_then(|variable| variable.field(offset, &self.config.catalog.lock_types()));
+            let name = symbol_display.get(object).cloned().unwrap_or_else(|| object.id());
+            symbol_display.insert(symbol.clone(), match (field, offset) {
+                (Some(field), _) => field.to_string(),
+                (None, 0) => name,
+                (None, _) => format!("{} +{}", name, offset)
+            });
+        }
+        symbol
+
+    }
+
+    /// The catalog entry for a direct call, if the callee is a known lock API.
+    fn classify(&self, inst : &InstructionValue) -> Option<LockApi> {
+
+        match InstructionCallSites::callee(inst) {
+            Ok(Callee::Direct(_)) => {
+                let path = InstructionCallSites::demangled_called_path(inst).ok()?;
+                self.config.catalog.classify(&path).cloned()
             },
-            InstructionOpcode::Alloca => {
-                self.handle_alloca_inst(inst);
-            },
-            _ => {
+            // intrinsics, indirect calls and inline asm never resolve to a known lock API
+            _ => None
+        }
+
+    }
+
+    /// Resolves a catalog operand against a call site.
+    fn operand(inst : &InstructionValue, operand : Option<Operand>) -> Option<RawValue> {
+
+        match operand? {
+            Operand::Argument(index) => InstructionCallSites::source_arguments(inst).get(index as usize).copied(),
+            Operand::Return(_) => Some(InstructionCallSites::result(inst))
+        }
+
+    }
+
+    fn operands(inst : &impl AsValueRef) -> Vec<RawValue> {
+
+        let inst_ref = inst.as_value_ref();
+        let count = unsafe { LLVMGetNumOperands(inst_ref) } as u32;
+        (0..count).map(|index| RawValue(unsafe { LLVMGetOperand(inst_ref, index) })).collect()
+
+    }
+
+    /// Whether `value` is the address of an object of its own: a local, a global, or the pointee
+    /// of a pointer parameter or of a loaded pointer, which may have been stored out of sight.
+    fn addresses_object(value : &impl AsValueRef) -> bool {
+
+        let value_ref = value.as_value_ref();
 
+        unsafe {
+
+            if !LLVMIsAAllocaInst(value_ref).is_null() || !LLVMIsAGlobalVariable(value_ref).is_null() {
+                return true;
             }
+
+            Self::addresses_unknown_object(value)
+            && LLVMGetTypeKind(LLVMTypeOf(value_ref)) == LLVMTypeKind::LLVMPointerTypeKind
+
         }
+
     }
 
-    pub fn handle_instruction(&self, inst : InstructionValue) {
+    /// Whether `value` is a parameter or a loaded pointer, whose pointee may have been written
+    /// by code the module does not contain.
+    fn addresses_unknown_object(value : &impl AsValueRef) -> bool {
 
-        for predecessor in Predecessors::get_predecessors(inst) {
-            let mut program = self.program.borrow_mut();
+        let value_ref = value.as_value_ref();
+
+        unsafe {
+            !LLVMIsAArgument(value_ref).is_null() || !LLVMIsALoadInst(value_ref).is_null()
+        }
+
+    }
+
+    /// Globals and the pointees of pointer parameters are objects whether or not anything in
+    /// the module stores to them.
+    pub fn handle_points_to_globals(&self, module : &Module) {
+
+        for global in module.get_globals() {
+            let mut program = self.points_to.borrow_mut();
             program.extend(vec![
-                Next(
-                    Symbol(format!("{:?}", predecessor)),
-                    Symbol(format!("{:?}", inst))
-                )
+                points_to::Alloca(self.value_symbol(&global), self.object_symbol(&global))
             ]);
         }
-        
+
+        // intrinsics such as `llvm.dbg.declare` take metadata, which inkwell cannot represent as
+        // parameters, and have no body for arguments to flow into
+        for function in module.get_functions().filter(|function| function.get_intrinsic_id() == 0) {
+
+            let function_sym = self.value_symbol(&function);
+
+            for (index, param) in function.get_params().iter().enumerate() {
+
+                let param_sym = self.value_symbol(param);
+                let mut program = self.points_to.borrow_mut();
+
+                program.extend(vec![
+                    points_to::Formal(function_sym.clone(), index, param_sym.clone())
+                ]);
+
+                if Self::addresses_object(param) {
+                    program.extend(vec![
+                        points_to::Alloca(param_sym, self.object_symbol(param))
+                    ]);
+                }
+
+            }
+
+        }
+
+    }
+
+    pub fn handle_points_to_instruction(&self, inst : InstructionValue) {
+
+        for operand in Self::operands(&inst) {
+            self.handle_points_to_constant(&operand, &inst);
+        }
+
+        if matches!(inst.get_opcode(), InstructionOpcode::Call | InstructionOpcode::Invoke) {
+            match InstructionCallSites::callee(&inst) {
+                Ok(Callee::Intrinsic(_)) => self.handle_intrinsic_points_to(inst),
+                Ok(Callee::Direct(function)) => match self.classify(&inst) {
+                    Some(api) => self.handle_api_points_to(inst, &api),
+                    None => self.handle_call_points_to(inst, function)
+                },
+                // nothing is known about where indirect calls go
+                _ => {}
+            }
+            return;
+        }
+
+        let inst_sym = self.value_symbol(&inst);
+        let operands = Self::operands(&inst);
+
+        let mut program = self.points_to.borrow_mut();
+
         match inst.get_opcode() {
-            InstructionOpcode::Call  | InstructionOpcode::Invoke => { // we don't care about error handling, so these can be the same thing
-               self.handle_call_inst(inst);
+            InstructionOpcode::Alloca => {
+                program.extend(vec![
+                    points_to::Alloca(inst_sym, self.object_symbol(&inst))
+                ]);
+            },
+            InstructionOpcode::Store => {
+                // `store %value, ptr %pointer`
+                program.extend(vec![
+                    points_to::Store(self.value_symbol(&operands[1]), self.value_symbol(&operands[0]))
+                ]);
+            },
+            InstructionOpcode::Load => {
+                program.extend(vec![
+                    points_to::Load(inst_sym.clone(), self.value_symbol(&operands[0]))
+                ]);
+                // what was stored there may be out of the module's sight, e.g. behind a parameter
+                if Self::addresses_object(&inst) {
+                    program.extend(vec![
+                        points_to::Alloca(inst_sym, self.object_symbol(&inst))
+                    ]);
+                }
+            },
+            InstructionOpcode::GetElementPtr => {
+                program.extend(vec![
+                    points_to::Gep(inst_sym, self.value_symbol(&operands[0]), GetElementPtr::offset(&inst))
+                ]);
+            },
+            InstructionOpcode::BitCast | InstructionOpcode::AddrSpaceCast | InstructionOpcode::PtrToInt
+            | InstructionOpcode::IntToPtr | InstructionOpcode::Freeze | InstructionOpcode::ExtractValue => {
+                program.extend(vec![
+                    points_to::Bitcast(inst_sym, self.value_symbol(&operands[0]))
+                ]);
+            },
+            InstructionOpcode::Phi | InstructionOpcode::InsertValue => {
+                for operand in operands.iter() {
+                    program.extend(vec![
+                        points_to::Bitcast(inst_sym.clone(), self.value_symbol(operand))
+                    ]);
+                }
+            },
+            InstructionOpcode::Select => {
+                for operand in operands[1..].iter() {
+                    program.extend(vec![
+                        points_to::Bitcast(inst_sym.clone(), self.value_symbol(operand))
+                    ]);
+                }
+            },
+            InstructionOpcode::Return => {
+                if let (Some(value), Some(function)) = (operands.first(), inst.get_parent().and_then(|block| block.get_parent())) {
+                    program.extend(vec![
+                        points_to::Ret(self.value_symbol(&function), self.value_symbol(value))
+                    ]);
+                }
             },
             _ => {
-               
+
             }
         }
 
     }
 
-    fn handle_alloca_inst(&self, inst : InstructionValue) {
+    /// Constant expressions over globals, e.g. `getelementptr (i8, ptr @STATE, i64 8)` for
+    /// `&STATE.b`, are operands rather than instructions, so they are handled with their users.
+    fn handle_points_to_constant(&self, value : &RawValue, user : &InstructionValue) {
 
-        match KnowAlloca::from_instr(&inst) {
-            KnowAlloca::MutexGuard => {
-                // ! look at this hack
-                // ! if we have this mutex guard allocation site
-                // ! we can assume that the next instruction is the guard label that will actually be used
-                // ! we the lock gets dropped.
-                let next_inst = inst.get_next_instruction()
-                .expect("if there's a mutex guard allocation, there should be a next instruction");
-    
-                let next_inst_sym = Symbol(
-                    Var::get_var_name_from_instr(&next_inst)
-                );
+        let value_ref = value.as_value_ref();
+        if unsafe { LLVMIsAConstantExpr(value_ref) }.is_null() {
+            return;
+        }
 
-                let this_symbol = Symbol(
-                    Var::get_var_name_from_instr(&inst)
-                );
+        let operands = Self::operands(value);
+        for operand in operands.iter() {
+            self.handle_points_to_constant(operand, user);
+        }
+
+        let value_sym = self.value_symbol(value);
 
-                let mut may_alias_table = self.may_alias_table.borrow_mut();
-                may_alias_table.insert(next_inst_sym, this_symbol);
+        let mut program = self.points_to.borrow_mut();
 
+        match unsafe { LLVMGetConstOpcode(value_ref) } {
+            LLVMOpcode::LLVMGetElementPtr => {
+                program.extend(vec![
+                    points_to::Gep(value_sym, self.value_symbol(&operands[0]), GetElementPtr::constant_offset(value, user))
+                ]);
+            },
+            LLVMOpcode::LLVMBitCast | LLVMOpcode::LLVMAddrSpaceCast | LLVMOpcode::LLVMPtrToInt | LLVMOpcode::LLVMIntToPtr => {
+                program.extend(vec![
+                    points_to::Bitcast(value_sym, self.value_symbol(&operands[0]))
+                ]);
             },
             _ => {
 
             }
         }
+
     }
 
-    fn handle_call_inst(&self, inst : InstructionValue) {
-       // 
-       match InstructionCallSites::demangled_called_function_name(&inst) {
-           Ok(_) => {
-               match KnownCallsites::from_instr(&inst) {
-                   KnownCallsites::StdSyncMutexLock => {
-                        self.handle_std_sync_mutex_lock(inst);
-                   },
-                   KnownCallsites::StdSyncMutexNew => {
-                        self.handle_std_sync_mutex_new(inst);
-                   },
-                   KnownCallsites::StdSyncMutexDrop => {
-                        self.handle_std_sync_mutex_drop(inst);
-                   },
-                   KnownCallsites::Unknown => {
-                       // println!("Unknown");
-                   }
-                   
+    pub fn handle_instruction<'ctx>(&self, inst : InstructionValue<'ctx>, cfg : &Cfg<'ctx>) {
+
+        for predecessor in Predecessors::get_predecessors(inst, cfg) {
+            let mut program = self.program.borrow_mut();
+            program.extend(vec![
+                Next(
+                    self.instruction_symbol(predecessor),
+                    self.instruction_symbol(inst)
+                )
+            ]);
+        }
+        
+        match inst.get_opcode() {
+            InstructionOpcode::Call  | InstructionOpcode::Invoke => { // we don't care about error handling, so these can be the same thing
+               match (self.classify(&inst), InstructionCallSites::callee(&inst)) {
+                   (Some(api), _) => self.handle_api(inst, &api),
+                   (None, Ok(Callee::Direct(function))) => self.handle_call(inst, function),
+                   _ => {}
                }
-           },
-           Err(e) => {
-               // println!("Error: {}", e);
-           }
-           
-       }
+            },
+            InstructionOpcode::Br if DropFlags::tests_drop_flag(&inst, &self.source_variables.borrow()) => {
+               self.handle_drop_flag(inst, cfg);
+            },
+            _ => {
+               
+            }
+        }
 
     }
 
-    fn handle_std_sync_mutex_lock_alias(&self, inst : InstructionValue) {
+    /// A drop flag is only clear once its value was moved out, to be released or held elsewhere,
+    /// so the edge skipping a flagged drop never leaves the value's guards held by it. Releasing
+    /// them at the test itself rules out the infeasible path that both keeps and skips them.
+    fn handle_drop_flag<'ctx>(&self, inst : InstructionValue<'ctx>, cfg : &Cfg<'ctx>) {
 
-        let var_basic_value = inst.get_operand(1)
-        .expect("Lock should have a 0th operand for the lock name")
-        .expect_left("Lock 0th operand should be a basic value.");
-        let var = Var::get_var_name_from_basic_value(
-            &var_basic_value
-        );
+        let successors = match inst.get_parent() {
+            Some(basic_block) => cfg.successors(&basic_block).to_vec(),
+            None => return
+        };
 
+        let mut locations = BTreeSet::new();
 
-        let alias_basic_value = inst.get_operand(0)
-        .expect("Lock should have a 0th operand for the guard alias")
-        .expect_left("Lock should be a basic value.");
-        let alias = Var::get_var_name_from_basic_value(
-            &alias_basic_value
-        );
+        // the flagged drop opens its block; anything past another call is not guarded by the flag
+        for successor in successors {
+            for call in InstructionIterator::new(&successor) {
+                if !matches!(call.get_opcode(), InstructionOpcode::Call | InstructionOpcode::Invoke) {
+                    continue;
+                }
+                match self.classify(&call).filter(|api| api.role == Role::Release) {
+                    Some(api) => locations.extend(self.api_locations(&call, &api).unwrap_or_default()),
+                    None => break
+                }
+            }
+        }
 
-        let var_sym = Symbol(var);
-        let alias_sym = Symbol(alias);
-    
-        // insert the guard  alias into the table
-        let mut may_alias_table = self.may_alias_table.borrow_mut();
-        may_alias_table.insert(alias_sym, var_sym.clone());
+        let inst_sym = self.instruction_symbol(inst);
 
-    }
+        let mut program = self.program.borrow_mut();
 
-    fn handle_std_sync_mutex_lock(&self, inst : InstructionValue) {
+        for var_sym in locations {
 
-        let var_basic_value = inst.get_operand(1)
-        .expect("Lock should have a 0th operand for the lock name")
-        .expect_left("Lock 0th operand should be a basic value.");
-        let var = Var::get_var_name_from_basic_value(
-            &var_basic_value
-        );
+            program.extend(vec![
+                Def(var_sym.clone(), inst_sym.clone())
+            ]);
 
+            program.extend(vec![
+                UseVar(var_sym.clone(), inst_sym.clone())
+            ]);
 
-        let alias_basic_value = inst.get_operand(0)
-        .expect("Lock should have a 0th operand for the guard alias")
-        .expect_left("Lock should be a basic value.");
-        let alias = Var::get_var_name_from_basic_value(
-            &alias_basic_value
-        );
+            program.extend(vec![
+                Release(inst_sym.clone(), var_sym)
+            ]);
 
-        let var_sym = Symbol(var);
-        let alias_sym = Symbol(alias);
-        let inst_sym = Symbol(format!("{:?}", inst));
+        }
 
-        let mut program = self.program.borrow_mut();
+    }
 
-        program.extend(vec![
-            Def(
-                var_sym.clone(),
-                inst_sym.clone()
-            )
-        ]);
-        
-        program.extend(vec![
-            UseVar(
-                var_sym.clone(),
-                inst_sym.clone()
-            )
-        ]);
+    /// A call continues into the callee's body, so locks the caller holds are still held there
+    /// and taking one of them again along a call chain is reentrant. Returns are not followed back,
+    /// so a guard moved into the callee, e.g. by `drop(guard)`, is released at the call itself,
+    /// and a guard the callee returns is acquired there, after the callee ran.
+    fn handle_call(&self, inst : InstructionValue, function : FunctionValue) {
+
+        let entry = match function.get_first_basic_block().and_then(|block| block.get_first_instruction()) {
+            Some(entry) => entry,
+            // only declared in this module
+            None => return
+        };
+
+        let released = self.inherited_releases(function);
+        let returned = self.returned_acquisitions(function);
+        let passed : BTreeSet<Symbol> = if released.is_empty() && returned.is_empty() {
+            BTreeSet::new()
+        } else {
+            InstructionCallSites::arguments(&inst).iter()
+            .flat_map(|argument| self.guard_locations(argument))
+            .collect()
+        };
+
+        // a returned guard holds a lock the caller handed over a way to, or a static
+        let passed_objects : HashSet<&ValueId> = passed.iter().filter_map(Self::object_id).collect();
+        let acquired : Vec<&Symbol> = returned.iter()
+        .filter(|location| match Self::object_id(location) {
+            Some(ValueId::Global(_)) => true,
+            Some(id) => passed_objects.contains(id),
+            None => false
+        })
+        .collect();
+
+        let inst_sym = self.instruction_symbol(inst);
+
+        let mut program = self.program.borrow_mut();
 
         program.extend(vec![
-            Lock(
+            Call(
                 inst_sym.clone(),
-                var_sym.clone()
+                self.instruction_symbol(entry)
             )
         ]);
 
+        for var_sym in released.intersection(&passed) {
+
+            program.extend(vec![
+                Def(var_sym.clone(), inst_sym.clone())
+            ]);
+
+            program.extend(vec![
+                UseVar(var_sym.clone(), inst_sym.clone())
+            ]);
+
+            program.extend(vec![
+                Release(inst_sym.clone(), var_sym.clone())
+            ]);
+
+        }
+
+        for var_sym in acquired {
+
+            program.extend(vec![
+                Def(var_sym.clone(), inst_sym.clone())
+            ]);
+
+            program.extend(vec![
+                UseVar(var_sym.clone(), inst_sym.clone())
+            ]);
+
+            program.extend(vec![
+                Lock(inst_sym.clone(), var_sym.clone())
+            ]);
+
+        }
+
     }
 
-    fn handle_std_sync_mutex_new(&self, inst : InstructionValue) {
+    /// The object a location lies in.
+    fn object_id(location : &Symbol) -> Option<&ValueId> {
+        match location {
+            Symbol::Location(id, _) | Symbol::Object(id) => Some(id),
+            _ => None
+        }
+    }
 
-        let mut program = self.program.borrow_mut();
+    /// Arguments flow into the callee's parameters and its returned values back into the call.
+    fn handle_call_points_to(&self, inst : InstructionValue, function : FunctionValue) {
 
-        // Add a def expression to indicate that the lock is created
-        let var_basic_value = inst.get_operand(0)
-        .expect("Lock should have a 0th operand for the lock name")
-        .expect_left("Lock 0th operand should be a basic value.");
-        let var = Var::get_var_name_from_basic_value(
-            &var_basic_value
-        );
+        let call_sym = self.value_symbol(&inst);
+        let function_sym = self.value_symbol(&function);
 
-        let var_sym = Symbol(var);
-        let inst_sym = Symbol(format!("{:?}", inst));
+        let mut program = self.points_to.borrow_mut();
 
         program.extend(vec![
-            Def(var_sym.clone(), inst_sym.clone()),
+            points_to::Call(call_sym.clone(), function_sym)
         ]);
 
+        for (index, argument) in InstructionCallSites::arguments(&inst).iter().enumerate() {
+            program.extend(vec![
+                points_to::Actual(call_sym.clone(), index, self.value_symbol(argument))
+            ]);
+        }
+
+    }
+
+    /// Futures and guards are moved with `memcpy`, which copies whatever the source holds.
+    fn handle_intrinsic_points_to(&self, inst : InstructionValue) {
+
+        let name = match InstructionCallSites::called_function_name(&inst) {
+            Ok(name) => name,
+            Err(_) => return
+        };
+
+        if !(name.starts_with("llvm.memcpy") || name.starts_with("llvm.memmove")) {
+            return;
+        }
+
+        let arguments = InstructionCallSites::arguments(&inst);
+        if arguments.len() < 2 {
+            return;
+        }
+
+        let mut program = self.points_to.borrow_mut();
+
         program.extend(vec![
-            UseVar(var_sym.clone(), inst_sym.clone()),
+            points_to::Memcpy(self.value_symbol(&arguments[0]), self.value_symbol(&arguments[1]))
         ]);
 
     }
 
-    fn handle_std_sync_mutex_drop(&self, inst : InstructionValue) {
+    /// Lock APIs are not analysed through their bodies. Whatever a call hands back as its guard
+    /// points to the lock it was given: the guard of an acquisition, a future that acquires the
+    /// lock when polled, or the guard a `wait` returns.
+    fn handle_api_points_to(&self, inst : InstructionValue, api : &LockApi) {
 
-        let var_basic_value = inst.get_operand(0)
-        .expect("Lock should have a 0th operand for the lock being dropped")
-        .expect_left("Lock 0th operand should be a basic value.");
-        let var = Var::get_var_name_from_basic_value(
-            &var_basic_value
-        );
+        if api.role == Role::New {
+            self.handle_new_points_to(inst, api);
+            return;
+        }
 
+        if matches!(api.role, Role::Release | Role::Forget) {
+            return;
+        }
 
-        let alias_sym = Symbol(var);
-        let inst_sym = Symbol(format!("{:?}", inst));
+        let (lock, guard) = match (Self::operand(&inst, api.lock), Self::operand(&inst, api.guard)) {
+            (Some(lock), Some(guard)) => (lock, guard),
+            _ => return
+        };
 
-        let may_alias_table = self.may_alias_table.borrow();
-        let root_sym = may_alias_table.get_root_symbol(&alias_sym);
+        let mut program = self.points_to.borrow_mut();
 
-        let mut program = self.program.borrow_mut();
+        let lock_sym = if api.indirect {
+            // the lock pointer is held by the guard or future `lock` points to
+            let held_sym = self.instruction_symbol(inst);
+            program.extend(vec![
+                points_to::Load(held_sym.clone(), self.value_symbol(&lock))
+            ]);
+            // a parameter or loaded pointer may hold what was stored out of the module's sight,
+            // e.g. the `Arc` behind a `&Arc<Mutex<T>>` parameter
+            if Self::addresses_unknown_object(&lock) {
+                program.extend(vec![
+                    points_to::Alloca(held_sym.clone(), self.loaded_object_symbol(&lock))
+                ]);
+            }
+            held_sym
+        } else {
+            self.value_symbol(&lock)
+        };
 
-        program.extend(vec![
-            Def(
-                root_sym.clone(),
-                inst_sym.clone()
-            )
-        ]);
+        let guard_sym = self.value_symbol(&guard);
 
-        program.extend(vec![
-            UseVar(
-                root_sym.clone(),
-                inst_sym.clone()
-            )
-        ]);
+        let returned_in_registers = api.guard == Some(Operand::Return(ReturnValue::Return))
+        && !InstructionCallSites::has_sret(&inst);
+
+        if returned_in_registers {
+            program.extend(vec![
+                points_to::Bitcast(guard_sym, lock_sym)
+            ]);
+        } else {
+            // the guard's layout is not known, only how many bytes it spans when returned by `sret`
+            let size = InstructionCallSites::sret_size(&inst).unwrap_or(0);
+            program.extend(vec![
+                points_to::Hold(guard_sym, lock_sym, size as i64)
+            ]);
+        }
+
+    }
+
+    /// A `new` that hands back a pointer, such as `Arc::new`, allocates what it points to.
+    /// Every call site is a distinct allocation, however many clones of the pointer are made.
+    fn handle_new_points_to(&self, inst : InstructionValue, api : &LockApi) {
+
+        if api.lock != Some(Operand::Return(ReturnValue::Return)) || InstructionCallSites::has_sret(&inst) {
+            return;
+        }
+
+        let result = InstructionCallSites::result(&inst);
+        if unsafe { LLVMGetTypeKind(LLVMTypeOf(result.as_value_ref())) } != LLVMTypeKind::LLVMPointerTypeKind {
+            return;
+        }
+
+        let mut program = self.points_to.borrow_mut();
 
         program.extend(vec![
-            Release(
-                inst_sym.clone(),
-                root_sym.clone()
-            )
+            points_to::Alloca(self.value_symbol(&result), self.object_symbol(&inst))
         ]);
-        
+
     }
 
-}
+    /// The lock resources a lock API operand refers to, by what the points-to program found for it:
+    /// the locations it points to, or for a `held` operand the locations the pointer it points to does.
+    fn lock_locations(&self, value : &RawValue, held : bool) -> Vec<Symbol> {
 
-impl LlvmModulePass for ReentrantLockPass {
-    fn run_pass(
-        &self,
-        module: &mut Module,
-        _manager: &ModuleAnalysisManager
-    ) -> PreservedAnalyses {
+        let value_sym = self.value_symbol(value);
 
-        for function in module.get_functions() {
+        let pointees = {
+            let table = self.points_to_table.borrow();
+            if held {
+                table.contents(&value_sym)
+            } else {
+                table.pointees(&value_sym)
+            }
+        };
 
-            for basic_block in function.get_basic_blocks() {
-                for instr in InstructionIterator::new(&basic_block) {
-                    self.handle_alias_instructions(instr);
+        if pointees.is_empty() {
+            // e.g. a pointer returned by a function the module only declares
+            let object = if held { self.loaded_object_symbol(value) } else { self.object_symbol(value) };
+            return vec![self.location_symbol(&object, 0)];
+        }
+
+        pointees.iter()
+        .map(|(object, offset)| self.location_symbol(object, *offset))
+        .collect()
+
+    }
+
+    /// The locations a guard handed over by value may hold. Small guards are passed in registers,
+    /// the lock pointer being their first field, and larger ones behind a pointer to a copy.
+    fn guard_locations(&self, value : &RawValue) -> BTreeSet<Symbol> {
+
+        let value_sym = self.value_symbol(value);
+
+        let pointees = {
+            let table = self.points_to_table.borrow();
+            let mut pointees = table.pointees(&value_sym);
+            pointees.extend(table.contents(&value_sym));
+            pointees
+        };
+
+        pointees.iter()
+        .map(|(object, offset)| self.location_symbol(object, *offset))
+        .collect()
+
+    }
+
+    /// The locations held by the guards a release drops: drop glue receives a pointer to the
+    /// guard, or to a value owning guards, which point to their locks.
+    fn dropped_locations(&self, value : &RawValue) -> Vec<Symbol> {
+
+        let owned = self.points_to_table.borrow().owned_contents(&self.value_symbol(value));
+
+        if owned.is_empty() {
+            return self.lock_locations(value, true);
+        }
+
+        owned.iter()
+        .map(|(object, offset)| self.location_symbol(object, *offset))
+        .collect()
+
+    }
+
+    /// The locations a call to a catalogued lock API refers to, if it refers to any.
+    fn api_locations(&self, inst : &InstructionValue, api : &LockApi) -> Option<Vec<Symbol>> {
+
+        match api.role {
+            Role::Alias | Role::Forget => None,
+            Role::Release => Self::operand(inst, api.guard).map(|guard| self.dropped_locations(&guard)),
+            _ => Self::operand(inst, api.lock).map(|lock| self.lock_locations(&lock, api.indirect))
+        }
+
+    }
+
+    /// The locations `function`, or anything it calls, releases without having acquired them,
+    /// i.e. those of guards it was handed. Recursive calls contribute what is known so far.
+    fn inherited_releases(&self, function : FunctionValue) -> BTreeSet<Symbol> {
+
+        let key = function.as_value_ref();
+        if let Some(released) = self.inherited_releases.borrow().get(&key) {
+            return released.clone();
+        }
+        self.inherited_releases.borrow_mut().insert(key, BTreeSet::new());
+
+        let mut released = BTreeSet::new();
+        let mut acquired = BTreeSet::new();
+
+        for basic_block in function.get_basic_blocks() {
+            for inst in InstructionIterator::new(&basic_block) {
+
+                if !matches!(inst.get_opcode(), InstructionOpcode::Call | InstructionOpcode::Invoke) {
+                    continue;
                 }
+
+                match (self.classify(&inst), InstructionCallSites::callee(&inst)) {
+                    (Some(api), _) => {
+                        let locations = self.api_locations(&inst, &api).unwrap_or_default();
+                        match api.role {
+                            Role::Release => released.extend(locations),
+                            role if role.acquires() => acquired.extend(locations),
+                            _ => {}
+                        }
+                    },
+                    (None, Ok(Callee::Direct(callee))) => released.extend(self.inherited_releases(callee)),
+                    _ => {}
+                }
+
             }
         }
-    
-        
-        for function in module.get_functions() {
 
-            for basic_block in function.get_basic_blocks() {
-                for instr in InstructionIterator::new(&basic_block) {
-                    self.handle_instruction(instr);
+        let released : BTreeSet<Symbol> = released.difference(&acquired).cloned().collect();
+        self.inherited_releases.borrow_mut().insert(key, released.clone());
+        released
+
+    }
+
+    /// The locations `function` returns holding: acquired by it, or returned held by a function it
+    /// calls, and not released again on some path to a `ret`, e.g. `fn state(&self) -> MutexGuard<State>`.
+    /// Recursive calls contribute what is known so far.
+    fn returned_acquisitions(&self, function : FunctionValue) -> BTreeSet<Symbol> {
+
+        let key = function.as_value_ref();
+        if let Some(returned) = self.returned_acquisitions.borrow().get(&key) {
+            return returned.clone();
+        }
+        self.returned_acquisitions.borrow_mut().insert(key, BTreeSet::new());
+
+        let cfg = Cfg::from_function(function);
+        let mut held_at_entry : HashMap<BasicBlock, BTreeSet<Symbol>> = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut worklist : Vec<BasicBlock> = function.get_first_basic_block().into_iter().collect();
+        let mut returned = BTreeSet::new();
+
+        while let Some(basic_block) = worklist.pop() {
+
+            visited.insert(basic_block);
+            let mut held = held_at_entry.get(&basic_block).cloned().unwrap_or_default();
+
+            for inst in InstructionIterator::new(&basic_block) {
+
+                let (acquired, released) = self.call_effects(&inst);
+                held.retain(|location| !released.contains(location));
+                held.extend(acquired);
+
+                if inst.get_opcode() == InstructionOpcode::Return {
+                    returned.extend(held.iter().cloned());
                 }
+
             }
-        }
 
-        {
-            let program = self.program.borrow();
-            let posts = program.compute();
-            /*println!("DONE");
-            for edge in posts.edge {
-               println!("_____\n from: {:?}\n\nto: {:?}\n\nvar: {:?}", edge.0, edge.1, edge.2);
+            for successor in cfg.successors(&basic_block) {
+                let entry = held_at_entry.entry(*successor).or_default();
+                let count = entry.len();
+                entry.extend(held.iter().cloned());
+                if entry.len() > count || !visited.contains(successor) {
+                    worklist.push(*successor);
+                }
             }
 
-            println!("MAY ALIAS TABLE: {:?}", self.may_alias_table.borrow());
+        }
+
+        self.returned_acquisitions.borrow_mut().insert(key, returned.clone());
+        returned
+
+    }
 
-            for release in program.priors().releases {
-                println!("_____\n release: {:?}\n\nvar: {:?}", release.0, release.1);
-            }*/
+    /// The locations a call leaves held and those it releases, as far as the caller is concerned.
+    fn call_effects(&self, inst : &InstructionValue) -> (BTreeSet<Symbol>, BTreeSet<Symbol>) {
 
-            /*for in_ in posts.in_ {
-                if in_.0.0.contains("%guard") {
-                    println!("_____\n in GUARD: {:?}\n\nvar: {:?}", in_.0, in_.1);
-                } else if in_.0.0.contains("%deadlock")  {
-                    println!("_____\n in DEADLOCK: {:?}\n\nvar: {:?}", in_.0, in_.1);
-                }
-            }*/
-
-            if posts.deadlock.len() > 0 {
-                println!("MAY DEADLOCK!");
-                let mut i = 0;
-                for deadlock in posts.deadlock {
-                    println!(
-                        "__________\nDEADLOCK #{}\n\tFIRST LOCK: {:?}\n\n\tRESOURCE: {:?}\n\n\tSECOND_LOCK: {:?}\n__________", 
-                        i, deadlock.0, deadlock.1, deadlock.2
-                    );
-                    i += 1;
+        if !matches!(inst.get_opcode(), InstructionOpcode::Call | InstructionOpcode::Invoke) {
+            return (BTreeSet::new(), BTreeSet::new());
+        }
+
+        match (self.classify(inst), InstructionCallSites::callee(inst)) {
+            (Some(api), _) => {
+                let locations = self.api_locations(inst, &api).unwrap_or_default().into_iter().collect();
+                match api.role {
+                    role if role.acquires() => (locations, BTreeSet::new()),
+                    Role::Release => (BTreeSet::new(), locations),
+                    _ => (BTreeSet::new(), BTreeSet::new())
                 }
-            } else {
-                println!("DID NOT FIND A DEADLOCK");
+            },
+            (None, Ok(Callee::Direct(callee))) => (self.returned_acquisitions(callee), self.inherited_releases(callee)),
+            _ => (BTreeSet::new(), BTreeSet::new())
+        }
+
+    }
+
+    /// Emits the facts for a call to a catalogued lock API, for every location its operand may
+    /// refer to. Every role but `alias` and `forget` redefines and uses the lock; acquisitions
+    /// additionally `Lock` it and releases `Release` it. A `wait` does both, as it releases the
+    /// lock while waiting and acquires it again before returning. Only blocking acquisitions can
+    /// be the second lock of a deadlock.
+    fn handle_api(&self, inst : InstructionValue, api : &LockApi) {
+
+        let locations = match self.api_locations(&inst, api) {
+            Some(locations) => locations,
+            None => return
+        };
+
+        let inst_sym = self.instruction_symbol(inst);
+
+        if api.role.blocks() {
+            self.blocking.borrow_mut().insert(inst_sym.clone());
+        }
+
+        if api.role == Role::TryLock {
+            self.try_locks.borrow_mut().insert(inst_sym.clone());
+        }
+
+        if api.role.acquires() && !api.indirect {
+            if let Some(lock) = Self::operand(&inst, api.lock) {
+                self.lock_operands.borrow_mut().insert(inst_sym.clone(), lock);
             }
+        }
 
-            println!("END ANALYSIS");
+        let mut program = self.program.borrow_mut();
+
+        for var_sym in locations {
+
+            program.extend(vec![
+                Def(var_sym.clone(), inst_sym.clone())
+            ]);
+
+            program.extend(vec![
+                UseVar(var_sym.clone(), inst_sym.clone())
+            ]);
+
+            if matches!(api.role, Role::Release | Role::Wait) {
+                program.extend(vec![
+                    Release(inst_sym.clone(), var_sym.clone())
+                ]);
+            }
+
+            if api.role.acquires() || api.role == Role::Wait {
+                program.extend(vec![
+                    Lock(inst_sym.clone(), var_sym)
+                ]);
+            }
 
         }
 
+    }
 
+}
 
-        PreservedAnalyses::None
+impl LlvmModulePass for ReentrantLockPass {
+    fn run_pass(
+        &self,
+        module: &mut Module,
+        manager: &ModuleAnalysisManager
+    ) -> PreservedAnalyses {
+
+        let report = manager.get_result::<LockAnalysis>(module).report(&self.config);
+        let mut errors = Vec::new();
+
+        if let Err(error) = self.write_report(&report) {
+            let message = format!("reentrant-lock-detection: {}", error);
+            diagnostics::diagnose(module, &message, Severity::Error);
+            errors.push(message);
+        }
+
+        // diagnosed once the whole report is out, since `opt` ends the run on the first error
+        if self.config.strict && self.diagnose(module, &report) == Some(Severity::Error) {
+            errors.push(format!("reentrant-lock-detection: {} may deadlock", module.get_name().to_string_lossy()));
+        }
+
+        // a diagnostic handler other than `opt`'s may return without printing errors
+        for error in errors {
+            eprintln!("error: {}", error);
+        }
+
+        // the IR is unchanged, so a later pass of the pipeline reuses the analysis
+        PreservedAnalyses::All
 
     }
-}
\ No newline at end of file
+}
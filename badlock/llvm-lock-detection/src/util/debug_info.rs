@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::ffi::c_char;
+use std::fmt;
+use llvm_plugin::inkwell::llvm_sys::core::{
+    LLVMIsAArgument, LLVMIsAStoreInst, LLVMGetMDNodeNumOperands, LLVMGetMDNodeOperands, LLVMGetMDString, LLVMGetMDKindIDInContext,
+    LLVMGetNumOperands, LLVMGetOperand, LLVMGetTypeContext, LLVMGlobalCopyAllMetadata,
+    LLVMDisposeValueMetadataEntries, LLVMIsAMDNode, LLVMMetadataAsValue, LLVMTypeOf,
+    LLVMValueAsMetadata, LLVMValueMetadataEntriesGetKind, LLVMValueMetadataEntriesGetMetadata,
+};
+use llvm_plugin::inkwell::llvm_sys::debuginfo::{
+    LLVMDIGlobalVariableExpressionGetVariable, LLVMDITypeGetName, LLVMDITypeGetOffsetInBits, LLVMDIVariableGetLine,
+    LLVMGetMetadataKind, LLVMMetadataKind,
+};
+use llvm_plugin::inkwell::llvm_sys::prelude::{LLVMMetadataRef, LLVMValueRef};
+use llvm_plugin::inkwell::module::Module;
+use llvm_plugin::inkwell::values::{AsValueRef, FunctionValue, InstructionOpcode};
+use llvm_plugin::utils::InstructionIterator;
+use crate::util::llvm_ir::InstructionCallSites;
+
+/// Operand layout shared by `DILocalVariable` and `DIGlobalVariable`.
+const DI_VARIABLE_SCOPE : usize = 0;
+const DI_VARIABLE_NAME : usize = 1;
+const DI_VARIABLE_TYPE : usize = 3;
+
+/// Operand layout of the scopes we walk through when naming a variable's scope.
+const DI_NAMED_SCOPE_NAME : usize = 2;
+const DI_LEXICAL_BLOCK_PARENT : usize = 1;
+
+/// Operand layout of the types we walk through when laying out a variable's fields.
+const DI_DERIVED_TYPE_BASE_TYPE : usize = 3;
+const DI_COMPOSITE_TYPE_ELEMENTS : usize = 4;
+
+/// How deep fields of fields are laid out; the innards of library types rarely matter.
+const MAX_FIELD_DEPTH : usize = 6;
+
+/// A field of a source variable, or of a field of it, e.g. `inner.c`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceField {
+    pub path : String,
+    pub type_name : Option<String>,
+    // bytes from the start of the variable
+    pub offset : u64
+}
+
+/// A source-level variable recovered from a `DILocalVariable` or `DIGlobalVariable`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceVariable {
+    pub name : String,
+    pub type_name : Option<String>,
+    pub scope : Option<String>,
+    pub line : u32,
+    // outer fields first; for references and pointers, the fields of what they point to
+    pub fields : Vec<SourceField>
+}
+
+impl SourceVariable {
+
+    /// The field starting `offset` bytes into the variable, named as a variable of its own
+    /// (`s.inner.c`). A field and its first field start at the same offset, so the outermost
+    /// one whose type is among `lock_types` is preferred, and otherwise the outermost one.
+    /// The start of a variable is only named after a field if that field is the lock.
+    pub fn field(&self, offset : i64, lock_types : &[String]) -> Option<SourceVariable> {
+
+        let is_lock = |type_name : &Option<String>| match type_name {
+            Some(type_name) => lock_types.iter().any(|lock_type| lock_type == Self::base_type_name(type_name)),
+            None => false
+        };
+
+        if offset == 0 && is_lock(&self.type_name) {
+            return None;
+        }
+
+        let starting = self.fields.iter()
+        .filter(|field| offset >= 0 && field.offset == offset as u64)
+        .collect::<Vec<_>>();
+
+        let field = match starting.iter().find(|field| is_lock(&field.type_name)) {
+            Some(field) => field,
+            None if offset != 0 => starting.first()?,
+            None => return None
+        };
+
+        Some(SourceVariable {
+            name : format!("{}.{}", self.name, field.path),
+            type_name : field.type_name.clone(),
+            scope : self.scope.clone(),
+            line : self.line,
+            fields : Vec::new()
+        })
+
+    }
+
+    /// `Mutex` for `&std::sync::mutex::Mutex<i32>`.
+    fn base_type_name(type_name : &str) -> &str {
+
+        let type_name = type_name.trim_start_matches(['&', '*']);
+        let type_name = type_name.trim_start_matches("mut ").trim_start_matches("const ");
+        let type_name = type_name.split('<').next().unwrap_or(type_name);
+        type_name.rsplit("::").next().unwrap_or(type_name)
+
+    }
+
+}
+
+impl fmt::Display for SourceVariable {
+
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        write!(f, "{}", self.name)?;
+        if let Some(type_name) = &self.type_name {
+            write!(f, ": {}", type_name)?;
+        }
+        if let Some(scope) = &self.scope {
+            write!(f, " in {}", scope)?;
+        }
+        Ok(())
+
+    }
+
+}
+
+/// Maps IR values (allocas, arguments, globals) to the source variables that debug info
+/// attaches to them through `llvm.dbg.declare`, `llvm.dbg.value` and `!dbg` global attachments.
+#[derive(Debug, Clone, Default)]
+pub struct SourceVariables(HashMap<LLVMValueRef, SourceVariable>);
+
+impl SourceVariables {
+
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn from_module(module : &Module) -> Self {
+
+        let mut variables = Self::new();
+
+        for global in module.get_globals() {
+            variables.collect_global(global.as_value_ref());
+        }
+
+        for function in module.get_functions() {
+            variables.collect_function(function);
+        }
+
+        variables
+
+    }
+
+    pub fn get(&self, value : &impl AsValueRef) -> Option<&SourceVariable> {
+        self.0.get(&value.as_value_ref())
+    }
+
+    /// Records every variable declared in the function through the `llvm.dbg.*` intrinsics.
+    pub fn collect_function(&mut self, function : FunctionValue) {
+
+        for basic_block in function.get_basic_blocks() {
+            for instr in InstructionIterator::new(&basic_block) {
+
+                if instr.get_opcode() != InstructionOpcode::Call {
+                    continue;
+                }
+
+                let is_dbg_intrinsic = match InstructionCallSites::called_function_name(&instr) {
+                    Ok(name) => matches!(name.as_str(), "llvm.dbg.declare" | "llvm.dbg.value" | "llvm.dbg.addr"),
+                    Err(_) => false
+                };
+
+                if !is_dbg_intrinsic {
+                    continue;
+                }
+
+                // ! the operands are metadata, so inkwell's `get_operand` cannot represent them
+                let instr_ref = instr.as_value_ref();
+                if unsafe { LLVMGetNumOperands(instr_ref) } < 2 {
+                    continue;
+                }
+
+                let (value_md, variable_md) = unsafe {
+                    (LLVMGetOperand(instr_ref, 0), LLVMGetOperand(instr_ref, 1))
+                };
+
+                let value = match Metadata::wrapped_value(value_md) {
+                    Some(value) => value,
+                    None => continue
+                };
+
+                if let Some(variable) = Metadata::source_variable(variable_md) {
+                    // the first declaration wins, later `dbg.value`s only track updates
+                    self.0.entry(value).or_insert(variable);
+                }
+
+            }
+        }
+
+        self.collect_spilled_arguments(function);
+
+    }
+
+    /// Debug builds declare arguments through the stack slot they are spilled to
+    /// (`store ptr %self, ptr %self.dbg.spill`), so the argument is named after the slot's variable.
+    fn collect_spilled_arguments(&mut self, function : FunctionValue) {
+
+        for basic_block in function.get_basic_blocks() {
+            for instr in InstructionIterator::new(&basic_block) {
+
+                let instr_ref = instr.as_value_ref();
+                if unsafe { LLVMIsAStoreInst(instr_ref) }.is_null() {
+                    continue;
+                }
+
+                let (value, slot) = unsafe {
+                    (LLVMGetOperand(instr_ref, 0), LLVMGetOperand(instr_ref, 1))
+                };
+
+                if unsafe { LLVMIsAArgument(value) }.is_null() {
+                    continue;
+                }
+
+                if let Some(variable) = self.0.get(&slot).cloned() {
+                    self.0.entry(value).or_insert(variable);
+                }
+
+            }
+        }
+
+    }
+
+    /// Records the `DIGlobalVariable` attached to a global through its `!dbg` metadata.
+    pub(crate) fn collect_global(&mut self, global : LLVMValueRef) {
+
+        unsafe {
+
+            let context = LLVMGetTypeContext(LLVMTypeOf(global));
+            let dbg = "dbg";
+            let dbg_kind = LLVMGetMDKindIDInContext(context, dbg.as_ptr() as *const c_char, dbg.len() as u32);
+
+            let mut count = 0;
+            let entries = LLVMGlobalCopyAllMetadata(global, &mut count);
+            if entries.is_null() {
+                return;
+            }
+
+            for index in 0..count as u32 {
+
+                if LLVMValueMetadataEntriesGetKind(entries, index) != dbg_kind {
+                    continue;
+                }
+
+                let expression = LLVMValueMetadataEntriesGetMetadata(entries, index);
+                let variable = LLVMDIGlobalVariableExpressionGetVariable(expression);
+                if variable.is_null() {
+                    continue;
+                }
+
+                let variable_md = LLVMMetadataAsValue(context, variable);
+                if let Some(variable) = Metadata::source_variable(variable_md) {
+                    self.0.entry(global).or_insert(variable);
+                }
+
+            }
+
+            LLVMDisposeValueMetadataEntries(entries);
+
+        }
+
+    }
+
+}
+
+/// Raw accessors for the metadata nodes inkwell does not wrap.
+pub(crate) struct Metadata;
+
+impl Metadata {
+
+    /// Returns the operands of an `MDNode` (or the single value of a `ValueAsMetadata`).
+    pub(crate) fn node_operands(md : LLVMValueRef) -> Vec<LLVMValueRef> {
+
+        unsafe {
+
+            // ! `LLVMGetMDNodeNumOperands` asserts on metadata that is neither a node nor a value (e.g. `DIArgList`)
+            if md.is_null() || LLVMIsAMDNode(md).is_null() {
+                return Vec::new();
+            }
+
+            let count = LLVMGetMDNodeNumOperands(md) as usize;
+            let mut operands = vec![std::ptr::null_mut(); count];
+            LLVMGetMDNodeOperands(md, operands.as_mut_ptr());
+            operands
+
+        }
+
+    }
+
+    pub(crate) fn node_operand(md : LLVMValueRef, index : usize) -> Option<LLVMValueRef> {
+
+        Self::node_operands(md).get(index).copied().filter(|operand| !operand.is_null())
+
+    }
+
+    /// Unwraps `metadata ptr %x` into `%x`.
+    pub(crate) fn wrapped_value(md : LLVMValueRef) -> Option<LLVMValueRef> {
+
+        if md.is_null() || unsafe { LLVMIsAMDNode(md) }.is_null() {
+            return None;
+        }
+
+        match Self::kind(md) {
+            LLVMMetadataKind::LLVMLocalAsMetadataMetadataKind | LLVMMetadataKind::LLVMConstantAsMetadataMetadataKind => {
+                Self::node_operand(md, 0)
+            },
+            _ => None
+        }
+
+    }
+
+    pub(crate) fn string(md : LLVMValueRef) -> Option<String> {
+
+        unsafe {
+            let mut len = 0;
+            let ptr = LLVMGetMDString(md, &mut len);
+            if ptr.is_null() {
+                return None;
+            }
+            let bytes = std::slice::from_raw_parts(ptr as *const u8, len as usize);
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+
+    }
+
+    pub(crate) fn kind(md : LLVMValueRef) -> LLVMMetadataKind {
+        unsafe { LLVMGetMetadataKind(Self::as_metadata(md)) }
+    }
+
+    pub(crate) fn as_metadata(md : LLVMValueRef) -> LLVMMetadataRef {
+        unsafe { LLVMValueAsMetadata(md) }
+    }
+
+    pub(crate) fn type_name(md : LLVMValueRef) -> Option<String> {
+
+        unsafe {
+            let mut len = 0;
+            let ptr = LLVMDITypeGetName(Self::as_metadata(md), &mut len);
+            if ptr.is_null() || len == 0 {
+                return None;
+            }
+            let bytes = std::slice::from_raw_parts(ptr as *const u8, len);
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+
+    }
+
+    /// Names the innermost function or namespace enclosing a scope, skipping lexical blocks.
+    pub(crate) fn scope_name(md : LLVMValueRef) -> Option<String> {
+
+        let mut current = md;
+        loop {
+            match Self::kind(current) {
+                LLVMMetadataKind::LLVMDISubprogramMetadataKind | LLVMMetadataKind::LLVMDINamespaceMetadataKind => {
+                    return Self::node_operand(current, DI_NAMED_SCOPE_NAME).and_then(Self::string);
+                },
+                LLVMMetadataKind::LLVMDILexicalBlockMetadataKind | LLVMMetadataKind::LLVMDILexicalBlockFileMetadataKind => {
+                    current = Self::node_operand(current, DI_LEXICAL_BLOCK_PARENT)?;
+                },
+                _ => {
+                    return None;
+                }
+            }
+        }
+
+    }
+
+    /// Reads a `DILocalVariable` or `DIGlobalVariable`.
+    pub(crate) fn source_variable(md : LLVMValueRef) -> Option<SourceVariable> {
+
+        match Self::kind(md) {
+            LLVMMetadataKind::LLVMDILocalVariableMetadataKind | LLVMMetadataKind::LLVMDIGlobalVariableMetadataKind => {},
+            _ => return None
+        }
+
+        let name = Self::node_operand(md, DI_VARIABLE_NAME).and_then(Self::string)?;
+        let type_md = Self::node_operand(md, DI_VARIABLE_TYPE);
+        let type_name = type_md.and_then(Self::type_name);
+        let scope = Self::node_operand(md, DI_VARIABLE_SCOPE).and_then(Self::scope_name);
+        let line = unsafe { LLVMDIVariableGetLine(Self::as_metadata(md)) };
+        let fields = type_md.map(Self::fields).unwrap_or_default();
+
+        Some(SourceVariable {
+            name,
+            type_name,
+            scope,
+            line,
+            fields
+        })
+
+    }
+
+    /// Lays out the fields of a struct type, or of the type a reference or pointer points to.
+    pub(crate) fn fields(type_md : LLVMValueRef) -> Vec<SourceField> {
+
+        // references, pointers and the like are the only derived types a variable has
+        let type_md = match Self::kind(type_md) {
+            LLVMMetadataKind::LLVMDIDerivedTypeMetadataKind => match Self::node_operand(type_md, DI_DERIVED_TYPE_BASE_TYPE) {
+                Some(base_type) => base_type,
+                None => return Vec::new()
+            },
+            _ => type_md
+        };
+
+        let mut fields = Vec::new();
+        Self::collect_fields(type_md, None, 0, 0, &mut fields);
+        fields
+
+    }
+
+    fn collect_fields(type_md : LLVMValueRef, prefix : Option<&str>, offset : u64, depth : usize, fields : &mut Vec<SourceField>) {
+
+        if depth >= MAX_FIELD_DEPTH || !matches!(Self::kind(type_md), LLVMMetadataKind::LLVMDICompositeTypeMetadataKind) {
+            return;
+        }
+
+        let elements = match Self::node_operand(type_md, DI_COMPOSITE_TYPE_ELEMENTS) {
+            Some(elements) => elements,
+            None => return
+        };
+
+        for member in Self::node_operands(elements) {
+
+            // enum variant parts are composites and array subranges are not types, neither is a field
+            if member.is_null() || !matches!(Self::kind(member), LLVMMetadataKind::LLVMDIDerivedTypeMetadataKind) {
+                continue;
+            }
+
+            let name = match Self::type_name(member) {
+                Some(name) => name,
+                None => continue
+            };
+
+            let path = match prefix {
+                Some(prefix) => format!("{}.{}", prefix, name),
+                None => name
+            };
+            let member_offset = offset + unsafe { LLVMDITypeGetOffsetInBits(Self::as_metadata(member)) } / 8;
+            let base_type = Self::node_operand(member, DI_DERIVED_TYPE_BASE_TYPE);
+
+            fields.push(SourceField {
+                path : path.clone(),
+                type_name : base_type.and_then(Self::type_name),
+                offset : member_offset
+            });
+
+            // fields behind references are not laid out inline, and are derived types themselves
+            if let Some(base_type) = base_type {
+                Self::collect_fields(base_type, Some(&path), member_offset, depth + 1, fields);
+            }
+
+        }
+
+    }
+
+}
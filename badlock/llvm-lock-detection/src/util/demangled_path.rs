@@ -0,0 +1,697 @@
+use std::fmt;
+
+/// A Rust type as printed by `rustc_demangle`, with lifetimes erased.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RustType {
+    Path(RustPath),
+    Reference { mutable : bool, inner : Box<RustType> },
+    Pointer { mutable : bool, inner : Box<RustType> },
+    Tuple(Vec<RustType>),
+    Array(Box<RustType>, String),
+    Slice(Box<RustType>),
+    Dyn(Vec<RustType>),
+    // fn pointers, `!`, const arguments and anything else we do not need to look into
+    Opaque(String)
+}
+
+/// A path such as `core::ptr::drop_in_place<std::sync::mutex::MutexGuard<i32>>` or
+/// `<alloc::sync::Arc<T> as core::clone::Clone>::clone`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RustPath {
+    pub qualified_self : Option<Box<QualifiedSelf>>,
+    pub segments : Vec<PathSegment>
+}
+
+/// The `<Type as Trait>` prefix of a qualified path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualifiedSelf {
+    pub self_type : RustType,
+    pub as_trait : Option<RustPath>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSegment {
+    pub name : String,
+    pub generics : Vec<RustType>
+}
+
+impl RustPath {
+
+    /// Parses a demangled symbol, dropping the legacy `::h<hash>` suffix if present.
+    pub fn parse(demangled : &str) -> Result<Self, anyhow::Error> {
+
+        let mut parser = Parser::new(demangled);
+        let path = parser.path()?;
+        if !parser.at_end() {
+            return Err(anyhow::anyhow!("Unexpected trailing input in demangled path: {}", demangled));
+        }
+        Ok(path.without_hash())
+
+    }
+
+    fn without_hash(mut self) -> Self {
+
+        let is_hash = |segment : &PathSegment| {
+            segment.generics.is_empty()
+            && segment.name.len() == 17
+            && segment.name.starts_with('h')
+            && segment.name[1..].chars().all(|c| c.is_ascii_hexdigit())
+        };
+
+        if self.segments.len() > 1 && self.segments.last().map(is_hash).unwrap_or(false) {
+            self.segments.pop();
+        }
+        self
+
+    }
+
+    fn join(types : &[RustType]) -> String {
+        types.iter().map(|inner| inner.to_string()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Segment names without generics. The v0 mangling prints inherent methods as
+    /// `<lock_api::mutex::Mutex<R, T>>::lock`, so an inherent qualified self is folded in front;
+    /// trait impls (`<T as Trait>::f`) keep only their own segments.
+    pub fn names(&self) -> Vec<&str> {
+
+        let mut names = match &self.qualified_self {
+            Some(qualified_self) => match (&qualified_self.self_type, &qualified_self.as_trait) {
+                (RustType::Path(self_path), None) => self_path.names(),
+                _ => Vec::new()
+            },
+            None => Vec::new()
+        };
+        names.extend(self.segments.iter().map(|segment| segment.name.as_str()));
+        names
+
+    }
+
+    /// Whether the segment names (ignoring generics) are exactly `names`.
+    pub fn is(&self, names : &[&str]) -> bool {
+        self.names() == names
+    }
+
+    /// Whether this is `<T as Trait>::method` for the trait path `as_trait`.
+    pub fn is_trait_method(&self, as_trait : &[&str], method : &str) -> bool {
+
+        match &self.qualified_self {
+            Some(qualified_self) => {
+                qualified_self.as_trait.as_ref().map(|path| path.is(as_trait)).unwrap_or(false)
+                && self.segments.len() == 1
+                && self.segments[0].name == method
+            },
+            None => false
+        }
+
+    }
+
+    pub fn self_type(&self) -> Option<&RustType> {
+        self.qualified_self.as_ref().map(|qualified_self| &qualified_self.self_type)
+    }
+
+    /// Whether this path matches `pattern`, comparing segment names and ignoring generics.
+    /// In the pattern, `_` matches any single segment or self type, and `{{closure}}` also
+    /// matches the v0 spelling `{closure#N}`.
+    pub fn matches(&self, pattern : &RustPath) -> bool {
+
+        match (&self.qualified_self, &pattern.qualified_self) {
+            (Some(qualified_self), Some(pattern_self)) => {
+                let traits_match = match (&qualified_self.as_trait, &pattern_self.as_trait) {
+                    (Some(as_trait), Some(pattern_trait)) => as_trait.matches(pattern_trait),
+                    (None, None) => true,
+                    _ => false
+                };
+                traits_match
+                && Self::type_matches(&qualified_self.self_type, &pattern_self.self_type)
+                && Self::names_match(&self.segment_names(), &pattern.segment_names())
+            },
+            // trait methods are only matched by qualified patterns
+            (Some(qualified_self), None) if qualified_self.as_trait.is_some() => false,
+            (_, None) => Self::names_match(&self.names(), &pattern.names()),
+            (None, Some(_)) => false
+        }
+
+    }
+
+    /// Whether this is drop glue (`drop_in_place::<T>`) for a type owning a value whose path
+    /// matches `pattern`, e.g. the guard itself, `Option<Guard>` or a tuple holding one, or the
+    /// `Drop::drop` of such a type. Values behind references are not dropped.
+    pub fn drops(&self, pattern : &RustPath) -> bool {
+
+        let owns = |dropped : &RustType| dropped.owns_path(&|inner : &RustPath| inner.matches(pattern));
+
+        if self.is(&["core", "ptr", "drop_in_place"]) {
+            return self.last_generics().iter().any(owns);
+        }
+
+        if self.is_trait_method(&["core", "ops", "drop", "Drop"], "drop") {
+            return self.self_type().map(owns).unwrap_or(false);
+        }
+
+        false
+
+    }
+
+    fn segment_names(&self) -> Vec<&str> {
+        self.segments.iter().map(|segment| segment.name.as_str()).collect()
+    }
+
+    fn names_match(names : &[&str], pattern : &[&str]) -> bool {
+
+        names.len() == pattern.len()
+        && names.iter().zip(pattern).all(|(name, pattern_name)| {
+            *pattern_name == "_"
+            || name == pattern_name
+            || (*pattern_name == "{{closure}}" && name.starts_with("{closure#"))
+        })
+
+    }
+
+    fn type_matches(self_type : &RustType, pattern : &RustType) -> bool {
+
+        match pattern {
+            RustType::Path(pattern_path) if pattern_path.is(&["_"]) => true,
+            RustType::Path(pattern_path) => match self_type {
+                RustType::Path(path) => path.matches(pattern_path),
+                _ => false
+            },
+            _ => self_type == pattern
+        }
+
+    }
+
+    pub fn last_generics(&self) -> &[RustType] {
+        self.segments.last().map(|segment| segment.generics.as_slice()).unwrap_or_default()
+    }
+
+    /// The path with generic arguments erased, e.g. `std::sync::mutex::Mutex::lock`
+    /// or `<alloc::sync::Arc as core::clone::Clone>::clone`.
+    pub fn without_generics(&self) -> String {
+
+        let names = self.segment_names().join("::");
+        match &self.qualified_self {
+            Some(qualified_self) => {
+                let self_type = match &qualified_self.self_type {
+                    RustType::Path(path) => path.without_generics(),
+                    other => other.to_string()
+                };
+                let prefix = match &qualified_self.as_trait {
+                    Some(as_trait) => format!("<{} as {}>", self_type, as_trait.without_generics()),
+                    None => format!("<{}>", self_type)
+                };
+                if names.is_empty() { prefix } else { format!("{}::{}", prefix, names) }
+            },
+            None => names
+        }
+
+    }
+
+}
+
+impl RustType {
+
+    /// Whether this type mentions a path satisfying `predicate` anywhere, including in generic
+    /// arguments, tuple fields, element types and behind references.
+    pub fn contains_path<F>(&self, predicate : &F) -> bool
+        where F : Fn(&RustPath) -> bool
+    {
+
+        match self {
+            RustType::Path(path) => {
+                predicate(path)
+                || path.segments.iter().any(|segment| segment.generics.iter().any(|generic| generic.contains_path(predicate)))
+                || path.qualified_self.as_ref().map(|qualified_self| qualified_self.self_type.contains_path(predicate)).unwrap_or(false)
+            },
+            RustType::Reference { inner, .. } | RustType::Pointer { inner, .. } | RustType::Slice(inner) | RustType::Array(inner, _) => {
+                inner.contains_path(predicate)
+            },
+            RustType::Tuple(types) | RustType::Dyn(types) => {
+                types.iter().any(|inner| inner.contains_path(predicate))
+            },
+            RustType::Opaque(_) => false
+        }
+
+    }
+
+    /// Like `contains_path`, but does not look behind references or raw pointers,
+    /// i.e. only considers values that are owned (and therefore dropped) by this type.
+    pub fn owns_path<F>(&self, predicate : &F) -> bool
+        where F : Fn(&RustPath) -> bool
+    {
+
+        match self {
+            RustType::Path(path) => {
+                predicate(path)
+                || path.segments.iter().any(|segment| segment.generics.iter().any(|generic| generic.owns_path(predicate)))
+            },
+            RustType::Slice(inner) | RustType::Array(inner, _) => inner.owns_path(predicate),
+            RustType::Tuple(types) => types.iter().any(|inner| inner.owns_path(predicate)),
+            RustType::Reference { .. } | RustType::Pointer { .. } | RustType::Dyn(_) | RustType::Opaque(_) => false
+        }
+
+    }
+
+}
+
+impl fmt::Display for RustPath {
+
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        if let Some(qualified_self) = &self.qualified_self {
+            match &qualified_self.as_trait {
+                Some(as_trait) => write!(f, "<{} as {}>", qualified_self.self_type, as_trait)?,
+                None => write!(f, "<{}>", qualified_self.self_type)?
+            }
+            if !self.segments.is_empty() {
+                write!(f, "::")?;
+            }
+        }
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                write!(f, "::")?;
+            }
+            write!(f, "{}", segment.name)?;
+            if !segment.generics.is_empty() {
+                write!(f, "<{}>", Self::join(&segment.generics))?;
+            }
+        }
+        Ok(())
+
+    }
+
+}
+
+impl fmt::Display for RustType {
+
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+            RustType::Path(path) => write!(f, "{}", path),
+            RustType::Reference { mutable, inner } => write!(f, "&{}{}", if *mutable { "mut " } else { "" }, inner),
+            RustType::Pointer { mutable, inner } => write!(f, "*{} {}", if *mutable { "mut" } else { "const" }, inner),
+            RustType::Tuple(types) if types.len() == 1 => write!(f, "({},)", types[0]),
+            RustType::Tuple(types) => write!(f, "({})", RustPath::join(types)),
+            RustType::Array(inner, len) => write!(f, "[{}; {}]", inner, len),
+            RustType::Slice(inner) => write!(f, "[{}]", inner),
+            RustType::Dyn(bounds) => write!(f, "dyn {}", bounds.iter().map(|bound| bound.to_string()).collect::<Vec<_>>().join(" + ")),
+            RustType::Opaque(text) => write!(f, "{}", text)
+        }
+
+    }
+
+}
+
+/// Recursive descent over the demangler's output.
+struct Parser<'a> {
+    input : &'a str,
+    position : usize
+}
+
+impl <'a> Parser<'a> {
+
+    fn new(input : &'a str) -> Self {
+        Self {
+            input,
+            position : 0
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.rest().trim().is_empty()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token : &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token : &str) -> Result<(), anyhow::Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Expected `{}` at `{}` in demangled path: {}", token, self.rest(), self.input))
+        }
+    }
+
+    fn path(&mut self) -> Result<RustPath, anyhow::Error> {
+
+        let mut path = RustPath {
+            qualified_self : None,
+            segments : Vec::new()
+        };
+
+        if self.eat("<") {
+            let self_type = self.rust_type()?;
+            self.skip_whitespace();
+            let as_trait = if self.eat("as ") {
+                self.skip_whitespace();
+                Some(self.path()?)
+            } else {
+                None
+            };
+            self.expect(">")?;
+            path.qualified_self = Some(Box::new(QualifiedSelf {
+                self_type,
+                as_trait
+            }));
+            if !self.eat("::") {
+                return Ok(path);
+            }
+        }
+
+        loop {
+
+            // turbofish generics apply to the previous segment (`drop_in_place::<T>`)
+            if self.rest().starts_with('<') && !path.segments.is_empty() {
+                let generics = self.generics()?;
+                path.segments.last_mut().expect("checked non-empty").generics = generics;
+            } else {
+                let name = self.segment_name()?;
+                let generics = if self.rest().starts_with('<') { self.generics()? } else { Vec::new() };
+                path.segments.push(PathSegment {
+                    name,
+                    generics
+                });
+            }
+
+            if !self.eat("::") {
+                return Ok(path);
+            }
+
+        }
+
+    }
+
+    fn segment_name(&mut self) -> Result<String, anyhow::Error> {
+
+        let rest = self.rest();
+
+        // `{{closure}}`, `{shim:vtable#0}` and friends may contain `:`
+        let len = if rest.starts_with('{') {
+            let mut depth = 0;
+            let mut end = rest.len();
+            for (i, c) in rest.char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = i + 1;
+                            break;
+                        }
+                    },
+                    _ => {}
+                }
+            }
+            end
+        } else {
+            rest.find(|c : char| ":<>,()[];&*+ ".contains(c)).unwrap_or(rest.len())
+        };
+
+        if len == 0 {
+            return Err(anyhow::anyhow!("Expected a path segment at `{}` in demangled path: {}", rest, self.input));
+        }
+
+        self.position += len;
+        Ok(rest[..len].to_string())
+
+    }
+
+    fn generics(&mut self) -> Result<Vec<RustType>, anyhow::Error> {
+
+        self.expect("<")?;
+        let mut generics = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat(">") {
+                return Ok(generics);
+            }
+            if self.peek() == Some('\'') {
+                self.lifetime();
+            } else {
+                generics.push(self.rust_type()?);
+            }
+            self.skip_whitespace();
+            if !self.eat(",") {
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(generics);
+            }
+        }
+
+    }
+
+    fn lifetime(&mut self) {
+        self.eat("'");
+        let rest = self.rest();
+        let len = rest.find(|c : char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        self.position += len;
+    }
+
+    fn rust_type(&mut self) -> Result<RustType, anyhow::Error> {
+
+        self.skip_whitespace();
+
+        if self.eat("&") {
+            self.skip_whitespace();
+            if self.peek() == Some('\'') {
+                self.lifetime();
+                self.skip_whitespace();
+            }
+            let mutable = self.eat("mut ");
+            let inner = self.rust_type()?;
+            return Ok(RustType::Reference { mutable, inner : Box::new(inner) });
+        }
+
+        if self.eat("*") {
+            let mutable = if self.eat("mut ") {
+                true
+            } else {
+                self.expect("const ")?;
+                false
+            };
+            let inner = self.rust_type()?;
+            return Ok(RustType::Pointer { mutable, inner : Box::new(inner) });
+        }
+
+        if self.eat("(") {
+            let mut types = Vec::new();
+            loop {
+                self.skip_whitespace();
+                if self.eat(")") {
+                    return Ok(RustType::Tuple(types));
+                }
+                types.push(self.rust_type()?);
+                self.skip_whitespace();
+                if !self.eat(",") {
+                    self.expect(")")?;
+                    return Ok(RustType::Tuple(types));
+                }
+            }
+        }
+
+        if self.eat("[") {
+            let inner = self.rust_type()?;
+            self.skip_whitespace();
+            if self.eat(";") {
+                let len = self.opaque();
+                self.expect("]")?;
+                return Ok(RustType::Array(Box::new(inner), len.trim().to_string()));
+            }
+            self.expect("]")?;
+            return Ok(RustType::Slice(Box::new(inner)));
+        }
+
+        if self.eat("dyn ") {
+            let mut bounds = Vec::new();
+            loop {
+                self.skip_whitespace();
+                if self.peek() == Some('\'') {
+                    self.lifetime();
+                } else {
+                    bounds.push(RustType::Path(self.path()?));
+                }
+                self.skip_whitespace();
+                if !self.eat("+") {
+                    return Ok(RustType::Dyn(bounds));
+                }
+            }
+        }
+
+        let rest = self.rest();
+        let is_opaque = rest.starts_with('!')
+            || rest.starts_with("fn(")
+            || rest.starts_with("unsafe ")
+            || rest.starts_with("extern ")
+            || rest.starts_with("impl ")
+            || rest.starts_with(|c : char| c.is_ascii_digit() || c == '-' || c == '"');
+        if is_opaque {
+            return Ok(RustType::Opaque(self.opaque().trim().to_string()));
+        }
+
+        Ok(RustType::Path(self.path()?))
+
+    }
+
+    /// Consumes balanced text up to the next delimiter at depth zero.
+    fn opaque(&mut self) -> String {
+
+        let rest = self.rest();
+        let mut depth = 0i32;
+        let mut end = rest.len();
+        let mut previous = ' ';
+        for (i, c) in rest.char_indices() {
+            match c {
+                '<' | '(' | '[' => depth += 1,
+                // `->` in fn pointer signatures is not a closing bracket
+                '>' if previous == '-' => {},
+                '>' | ')' | ']' | ',' | ';' if depth == 0 => {
+                    end = i;
+                    break;
+                },
+                '>' | ')' | ']' => depth -= 1,
+                _ => {}
+            }
+            previous = c;
+        }
+
+        self.position += end;
+        rest[..end].to_string()
+
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_parses_drop_in_place_of_guard() {
+
+        let path = RustPath::parse(
+            "core::ptr::drop_in_place<std::sync::mutex::MutexGuard<i32>>::h5b1e0a07d8f5ea3c"
+        ).unwrap();
+
+        assert!(path.is(&["core", "ptr", "drop_in_place"]));
+        assert_eq!(path.last_generics().len(), 1);
+        assert!(path.last_generics()[0].contains_path(&|inner : &RustPath| {
+            inner.is(&["std", "sync", "mutex", "MutexGuard"])
+        }));
+
+    }
+
+    #[test]
+    fn test_skips_lifetimes_and_parses_nested_generics() {
+
+        let path = RustPath::parse(
+            "core::ptr::drop_in_place::<core::option::Option<std::sync::mutex::MutexGuard<'_, std::collections::hash::map::HashMap<u64, (alloc::string::String, [u8; 4])>>>>"
+        ).unwrap();
+
+        assert!(path.is(&["core", "ptr", "drop_in_place"]));
+        let guard = &path.last_generics()[0];
+        assert!(guard.owns_path(&|inner : &RustPath| inner.is(&["std", "sync", "mutex", "MutexGuard"])));
+        assert!(guard.contains_path(&|inner : &RustPath| inner.is(&["alloc", "string", "String"])));
+
+    }
+
+    #[test]
+    fn test_references_are_not_owned() {
+
+        let path = RustPath::parse(
+            "core::ptr::drop_in_place<(&std::sync::mutex::MutexGuard<i32>, alloc::vec::Vec<u8>)>"
+        ).unwrap();
+
+        let tuple = &path.last_generics()[0];
+        assert!(tuple.contains_path(&|inner : &RustPath| inner.is(&["std", "sync", "mutex", "MutexGuard"])));
+        assert!(!tuple.owns_path(&|inner : &RustPath| inner.is(&["std", "sync", "mutex", "MutexGuard"])));
+
+    }
+
+    #[test]
+    fn test_parses_qualified_paths_and_closures() {
+
+        let path = RustPath::parse(
+            "<alloc::sync::Arc<std::sync::mutex::Mutex<i32>> as core::clone::Clone>::clone"
+        ).unwrap();
+        assert_eq!(path.without_generics(), "<alloc::sync::Arc as core::clone::Clone>::clone");
+
+        let path = RustPath::parse("tokio::sync::mutex::Mutex<T>::lock::{{closure}}").unwrap();
+        assert!(path.is(&["tokio", "sync", "mutex", "Mutex", "lock", "{{closure}}"]));
+        assert_eq!(path.without_generics(), "tokio::sync::mutex::Mutex::lock::{{closure}}");
+
+        let path = RustPath::parse(
+            "<alloc::boxed::Box<dyn core::ops::function::FnOnce<()> + core::marker::Send> as core::ops::function::FnOnce<()>>::call_once::{shim:vtable#0}"
+        ).unwrap();
+        assert_eq!(path.names(), vec!["call_once", "{shim:vtable#0}"]);
+        assert_eq!(path.without_generics(), "<alloc::boxed::Box as core::ops::function::FnOnce>::call_once::{shim:vtable#0}");
+
+    }
+
+    #[test]
+    fn test_folds_inherent_qualified_self() {
+
+        let path = RustPath::parse(
+            "<lock_api::mutex::Mutex<parking_lot::raw_mutex::RawMutex, i32>>::lock"
+        ).unwrap();
+        assert!(path.is(&["lock_api", "mutex", "Mutex", "lock"]));
+
+        let path = RustPath::parse(
+            "<lock_api::mutex::MutexGuard<parking_lot::raw_mutex::RawMutex, i32> as core::ops::drop::Drop>::drop"
+        ).unwrap();
+        assert!(path.is(&["drop"]));
+        assert!(path.is_trait_method(&["core", "ops", "drop", "Drop"], "drop"));
+
+    }
+
+    #[test]
+    fn test_matches_patterns() {
+
+        let pattern = RustPath::parse("<_ as core::future::into_future::IntoFuture>::into_future").unwrap();
+        let path = RustPath::parse("<F as core::future::into_future::IntoFuture>::into_future::h0123456789abcdef").unwrap();
+        assert!(path.matches(&pattern));
+
+        let pattern = RustPath::parse("tokio::sync::mutex::Mutex::lock::{{closure}}").unwrap();
+        assert!(RustPath::parse("<tokio::sync::mutex::Mutex<i32>>::lock::{closure#0}").unwrap().matches(&pattern));
+        assert!(!RustPath::parse("tokio::sync::mutex::Mutex<T>::lock").unwrap().matches(&pattern));
+        assert!(!RustPath::parse("tokio::sync::mutex::Mutex<T>::lock::{{closure}}::{{closure}}").unwrap().matches(&pattern));
+
+        let pattern = RustPath::parse("std::sync::mutex::MutexGuard").unwrap();
+        assert!(RustPath::parse("core::ptr::drop_in_place<core::option::Option<std::sync::mutex::MutexGuard<u8>>>").unwrap().drops(&pattern));
+        assert!(RustPath::parse("<std::sync::mutex::MutexGuard<T> as core::ops::drop::Drop>::drop").unwrap().drops(&pattern));
+        assert!(!RustPath::parse("core::ptr::drop_in_place<&std::sync::mutex::MutexGuard<u8>>").unwrap().drops(&pattern));
+
+    }
+
+    #[test]
+    fn test_fn_pointers_are_opaque() {
+
+        let path = RustPath::parse(
+            "core::ops::function::FnOnce::call_once<fn() -> core::result::Result<(), std::io::error::Error>, ()>"
+        ).unwrap();
+
+        assert_eq!(path.last_generics().len(), 2);
+        assert_eq!(path.last_generics()[0], RustType::Opaque("fn() -> core::result::Result<(), std::io::error::Error>".to_string()));
+
+    }
+
+}
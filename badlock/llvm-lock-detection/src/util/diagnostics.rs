@@ -0,0 +1,28 @@
+use std::ffi::{c_char, CString};
+use llvm_plugin::inkwell::llvm_sys::LLVMDiagnosticSeverity;
+use llvm_plugin::inkwell::llvm_sys::prelude::LLVMModuleRef;
+use llvm_plugin::inkwell::module::Module;
+use lock_detection::report::Severity;
+
+extern "C" {
+    // cpp/diagnostics.cc
+    fn badlock_diagnose(module : LLVMModuleRef, message : *const c_char, severity : LLVMDiagnosticSeverity);
+}
+
+/// Reports `message` about `module` through the diagnostic handler of its context. `opt` installs
+/// none, so LLVM prints `error: <module>: <message>` and exits with 1 on the first error.
+pub fn diagnose(module : &Module, message : &str, severity : Severity) {
+
+    let severity = match severity {
+        Severity::Warning => LLVMDiagnosticSeverity::LLVMDSWarning,
+        Severity::Error => LLVMDiagnosticSeverity::LLVMDSError
+    };
+
+    // symbols are printed from IR, which may quote names containing NUL
+    let message = CString::new(message.replace('\0', "\\00")).unwrap_or_default();
+
+    unsafe {
+        badlock_diagnose(module.as_mut_ptr(), message.as_ptr(), severity);
+    }
+
+}
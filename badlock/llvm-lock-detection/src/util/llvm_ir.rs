@@ -1,40 +1,208 @@
-use llvm_plugin::inkwell::basic_block::{BasicBlock, self};
-use regex::Regex;
+use llvm_plugin::inkwell::basic_block::BasicBlock;
 use llvm_plugin::inkwell::values::{InstructionValue, InstructionOpcode, AsValueRef, BasicValueEnum};
 use llvm_plugin::utils::InstructionIterator;
+use llvm_plugin::inkwell::llvm_sys::core::{
+    LLVMAliasGetAliasee, LLVMCountParams, LLVMDisposeMessage, LLVMGetBasicBlockParent, LLVMGetCalledValue,
+    LLVMGetConstOpcode, LLVMGetInstructionParent, LLVMGetNumSuccessors, LLVMGetOperand, LLVMGetParam,
+    LLVMGetParamParent, LLVMGetSuccessor, LLVMGetValueName2, LLVMIsAArgument, LLVMIsAConstantExpr,
+    LLVMIsAFunction, LLVMIsAGlobalAlias, LLVMIsAGlobalValue, LLVMIsAInlineAsm, LLVMIsAInstruction,
+    LLVMPrintValueToString, LLVMGetNumArgOperands, LLVMGetCallSiteEnumAttribute,
+    LLVMGetEnumAttributeKindForName, LLVMIsAGetElementPtrInst, LLVMIsALoadInst, LLVMIsAConstantInt,
+    LLVMConstIntGetSExtValue, LLVMGetGEPSourceElementType, LLVMPrintTypeToString, LLVMGetNumOperands,
+    LLVMGetGlobalParent, LLVMGetTypeKind, LLVMGetAllocatedType, LLVMGetFirstUse, LLVMGetNextUse, LLVMGetUser,
+    LLVMIsAAllocaInst, LLVMIsAICmpInst, LLVMIsAStoreInst, LLVMIsATruncInst, LLVMStructGetTypeAtIndex, LLVMGetElementType, LLVMGetTypeAttributeValue,
+    LLVMGetICmpPredicate, LLVMGetNextInstruction, LLVMIsABranchInst, LLVMIsASwitchInst, LLVMIsConditional, LLVMIsNull,
+    LLVMTypeOf,
+};
+use llvm_plugin::inkwell::llvm_sys::target::{
+    LLVMGetModuleDataLayout, LLVMABISizeOfType, LLVMOffsetOfElement, LLVMTargetDataRef,
+};
+use llvm_plugin::inkwell::llvm_sys::LLVMTypeKind;
+use llvm_plugin::inkwell::llvm_sys::{LLVMIntPredicate, LLVMOpcode};
+use llvm_plugin::inkwell::llvm_sys::prelude::{LLVMAttributeRef, LLVMBasicBlockRef, LLVMValueRef};
+use llvm_plugin::inkwell::module::Module;
+use llvm_plugin::inkwell::values::FunctionValue;
 use rustc_demangle::demangle;
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::fmt;
+use crate::util::debug_info::SourceVariables;
+use crate::util::demangled_path::RustPath;
+
+/// What a call or invoke transfers control to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Callee<'ctx> {
+    Direct(FunctionValue<'ctx>),
+    Intrinsic(FunctionValue<'ctx>),
+    Indirect(BasicValueEnum<'ctx>),
+    InlineAsm
+}
 
-pub struct InstructionCallSites;
+impl <'ctx> Callee<'ctx> {
+
+    pub fn function(&self) -> Option<FunctionValue<'ctx>> {
+        match self {
+            Callee::Direct(function) | Callee::Intrinsic(function) => Some(*function),
+            _ => None
+        }
+    }
+
+}
 
+/// A raw value handed out by the C API, e.g. a call argument, so it can be used wherever inkwell
+/// expects a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawValue(pub LLVMValueRef);
+
+unsafe impl AsValueRef for RawValue {
+    fn as_value_ref(&self) -> LLVMValueRef {
+        self.0
+    }
+}
+
+pub struct InstructionCallSites;
 
 impl InstructionCallSites {
 
-    pub fn called_function_name_from_str(instr_str : &str) -> Result<String, anyhow::Error> {
+    /// Resolves the called operand (the last operand of a call, invoke or callbr),
+    /// looking through pointer casts and global aliases.
+    pub fn callee<'ctx>(instr : &InstructionValue<'ctx>) -> Result<Callee<'ctx>, anyhow::Error> {
 
-        let re = Regex::new(r"(call|invoke) [^@]+@([^(\s]+)").unwrap();
+        match instr.get_opcode() {
+            InstructionOpcode::Call | InstructionOpcode::Invoke | InstructionOpcode::CallBr => {},
+            _ => {
+                return Err(anyhow::anyhow!("Instruction is not a call instruction"));
+            }
+        }
+
+        let called = Self::strip_pointer_casts_and_aliases(
+            unsafe { LLVMGetCalledValue(instr.as_value_ref()) }
+        );
+
+        unsafe {
+
+            if !LLVMIsAInlineAsm(called).is_null() {
+                return Ok(Callee::InlineAsm);
+            }
+
+            if !LLVMIsAFunction(called).is_null() {
+                let function = FunctionValue::new(called)
+                .ok_or_else(|| anyhow::anyhow!("Called function should be a valid function value"))?;
+                if function.get_intrinsic_id() != 0 {
+                    return Ok(Callee::Intrinsic(function));
+                }
+                return Ok(Callee::Direct(function));
+            }
+
+        }
+
+        let called_operand = instr.get_operand(instr.get_num_operands() - 1)
+        .and_then(|operand| operand.left())
+        .ok_or_else(|| anyhow::anyhow!("Call should have a called operand"))?;
+
+        Ok(Callee::Indirect(called_operand))
+
+    }
 
-        if let Some(caps) = re.captures(instr_str) {
-            if let Some(function_name) = caps.get(2) {
-                // Trim double quotes if present
-                let trimmed_name = function_name.as_str().trim_matches(|c: char| !c.is_alphanumeric());
-                return Ok(trimmed_name.to_string());
+    pub fn strip_pointer_casts_and_aliases(value : LLVMValueRef) -> LLVMValueRef {
+
+        let mut current = value;
+        loop {
+            unsafe {
+                if !LLVMIsAConstantExpr(current).is_null() && matches!(
+                    LLVMGetConstOpcode(current),
+                    LLVMOpcode::LLVMBitCast | LLVMOpcode::LLVMAddrSpaceCast
+                ) {
+                    current = LLVMGetOperand(current, 0);
+                } else if !LLVMIsAGlobalAlias(current).is_null() {
+                    let aliasee = LLVMAliasGetAliasee(current);
+                    // an alias cycle is invalid IR, but do not spin on it
+                    if aliasee == value || aliasee == current {
+                        return current;
+                    }
+                    current = aliasee;
+                } else {
+                    return current;
+                }
             }
-        } 
-    
-        Err(anyhow::anyhow!("Could not parse function name from instruction: {}", instr_str))
-    
+        }
+
+    }
+
+    /// The arguments of a call, without the called operand (and, for invokes, the successors).
+    pub fn arguments(instr : &InstructionValue) -> Vec<RawValue> {
+
+        let instr_ref = instr.as_value_ref();
+        let count = unsafe { LLVMGetNumArgOperands(instr_ref) };
+        (0..count).map(|index| RawValue(unsafe { LLVMGetOperand(instr_ref, index) })).collect()
+
+    }
+
+    /// Whether the first argument is an `sret` out pointer the callee writes its result to.
+    pub fn has_sret(instr : &InstructionValue) -> bool {
+        Self::sret_attribute(instr).is_some()
+    }
+
+    /// Bytes the callee writes through its `sret` out pointer, per the module's data layout.
+    pub fn sret_size(instr : &InstructionValue) -> Option<u64> {
+
+        let attribute = Self::sret_attribute(instr)?;
+
+        unsafe {
+            let data_layout = module_data_layout(instr.as_value_ref());
+            Some(LLVMABISizeOfType(data_layout, LLVMGetTypeAttributeValue(attribute)))
+        }
+
+    }
+
+    fn sret_attribute(instr : &InstructionValue) -> Option<LLVMAttributeRef> {
+
+        if Self::arguments(instr).is_empty() {
+            return None;
+        }
+
+        let sret = "sret";
+        let attribute = unsafe {
+            let kind = LLVMGetEnumAttributeKindForName(sret.as_ptr() as *const c_char, sret.len());
+            // attribute index 0 is the return value, arguments start at 1
+            LLVMGetCallSiteEnumAttribute(instr.as_value_ref(), 1, kind)
+        };
+
+        if attribute.is_null() {
+            None
+        } else {
+            Some(attribute)
+        }
+
+    }
+
+    /// The value a call produces: its `sret` out pointer if it has one, the call itself otherwise.
+    pub fn result(instr : &InstructionValue) -> RawValue {
+
+        if Self::has_sret(instr) {
+            Self::arguments(instr)[0]
+        } else {
+            RawValue(instr.as_value_ref())
+        }
+
+    }
+
+    /// The arguments the callee's Rust signature declares, i.e. without an `sret` out pointer.
+    pub fn source_arguments(instr : &InstructionValue) -> Vec<RawValue> {
+
+        let mut arguments = Self::arguments(instr);
+        if Self::has_sret(instr) {
+            arguments.remove(0);
+        }
+        arguments
+
     }
 
     pub fn called_function_name(instr : &InstructionValue) -> Result<String, anyhow::Error> {
 
-        match instr.get_opcode() {
-            InstructionOpcode::Call | InstructionOpcode::Invoke => { // we don't care about error handling, so these can be the same thing
-                let instr_str = instr.to_string();
-                Self::called_function_name_from_str(&instr_str)
-            },
-            _ => {
-                Err(anyhow::anyhow!("Instruction is not a call instruction"))
-            }
+        match Self::callee(instr)?.function() {
+            Some(function) => Ok(function.get_name().to_string_lossy().into_owned()),
+            None => Err(anyhow::anyhow!("Instruction does not call a known function"))
         }
 
     }
@@ -47,274 +215,640 @@ impl InstructionCallSites {
 
     }
 
- 
+    /// Parses the demangled callee, e.g. `std::sync::mutex::Mutex<T>::lock`, without its hash.
+    pub fn demangled_called_path(instr : &InstructionValue) -> Result<RustPath, anyhow::Error> {
+
+        let function_name = Self::called_function_name(instr)?;
+
+        RustPath::parse(&format!("{:#}", demangle(&function_name)))
+
+    }
 
 }
 
-pub enum KnownCallsites {
-    StdSyncMutexLock,
-    StdSyncMutexNew,
-    StdSyncMutexDrop,
-    Unknown
+/// Control flow graph of a single function, read off the successors of each block's terminator.
+///
+/// `LLVMGetSuccessor` covers every terminator with successors (`br`, `switch`, `invoke`,
+/// `indirectbr`, `callbr`, `cleanupret`, `catchswitch`, `catchret`), which inkwell does not expose.
+#[derive(Debug, Clone)]
+pub struct Cfg<'ctx> {
+    blocks : HashMap<LLVMBasicBlockRef, BasicBlock<'ctx>>,
+    successors : HashMap<BasicBlock<'ctx>, Vec<BasicBlock<'ctx>>>,
+    predecessors : HashMap<BasicBlock<'ctx>, Vec<BasicBlock<'ctx>>>
 }
 
-impl KnownCallsites {
+impl <'ctx> Cfg<'ctx> {
+
+    pub fn from_function(function : FunctionValue<'ctx>) -> Self {
+
+        let basic_blocks = function.get_basic_blocks();
 
-    pub fn is_std_sync_mutex_lock_call(call_str : &str) -> bool {
+        // inkwell cannot wrap a raw block reference, so resolve successors through the function's own blocks
+        let by_ref : HashMap<LLVMBasicBlockRef, BasicBlock<'ctx>> = basic_blocks.iter()
+        .map(|basic_block| (basic_block.as_mut_ptr(), *basic_block))
+        .collect();
+
+        let mut successors = HashMap::new();
+        let mut predecessors : HashMap<BasicBlock<'ctx>, Vec<BasicBlock<'ctx>>> = HashMap::new();
+
+        for basic_block in basic_blocks.iter() {
+
+            predecessors.entry(*basic_block).or_default();
+
+            let mut block_successors = Vec::new();
+            if let Some(terminator) = basic_block.get_terminator() {
+                for successor_ref in Self::terminator_successors(&terminator) {
+                    if let Some(successor) = by_ref.get(&successor_ref) {
+                        if !block_successors.contains(successor) {
+                            block_successors.push(*successor);
+                        }
+                    }
+                }
+            }
+
+            for successor in block_successors.iter() {
+                predecessors.entry(*successor).or_default().push(*basic_block);
+            }
+
+            successors.insert(*basic_block, block_successors);
+
+        }
+
+        Self {
+            blocks : by_ref,
+            successors,
+            predecessors
+        }
 
-        call_str.starts_with("std::sync::mutex::Mutex<T>::lock")
-     
     }
 
-    pub fn is_std_sync_mutex_lock_call_site(instr : &InstructionValue) -> Result<bool, anyhow::Error> {
+    pub fn terminator_successors(terminator : &InstructionValue) -> Vec<LLVMBasicBlockRef> {
+
+        let terminator_ref = terminator.as_value_ref();
+        unsafe {
+            (0..LLVMGetNumSuccessors(terminator_ref))
+            .map(|index| LLVMGetSuccessor(terminator_ref, index))
+            .collect()
+        }
 
-        let call_str = InstructionCallSites::demangled_called_function_name(instr)?;
-        Ok(Self::is_std_sync_mutex_lock_call(&call_str))
-    
     }
 
-    pub fn is_std_sync_mutex_new_call(call_str : &str) -> bool {
+    pub fn successors(&self, basic_block : &BasicBlock<'ctx>) -> &[BasicBlock<'ctx>] {
+        self.successors.get(basic_block).map(Vec::as_slice).unwrap_or_default()
+    }
 
-        call_str.starts_with("std::sync::mutex::Mutex<T>::new")
-     
+    pub fn predecessors(&self, basic_block : &BasicBlock<'ctx>) -> &[BasicBlock<'ctx>] {
+        self.predecessors.get(basic_block).map(Vec::as_slice).unwrap_or_default()
     }
 
-    pub fn is_std_sync_mutex_new_call_site(instr : &InstructionValue) -> Result<bool, anyhow::Error> {
+    /// The block of the function a raw block reference refers to.
+    pub fn block(&self, basic_block : LLVMBasicBlockRef) -> Option<BasicBlock<'ctx>> {
+        self.blocks.get(&basic_block).copied()
+    }
+
+}
+
+pub struct Predecessors;
+
+impl Predecessors {
+
+    /// The instructions control can come from: the previous instruction in the block or,
+    /// for the first instruction of a block, the terminators of the block's CFG predecessors.
+    pub fn get_predecessors<'ctx>(instruction : InstructionValue<'ctx>, cfg : &Cfg<'ctx>) -> Vec<InstructionValue<'ctx>> {
+
+        if let Some(prev_inst) = instruction.get_previous_instruction() {
+            return vec![prev_inst];
+        }
+
+        match instruction.get_parent() {
+            Some(block) => {
+                cfg.predecessors(&block).iter()
+                .filter_map(|predecessor| predecessor.get_terminator())
+                .collect()
+            },
+            None => Vec::new()
+        }
 
-        let call_str = InstructionCallSites::demangled_called_function_name(instr)?;
-        Ok(Self::is_std_sync_mutex_new_call(&call_str))
-    
     }
 
-    pub fn is_std_sync_mutex_drop(call_str : &str) -> bool {
+}
+
+pub struct Var;
+
+impl Var {
+
+    /// Names a value the way it is spelled in the IR (`%safe_x`, `%_3`, `@GLOBAL`), without any per-run address.
+    pub fn get_ir_name(value : &impl AsValueRef) -> String {
+
+        let value_ref = value.as_value_ref();
+
+        let mut len = 0;
+        let name_ptr = unsafe { LLVMGetValueName2(value_ref, &mut len) };
+        if !name_ptr.is_null() && len > 0 {
+            let bytes = unsafe { std::slice::from_raw_parts(name_ptr as *const u8, len) };
+            let sigil = if unsafe { LLVMIsAGlobalValue(value_ref) }.is_null() { "%" } else { "@" };
+            return format!("{}{}", sigil, String::from_utf8_lossy(bytes));
+        }
+
+        // unnamed values only have their slot number, which is only visible in the printed form
+        let printed = unsafe {
+            let printed_ptr = LLVMPrintValueToString(value_ref);
+            let printed = CStr::from_ptr(printed_ptr).to_string_lossy().into_owned();
+            LLVMDisposeMessage(printed_ptr);
+            printed
+        };
+
+        match printed.split_once(" = ") {
+            Some((lhs, _)) => lhs.trim().to_string(),
+            None => printed.split_whitespace().last().unwrap_or_default().to_string()
+        }
 
-        call_str.starts_with("core::ptr::drop_in_place<std::sync::mutex::MutexGuard<i32>>::")
-     
     }
 
-    pub fn is_std_sync_mutex_drop_call_site(instr : &InstructionValue) -> Result<bool, anyhow::Error> {
+    /// Demangled name (without hash) of the function a local value belongs to.
+    pub fn get_parent_function_name(value : &impl AsValueRef) -> Option<String> {
+
+        let value_ref = value.as_value_ref();
+
+        let function_ref = unsafe {
+            if !LLVMIsAInstruction(value_ref).is_null() {
+                LLVMGetBasicBlockParent(LLVMGetInstructionParent(value_ref))
+            } else if !LLVMIsAArgument(value_ref).is_null() {
+                LLVMGetParamParent(value_ref)
+            } else {
+                return None;
+            }
+        };
+
+        let function = unsafe { FunctionValue::new(function_ref) }?;
+        let name = function.get_name().to_string_lossy();
+        Some(format!("{:#}", demangle(&name)))
 
-        let call_str = InstructionCallSites::demangled_called_function_name(instr)?;
-        Ok(Self::is_std_sync_mutex_drop(&call_str))
-    
     }
 
-    pub fn try_from_instr(instr : &InstructionValue) -> Result<Self, anyhow::Error> {
+    /// Names a lock resource after the source variable debug info declares for it,
+    /// falling back to the IR name qualified by its function.
+    pub fn get_resource_name(value : &impl AsValueRef, source_variables : &SourceVariables) -> String {
 
-        let call_str = InstructionCallSites::demangled_called_function_name(instr)?;
+        if let Some(variable) = source_variables.get(value) {
+            return variable.to_string();
+        }
 
-        if Self::is_std_sync_mutex_lock_call(&call_str) {
-            return Ok(Self::StdSyncMutexLock);
-        } else if Self::is_std_sync_mutex_new_call(&call_str) {
-            return Ok(Self::StdSyncMutexNew);
-        } else if Self::is_std_sync_mutex_drop(&call_str) {
-            return Ok(Self::StdSyncMutexDrop);
+        let ir_name = Self::get_ir_name(value);
+        match Self::get_parent_function_name(value) {
+            Some(function_name) => format!("{} in {}", ir_name, function_name),
+            None => ir_name
         }
 
-        Ok(Self::Unknown)
+    }
+
+}
 
+/// Position of an instruction that does not depend on where LLVM allocated it:
+/// the (mangled) function name, the index of its basic block and its index within that block.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstructionId {
+    pub function : String,
+    pub block : usize,
+    pub index : usize
+}
+
+impl fmt::Display for InstructionId {
+
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#} bb{} #{}", demangle(&self.function), self.block, self.index)
     }
 
-    pub fn from_instr(instr : &InstructionValue) -> Self {
+}
 
-        Self::try_from_instr(instr).unwrap_or(Self::Unknown)
+/// Identity of an IR value that is stable across runs on the same module.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ValueId {
+    Instruction(InstructionId),
+    Argument { function : String, index : u32 },
+    Global(String),
+    Constant(String),
+    // a constant offset from another location, e.g. a field of an async fn's state
+    Field { base : Box<ValueId>, element_type : String, indices : Vec<i64> },
+    // whatever is stored at another location
+    Loaded(Box<ValueId>)
+}
+
+impl ValueId {
 
+    /// The function or global the value belongs to.
+    pub fn owner(&self) -> Option<&str> {
+        match self {
+            ValueId::Instruction(id) => Some(&id.function),
+            ValueId::Argument { function, .. } => Some(function),
+            ValueId::Global(name) => Some(name),
+            ValueId::Constant(_) => None,
+            ValueId::Field { base, .. } | ValueId::Loaded(base) => base.owner()
+        }
     }
 
 }
 
-pub struct Predecessors;
+impl fmt::Display for ValueId {
 
-impl Predecessors {
-    // ! This is broken. Inkwell does not seem to have an appropriate API to accomplish this.
-    pub fn get_predecessors(instruction: InstructionValue) -> Vec<InstructionValue> {
-
-        let mut ret = Vec::new();
-        if let Some(prev_inst) = instruction.get_previous_instruction(){ // ! this only gets the previous instruction in the basic block
-            ret.push(prev_inst);
-        }
-    
-        if let Some(block) = instruction.get_parent() {
-            
-            if let Some(first_instruction) = block.get_first_instruction() {
-                if first_instruction != instruction {
-                    return ret;
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueId::Instruction(id) => write!(f, "{}", id),
+            ValueId::Argument { function, index } => write!(f, "{:#} arg{}", demangle(function), index),
+            ValueId::Global(name) => write!(f, "@{}", name),
+            ValueId::Constant(text) => write!(f, "{}", text),
+            ValueId::Field { base, element_type, indices } => write!(f, "{} +{}{:?}", base, element_type, indices),
+            ValueId::Loaded(base) => write!(f, "*({})", base)
+        }
+    }
+
+}
+
+/// Assigns every instruction of a module its `InstructionId` once, so lookups do not rescan functions.
+#[derive(Debug, Clone, Default)]
+pub struct ValueIds {
+    instructions : HashMap<LLVMValueRef, InstructionId>
+}
+
+impl ValueIds {
+
+    pub fn new() -> Self {
+        Self {
+            instructions : HashMap::new()
+        }
+    }
+
+    pub fn from_module(module : &Module) -> Self {
+
+        let mut ids = Self::new();
+
+        for function in module.get_functions() {
+            let function_name = function.get_name().to_string_lossy().into_owned();
+            for (block, basic_block) in function.get_basic_blocks().into_iter().enumerate() {
+                for (index, instr) in InstructionIterator::new(&basic_block).enumerate() {
+                    ids.instructions.insert(instr.as_value_ref(), InstructionId {
+                        function : function_name.clone(),
+                        block,
+                        index
+                    });
                 }
             }
-            // println!("__________");
-            // println!("block: {:?}", block.get_name());
+        }
 
-            if let Some(parent) = block.get_parent() {
-                for iter in parent.get_basic_blocks() {
+        ids
 
-                    for instruction in InstructionIterator::new(&iter) {
+    }
 
-                        let name_with_quotes = format!("{:?}", block.get_name()); // This will have quotes due to Debug formatting
-                        let trimmed_name = name_with_quotes.trim_matches('"'); // This removes leading and trailing quotes
+    pub fn instruction_id(&self, instr : &InstructionValue) -> InstructionId {
 
-                        let pattern = format!("label %{}", trimmed_name);
+        self.instructions.get(&instr.as_value_ref()).cloned()
+        .expect("every instruction of the module should have been assigned an id")
 
-                        if instruction.to_string().contains(
-                            pattern.as_str()
-                        ) {
-                            ret.push(instruction);
-                            // println!("pred: {:?}", iter);
-                        }
+    }
 
-                    }
+    pub fn value_id(&self, value : &impl AsValueRef) -> ValueId {
 
-                    /*loop {
-                        // println!("iter: {:?}", iter.get_name());
-                        if let Some(last_inst) = iter.get_last_instruction() {
-                            ret.push(last_inst);
-                        }
+        let value_ref = value.as_value_ref();
 
-                        if let Some(next_block) = iter.get_next_basic_block() {
-                            iter = next_block;
-                        } else {
-                            break;
-                        }
-                    }*/
+        if let Some(id) = self.instructions.get(&value_ref) {
+            return ValueId::Instruction(id.clone());
+        }
 
-                }
-                
-                // ! Also tried this, but it messes up reaching definitions.
-                /*for inner_block in parent.get_basic_blocks() {
-
-                    println!("inner_block: {:?}", inner_block.get_name());
-                    if let Some(last_inst) = inner_block.get_last_instruction() {
-                        if inner_block != block {
-                            ret.push(last_inst);
-                        }
-                    }
-                }*/
+        unsafe {
+
+            if !LLVMIsAArgument(value_ref).is_null() {
+                let function_ref = LLVMGetParamParent(value_ref);
+                let index = (0..LLVMCountParams(function_ref))
+                .find(|index| LLVMGetParam(function_ref, *index) == value_ref)
+                .expect("an argument should be one of its function's parameters");
+                let function = FunctionValue::new(function_ref)
+                .expect("an argument's parent should be a function");
+                return ValueId::Argument {
+                    function : function.get_name().to_string_lossy().into_owned(),
+                    index
+                };
+            }
 
+            if !LLVMIsAGlobalValue(value_ref).is_null() {
+                return ValueId::Global(Var::get_ir_name(value).trim_start_matches('@').to_string());
             }
 
         }
-    
-        ret
+
+        ValueId::Constant(Var::get_ir_name(value))
 
     }
 
-    pub fn get_jump_labels(basic_block: &BasicBlock) -> Vec<String> {
+    /// Like `value_id`, but identifies the memory a value denotes rather than the value itself:
+    /// constant-index `getelementptr`s are keyed by their base and indices, and loads by the
+    /// location they read. Generators recompute the address of their state after every resume,
+    /// so the same field is otherwise a different value at each `.await`.
+    pub fn location_id(&self, value : &impl AsValueRef) -> ValueId {
 
-        let mut ret = Vec::new();
-    
-        let instruction_str = format!("{:?}", basic_block);
+        let value_ref = value.as_value_ref();
 
-        let re = Regex::new(r"label %([a-zA-Z0-9_]+)");
+        unsafe {
 
-        match re {
-            Ok(re) => {
-                for cap in re.captures_iter(&instruction_str) {
-                    ret.push(cap[1].to_string());
+            if !LLVMIsALoadInst(value_ref).is_null() {
+                let pointer = RawValue(LLVMGetOperand(value_ref, 0));
+                return ValueId::Loaded(Box::new(self.location_id(&pointer)));
+            }
+
+            if !LLVMIsAGetElementPtrInst(value_ref).is_null() {
+
+                let count = LLVMGetNumOperands(value_ref) as u32;
+                let indices : Option<Vec<i64>> = (1..count).map(|index| {
+                    let operand = LLVMGetOperand(value_ref, index);
+                    if LLVMIsAConstantInt(operand).is_null() {
+                        None
+                    } else {
+                        Some(LLVMConstIntGetSExtValue(operand))
+                    }
+                }).collect();
+
+                if let Some(indices) = indices {
+                    let type_ptr = LLVMPrintTypeToString(LLVMGetGEPSourceElementType(value_ref));
+                    let element_type = CStr::from_ptr(type_ptr).to_string_lossy().into_owned();
+                    LLVMDisposeMessage(type_ptr);
+                    let base = RawValue(LLVMGetOperand(value_ref, 0));
+                    return ValueId::Field {
+                        base : Box::new(self.location_id(&base)),
+                        element_type,
+                        indices
+                    };
                 }
-                ret
-            },
-            Err(_) => {
-                // println!("Error: Could not parse jump labels from instruction: {}", instruction_str);
-                ret
+
             }
 
         }
 
+        self.value_id(value)
+
     }
 
 }
 
-pub struct Alloca;
+pub struct GetElementPtr;
+
+impl GetElementPtr {
 
-impl Alloca {
+    /// Bytes a `getelementptr` instruction advances its base by, per the module's data layout.
+    /// Indices that are not constant, i.e. array elements picked at run time, count as 0.
+    pub fn offset(instr : &InstructionValue) -> i64 {
+        Self::constant_offset(instr, instr)
+    }
+
+    /// Like `offset`, for a `getelementptr` constant expression, which belongs to no module
+    /// of its own but takes the data layout of the instruction `user`.
+    pub fn constant_offset(gep : &impl AsValueRef, user : &InstructionValue) -> i64 {
+
+        let instr_ref = gep.as_value_ref();
+
+        unsafe {
 
-    pub fn get_type_from_alloca_str(instr_str : &str) -> Result<String, anyhow::Error> {
+            let data_layout = module_data_layout(user.as_value_ref());
 
-        let re = Regex::new(r"(alloca (.*?),)").unwrap();;
+            let mut element_type = LLVMGetGEPSourceElementType(instr_ref);
+            let mut offset : i64 = 0;
 
-        // return Ok("".to_string());
+            for operand in 1..(LLVMGetNumOperands(instr_ref) as u32) {
+
+                let index = LLVMGetOperand(instr_ref, operand);
+                let index = if LLVMIsAConstantInt(index).is_null() {
+                    0
+                } else {
+                    LLVMConstIntGetSExtValue(index)
+                };
+
+                // the first index steps over whole elements of the source type
+                if operand == 1 {
+                    offset = offset.wrapping_add(index.wrapping_mul(LLVMABISizeOfType(data_layout, element_type) as i64));
+                    continue;
+                }
+
+                match LLVMGetTypeKind(element_type) {
+                    LLVMTypeKind::LLVMStructTypeKind => {
+                        offset = offset.wrapping_add(LLVMOffsetOfElement(data_layout, element_type, index as u32) as i64);
+                        element_type = LLVMStructGetTypeAtIndex(element_type, index as u32);
+                    },
+                    LLVMTypeKind::LLVMArrayTypeKind | LLVMTypeKind::LLVMVectorTypeKind => {
+                        element_type = LLVMGetElementType(element_type);
+                        offset = offset.wrapping_add(index.wrapping_mul(LLVMABISizeOfType(data_layout, element_type) as i64));
+                    },
+                    _ => break
+                }
 
-        if let Some(caps) = re.captures(instr_str) {
-            if let Some(function_name) = caps.get(2) {
-                // Trim double quotes if present
-                let trimmed_name = function_name.as_str().trim_matches(|c: char| !c.is_alphanumeric());
-                return Ok(trimmed_name.to_string());
             }
-        } 
-    
-        Err(anyhow::anyhow!("Could  not parse type from fun tion: {}", instr_str))
-    
+
+            offset
+
+        }
+
     }
 
 }
 
-pub enum KnowAlloca {
-    MutexGuard,
-    Unknown
-}
+/// Recognises the drop flags rustc emits for conditionally moved values.
+pub struct DropFlags;
 
-impl KnowAlloca {
+impl DropFlags {
 
-    pub fn is_mutex_guard(instr : &InstructionValue) -> bool {
+    /// Whether a conditional branch tests a drop flag: rustc's one byte stack slot recording whether
+    /// a conditionally moved value is still owned, and so whether its drop glue should run.
+    /// The test loads the flag and truncates (or compares) it to a bit. A `bool` of the source,
+    /// which `variables` knows from its debug info, is not a drop flag.
+    pub fn tests_drop_flag(branch : &InstructionValue, variables : &SourceVariables) -> bool {
 
-    
-    
-        let type_str = Alloca::get_type_from_alloca_str(format!("{:?}", instr).as_str()).unwrap();
-        type_str.to_string().contains("core::result::Result<std::sync::mutex::MutexGuard")
-    
+        let branch_ref = branch.as_value_ref();
 
+        unsafe {
+
+            if branch.get_opcode() != InstructionOpcode::Br || LLVMGetNumOperands(branch_ref) != 3 {
+                return false;
+            }
+
+            let mut condition = LLVMGetOperand(branch_ref, 0);
+            if !LLVMIsATruncInst(condition).is_null() || !LLVMIsAICmpInst(condition).is_null() {
+                condition = LLVMGetOperand(condition, 0);
+            }
+
+            if LLVMIsALoadInst(condition).is_null() {
+                return false;
+            }
+
+            let slot = LLVMGetOperand(condition, 0);
+            Self::is_drop_flag(slot) && variables.get(&RawValue(slot)).is_none()
+
+        }
 
     }
 
-    pub fn try_from_instr(instr : &InstructionValue) -> Result<Self, anyhow::Error> {
+    /// A byte alloca only ever assigned constants and read back.
+    unsafe fn is_drop_flag(slot : LLVMValueRef) -> bool {
+
+        if LLVMIsAAllocaInst(slot).is_null() {
+            return false;
+        }
 
-        if Self::is_mutex_guard(instr) {
-            return Ok(Self::MutexGuard);
+        let data_layout = module_data_layout(slot);
+        if LLVMABISizeOfType(data_layout, LLVMGetAllocatedType(slot)) != 1 {
+            return false;
         }
 
-        Err(anyhow::anyhow!("Unknown alloca type"))
+        let mut current = LLVMGetFirstUse(slot);
+        while !current.is_null() {
 
-    }
+            let user = LLVMGetUser(current);
+            let is_flag_use = if !LLVMIsAStoreInst(user).is_null() {
+                LLVMGetOperand(user, 1) == slot && !LLVMIsAConstantInt(LLVMGetOperand(user, 0)).is_null()
+            } else {
+                !LLVMIsALoadInst(user).is_null()
+            };
+
+            if !is_flag_use {
+                return false;
+            }
+
+            current = LLVMGetNextUse(current);
 
-    pub fn from_instr(instr : &InstructionValue) -> Self {
+        }
 
-        Self::try_from_instr(instr).unwrap_or(Self::Unknown)
+        true
 
     }
 
 }
 
-pub struct Var;
+/// Recognises the test of whether a try-lock got the lock.
+pub struct TryLocks;
+
+impl TryLocks {
+
+    /// The block a try-lock call continues in once it got the lock, provided nothing else leads
+    /// there: the edge taken when the tag of the `Result` it writes through its `sret` out pointer
+    /// is `Ok`'s 0, or when the guard it returns in a pointer, as an `Option`, is not null. The
+    /// test has to follow the call, in its block or in that block's only successor.
+    pub fn success_block<'ctx>(call : &InstructionValue<'ctx>, cfg : &Cfg<'ctx>) -> Option<BasicBlock<'ctx>> {
+
+        let call_block = call.get_parent()?;
+        let call_ref = call.as_value_ref();
+
+        unsafe {
+
+            // the values telling the outcome, and whether it is a success when they are 0 (null)
+            let (outcomes, success_is_zero) = if InstructionCallSites::has_sret(call) {
+                let loads = users(InstructionCallSites::result(call).0).into_iter()
+                .filter(|user| !LLVMIsALoadInst(*user).is_null()
+                    && LLVMGetTypeKind(LLVMTypeOf(*user)) == LLVMTypeKind::LLVMIntegerTypeKind)
+                .collect();
+                (loads, true)
+            } else if LLVMGetTypeKind(LLVMTypeOf(call_ref)) == LLVMTypeKind::LLVMPointerTypeKind {
+                (vec![call_ref], false)
+            } else {
+                return None;
+            };
+
+            for outcome in outcomes {
+                for (test, success) in Self::tests(outcome, success_is_zero) {
+
+                    let (test_block, success) = match (cfg.block(LLVMGetInstructionParent(test)), cfg.block(success)) {
+                        (Some(test_block), Some(success)) => (test_block, success),
+                        _ => continue
+                    };
+
+                    let follows_call = test_block == call_block || cfg.predecessors(&test_block) == [call_block];
+                    let outcome_block = LLVMGetInstructionParent(outcome);
+                    let outcome_follows_call = outcome == call_ref
+                    || (outcome_block == call_block.as_mut_ptr() && Self::follows(call_ref, outcome))
+                    || (outcome_block == test_block.as_mut_ptr() && test_block != call_block);
+                    let only_edge = (0..LLVMGetNumSuccessors(test))
+                    .filter(|index| LLVMGetSuccessor(test, *index) == success.as_mut_ptr())
+                    .count() == 1;
+
+                    if follows_call && outcome_follows_call && only_edge && cfg.predecessors(&success) == [test_block] {
+                        return Some(success);
+                    }
 
-impl Var {
+                }
+            }
 
-    pub fn get_var_name_from_instr(instr : &InstructionValue) -> String {
+        }
 
-        let name_with_quotes = format!("{:?}", 
-            instr.get_name().expect("Could not get name from instruction") // This will have quotes due to Debug formatting
-        ); // This will have quotes due to Debug formatting
-        let trimmed_name = name_with_quotes.trim_matches('"'); // This removes leading and trailing quotes
+        None
 
-        let full_name = format!("%{} {:?}", trimmed_name, instr.as_value_ref());
+    }
+
+    /// The terminators branching on whether `outcome` is 0, with the block each enters on success.
+    unsafe fn tests(outcome : LLVMValueRef, success_is_zero : bool) -> Vec<(LLVMValueRef, LLVMBasicBlockRef)> {
+
+        let mut tests = Vec::new();
+
+        for user in users(outcome) {
+
+            if !LLVMIsAICmpInst(user).is_null() && LLVMIsNull(LLVMGetOperand(user, 1)) != 0 {
+
+                let equal = match LLVMGetICmpPredicate(user) {
+                    LLVMIntPredicate::LLVMIntEQ => true,
+                    LLVMIntPredicate::LLVMIntNE => false,
+                    _ => continue
+                };
+
+                // the first successor is taken when the comparison holds
+                for branch in users(user) {
+                    if !LLVMIsABranchInst(branch).is_null() && LLVMIsConditional(branch) != 0 {
+                        tests.push((branch, LLVMGetSuccessor(branch, if equal == success_is_zero { 0 } else { 1 })));
+                    }
+                }
+
+            } else if !LLVMIsASwitchInst(user).is_null() && LLVMGetOperand(user, 0) == outcome && success_is_zero {
+
+                // successor `i` is the destination of case `i - 1`, whose value is operand `2 * i`
+                for index in 1..LLVMGetNumSuccessors(user) {
+                    if LLVMIsNull(LLVMGetOperand(user, 2 * index)) != 0 {
+                        tests.push((user, LLVMGetSuccessor(user, index)));
+                    }
+                }
+
+            }
+
+        }
+
+        tests
 
-        full_name
-    
     }
 
-    pub fn get_var_name_from_basic_value(instr : &BasicValueEnum) -> String {
+    /// Whether `later` comes after `earlier` in their block.
+    unsafe fn follows(earlier : LLVMValueRef, later : LLVMValueRef) -> bool {
+
+        let mut current = LLVMGetNextInstruction(earlier);
+        while !current.is_null() {
+            if current == later {
+                return true;
+            }
+            current = LLVMGetNextInstruction(current);
+        }
+
+        false
 
-        // should be a pointer type
-        let address = instr.as_value_ref();
+    }
 
-        let name_with_quotes = format!(
-            "{:?}", 
-            instr.into_pointer_value().get_name()
-        ); // This will have quotes due to Debug formatting
-        let trimmed_name = name_with_quotes.trim_matches('"'); // This removes leading and trailing quotes
+}
 
-        let full_name = format!("%{} {:?}", trimmed_name, address);
+/// The instructions using `value`.
+unsafe fn users(value : LLVMValueRef) -> Vec<LLVMValueRef> {
 
-        full_name
-    
+    let mut users = Vec::new();
+    let mut current = LLVMGetFirstUse(value);
+    while !current.is_null() {
+        users.push(LLVMGetUser(current));
+        current = LLVMGetNextUse(current);
     }
 
-}
\ No newline at end of file
+    users
+
+}
+
+/// The data layout of the module an instruction belongs to.
+unsafe fn module_data_layout(instr_ref : LLVMValueRef) -> LLVMTargetDataRef {
+    LLVMGetModuleDataLayout(LLVMGetGlobalParent(LLVMGetBasicBlockParent(LLVMGetInstructionParent(instr_ref))))
+}
@@ -1 +1,4 @@
-pub mod llvm_ir;
\ No newline at end of file
+pub mod llvm_ir;
+pub mod debug_info;
+pub mod demangled_path;
+pub mod diagnostics;
@@ -1,2 +1,6 @@
 pub mod reentrant_lock_detection;
+pub mod points_to;
+pub mod lock_order;
+pub mod report;
+pub mod trace;
 pub mod util;
\ No newline at end of file
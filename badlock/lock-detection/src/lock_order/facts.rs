@@ -0,0 +1,317 @@
+use std::collections::HashSet;
+use crepe::crepe;
+// shared with the reentrant program, so one import extends either
+pub use crate::reentrant_lock_detection::facts::Extendable;
+
+crepe! {
+
+    // control flows from the first instruction to the second
+    @input
+    #[derive(Debug)]
+    pub struct Next(pub usize, pub usize);
+
+    // a call, the first instruction of the callee it continues into
+    @input
+    #[derive(Debug)]
+    pub struct Call(pub usize, pub usize);
+
+    // instruction, variable locked there
+    @input
+    #[derive(Debug)]
+    pub struct Lock(pub usize, pub usize);
+
+    // instruction, variable released there
+    @input
+    #[derive(Debug)]
+    pub struct Release(pub usize, pub usize);
+
+
+    // instruction, variable held when it runs, instruction that acquired the variable
+    @output
+    #[derive(Debug)]
+    pub struct Held(pub usize, pub usize, pub usize);
+
+    // the first variable, acquired at the first instruction, is held while the second is locked
+    @output
+    #[derive(Debug)]
+    pub struct Order(pub usize, pub usize, pub usize, pub usize);
+
+    // two variables locked in both orders: a acquired, then b locked, and b acquired, then a locked
+    @output
+    #[derive(Debug)]
+    pub struct Inversion(pub usize, pub usize, pub usize, pub usize, pub usize, pub usize);
+
+    Held(next, var, acquired) <- Lock(acquired, var), Next(acquired, next);
+    Held(next, var, acquired) <- Held(inst, var, acquired), !Release(inst, var), Next(inst, next);
+    // a callee holds what is held when it is called, not what the call itself acquires
+    Held(entry, var, acquired) <- Held(call, var, acquired), Call(call, entry);
+
+    Order(acquired, held, inst, var) <- Held(inst, held, acquired), Lock(inst, var), (held != var);
+
+    // each pair of variables is reported once, the first mapped first
+    Inversion(a_acquired, a, b_locked, b_acquired, b, a_locked) <-
+        Order(a_acquired, a, b_locked, b),
+        Order(b_acquired, b, a_locked, a),
+        (a < b);
+
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LockOrderPriors {
+    pub nexts : HashSet<Next>,
+    pub calls : HashSet<Call>,
+    pub locks : HashSet<Lock>,
+    pub releases : HashSet<Release>
+}
+
+impl LockOrderPriors {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn compute(&self) -> LockOrderPosts {
+
+        let mut runtime = Crepe::new();
+
+        runtime.extend(self.nexts.iter().cloned());
+        runtime.extend(self.calls.iter().cloned());
+        runtime.extend(self.locks.iter().cloned());
+        runtime.extend(self.releases.iter().cloned());
+
+        runtime.run().into()
+
+    }
+
+}
+
+impl Extendable<Next> for LockOrderPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Next> {
+        self.nexts.extend(values);
+        self
+    }
+}
+
+impl Extendable<Call> for LockOrderPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Call> {
+        self.calls.extend(values);
+        self
+    }
+}
+
+impl Extendable<Lock> for LockOrderPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Lock> {
+        self.locks.extend(values);
+        self
+    }
+}
+
+impl Extendable<Release> for LockOrderPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Release> {
+        self.releases.extend(values);
+        self
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    pub fn test_lock_order_finds_inversion() {
+
+        // fn f() { let _a = A.lock(); B.lock(); }  fn g() { let _b = B.lock(); A.lock(); }
+        let mut facts = LockOrderPriors::new();
+        facts.extend(vec![
+            Next(0, 1),
+            Next(1, 2),
+            Next(10, 11),
+            Next(11, 12),
+        ]);
+
+        facts.extend(vec![
+            Lock(0, 100),
+            Lock(1, 200),
+            Lock(10, 200),
+            Lock(11, 100),
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        assert!(posts.order.contains(&Order(0, 100, 1, 200)));
+        assert!(posts.order.contains(&Order(10, 200, 11, 100)));
+        assert_eq!(posts.inversion.len(), 1);
+        assert!(posts.inversion.contains(&Inversion(0, 100, 1, 10, 200, 11)));
+
+    }
+
+    #[test]
+    pub fn test_lock_order_holds_in_callees_what_is_held_at_the_call() {
+
+        // fn state() -> Guard { A.lock() }  fn main() { let _b = B.lock(); let _a = state(); B.lock(); },
+        // where the call at 1 returns A held
+        let mut facts = LockOrderPriors::new();
+        facts.extend(vec![
+            Next(0, 1),
+            Next(1, 2),
+            Next(10, 11),
+        ]);
+
+        facts.extend(vec![
+            Call(1, 10),
+        ]);
+
+        facts.extend(vec![
+            Lock(0, 200),
+            Lock(1, 100),
+            Lock(2, 200),
+            Lock(10, 100),
+        ]);
+
+        let posts = facts.compute();
+
+        // B is held in `state`, A only once it returned
+        assert!(posts.held.contains(&Held(10, 200, 0)));
+        assert!(!posts.held.contains(&Held(10, 100, 1)));
+        assert!(posts.order.contains(&Order(0, 200, 10, 100)));
+        assert!(posts.order.contains(&Order(1, 100, 2, 200)));
+
+    }
+
+    #[test]
+    pub fn test_lock_order_respects_releases() {
+
+        // fn f() { let a = A.lock(); drop(a); B.lock(); }  fn g() { let _b = B.lock(); A.lock(); }
+        let mut facts = LockOrderPriors::new();
+        facts.extend(vec![
+            Next(0, 1),
+            Next(1, 2),
+            Next(2, 3),
+            Next(10, 11),
+            Next(11, 12),
+        ]);
+
+        facts.extend(vec![
+            Lock(0, 100),
+            Lock(2, 200),
+            Lock(10, 200),
+            Lock(11, 100),
+        ]);
+
+        facts.extend(vec![
+            Release(1, 100),
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        assert!(!posts.order.contains(&Order(0, 100, 2, 200)));
+        assert!(posts.order.contains(&Order(10, 200, 11, 100)));
+        assert!(posts.inversion.is_empty());
+
+    }
+
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LockOrderPosts {
+    pub held : HashSet<Held>,
+    pub order : HashSet<Order>,
+    pub inversion : HashSet<Inversion>
+}
+
+impl LockOrderPosts {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+}
+
+impl From<(HashSet<Held>, HashSet<Order>, HashSet<Inversion>)> for LockOrderPosts {
+    fn from(outputs : (HashSet<Held>, HashSet<Order>, HashSet<Inversion>)) -> Self {
+        LockOrderPosts {
+            held : outputs.0,
+            order : outputs.1,
+            inversion : outputs.2
+        }
+    }
+}
+
+pub mod generic {
+
+    use std::collections::HashSet;
+
+    // the inputs are those of the reentrant program, so its facts can be handed over as they are
+    pub use crate::reentrant_lock_detection::generic::{Next, Call, Lock, Release};
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Held<Symbol>(pub Symbol, pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Order<Symbol>(pub Symbol, pub Symbol, pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Inversion<Symbol>(pub Symbol, pub Symbol, pub Symbol, pub Symbol, pub Symbol, pub Symbol);
+
+    #[derive(Debug)]
+    pub struct LockOrderPriors<Symbol> {
+        pub nexts : HashSet<Next<Symbol>>,
+        pub calls : HashSet<Call<Symbol>>,
+        pub locks : HashSet<Lock<Symbol>>,
+        pub releases : HashSet<Release<Symbol>>
+    }
+
+    impl <Symbol> LockOrderPriors<Symbol> {
+
+        pub fn new() -> Self {
+            Self {
+                nexts : HashSet::new(),
+                calls : HashSet::new(),
+                locks : HashSet::new(),
+                releases : HashSet::new()
+            }
+        }
+
+    }
+
+    impl <Symbol> Default for LockOrderPriors<Symbol> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct LockOrderPosts<Symbol> {
+        pub held : HashSet<Held<Symbol>>,
+        pub order : HashSet<Order<Symbol>>,
+        pub inversion : HashSet<Inversion<Symbol>>
+    }
+
+    impl <Symbol> LockOrderPosts<Symbol> {
+
+        pub fn new() -> Self {
+            Self {
+                held : HashSet::new(),
+                order : HashSet::new(),
+                inversion : HashSet::new()
+            }
+        }
+
+    }
+
+    impl <Symbol> Default for LockOrderPosts<Symbol> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+}
@@ -0,0 +1,5 @@
+pub mod facts;
+pub mod program;
+pub use facts::generic;
+pub use facts::generic::*;
+pub use program::Program;
@@ -0,0 +1,242 @@
+use crate::util::insertion_order_map::InsertionOrderMap;
+use super::{facts, generic::*};
+
+#[derive(Clone, Debug)]
+pub struct Program<Symbol : std::cmp::Eq + std::hash::Hash> {
+
+    // we need a mapping of the symbols to numbers
+    pub symbol_mapping : InsertionOrderMap<Symbol>,
+
+    // now we want collections of all of the different types of facts
+    priors : facts::LockOrderPriors
+
+}
+
+impl <Symbol> Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    pub fn new() -> Self {
+        Self {
+            symbol_mapping : InsertionOrderMap::new(),
+            priors : facts::LockOrderPriors::new()
+        }
+    }
+
+    pub fn priors(&self) -> LockOrderPriors<&Symbol> {
+
+        let mut priors = LockOrderPriors::new();
+
+        for next in self.priors.nexts.iter() {
+            let next = Next(
+                self.symbol_mapping.unmap(next.0).unwrap(), 
+                self.symbol_mapping.unmap(next.1).unwrap()
+            );
+            priors.nexts.insert(next);
+        }
+
+        for call in self.priors.calls.iter() {
+            let call = Call(
+                self.symbol_mapping.unmap(call.0).unwrap(), 
+                self.symbol_mapping.unmap(call.1).unwrap()
+            );
+            priors.calls.insert(call);
+        }
+
+        for lock in self.priors.locks.iter() {
+            let lock = Lock(
+                self.symbol_mapping.unmap(lock.0).unwrap(), 
+                self.symbol_mapping.unmap(lock.1).unwrap()
+            );
+            priors.locks.insert(lock);
+        }
+
+        for release in self.priors.releases.iter() {
+            let release = Release(
+                self.symbol_mapping.unmap(release.0).unwrap(), 
+                self.symbol_mapping.unmap(release.1).unwrap()
+            );
+            priors.releases.insert(release);
+        }
+
+        priors
+
+    }
+
+    pub fn compute(&self) -> LockOrderPosts<&Symbol> {
+
+        let inner_posts = self.priors.compute();
+        let mut posts = LockOrderPosts::new();
+
+        for held in inner_posts.held.iter() {
+            let held = Held(
+                self.symbol_mapping.unmap(held.0).unwrap(), 
+                self.symbol_mapping.unmap(held.1).unwrap(), 
+                self.symbol_mapping.unmap(held.2).unwrap()
+            );
+            posts.held.insert(held);
+        }
+
+        for order in inner_posts.order.iter() {
+            let order = Order(
+                self.symbol_mapping.unmap(order.0).unwrap(), 
+                self.symbol_mapping.unmap(order.1).unwrap(), 
+                self.symbol_mapping.unmap(order.2).unwrap(), 
+                self.symbol_mapping.unmap(order.3).unwrap()
+            );
+            posts.order.insert(order);
+        }
+
+        for inversion in inner_posts.inversion.iter() {
+            let inversion = Inversion(
+                self.symbol_mapping.unmap(inversion.0).unwrap(), 
+                self.symbol_mapping.unmap(inversion.1).unwrap(), 
+                self.symbol_mapping.unmap(inversion.2).unwrap(), 
+                self.symbol_mapping.unmap(inversion.3).unwrap(), 
+                self.symbol_mapping.unmap(inversion.4).unwrap(), 
+                self.symbol_mapping.unmap(inversion.5).unwrap()
+            );
+            posts.inversion.insert(inversion);
+        }
+
+        posts
+
+    }
+
+}
+
+impl <Symbol> Default for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <Symbol> facts::Extendable<Next<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Next<Symbol>>
+    {
+
+        for next in iter.into_iter() {
+            let next = facts::Next(
+                self.symbol_mapping.map(next.0), 
+                self.symbol_mapping.map(next.1)
+            );
+            self.priors.nexts.insert(next);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Call<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Call<Symbol>>
+    {
+
+        for call in iter.into_iter() {
+            let call = facts::Call(
+                self.symbol_mapping.map(call.0), 
+                self.symbol_mapping.map(call.1)
+            );
+            self.priors.calls.insert(call);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Lock<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Lock<Symbol>>
+    {
+
+        for lock in iter.into_iter() {
+            let lock = facts::Lock(
+                self.symbol_mapping.map(lock.0), 
+                self.symbol_mapping.map(lock.1)
+            );
+            self.priors.locks.insert(lock);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Release<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Release<Symbol>>
+    {
+
+        for release in iter.into_iter() {
+            let release = facts::Release(
+                self.symbol_mapping.map(release.0), 
+                self.symbol_mapping.map(release.1)
+            );
+            self.priors.releases.insert(release);
+        }
+        self
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use facts::Extendable;
+
+    use super::*;
+
+    #[test]
+    fn test_maps_and_unmaps_inversion() {
+
+        let mut program : Program<&str> = Program::new();
+
+        // f locks a then b, g locks b then a
+        program.extend(vec![
+            Next("f a", "f b"),
+            Next("f b", "f ret"),
+            Next("g b", "g a"),
+            Next("g a", "g ret"),
+        ]);
+
+        program.extend(vec![
+            Lock("f a", "a"),
+            Lock("f b", "b"),
+            Lock("g b", "b"),
+            Lock("g a", "a"),
+        ]);
+
+        let posts = program.compute();
+
+        println!("{:#?}", posts);
+
+        assert_eq!(posts.inversion.len(), 1);
+        assert!(posts.inversion.contains(&Inversion(&"f a", &"a", &"f b", &"g b", &"b", &"g a")));
+
+    }
+
+}
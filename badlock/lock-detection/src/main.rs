@@ -1,5 +1,169 @@
+use std::path::{Path, PathBuf};
+use lock_detection::reentrant_lock_detection::Program;
+use lock_detection::reentrant_lock_detection::io::{self, InputFormat, Table};
+use lock_detection::report::Report;
+use lock_detection::trace::{event, goodlock};
+
+const USAGE : &str = "\
+Runs the reentrant deadlock program over priors and prints the relations it computes, or looks
+for lock order cycles in a recorded trace.
+
+USAGE:
+    lock-detection [OPTIONS] <PRIORS>...
+    lock-detection [--format <table|json>] --trace <TRACE>
+
+PRIORS are `.facts` files of tab separated tuples named after their relation (or directories of them),
+`.json` objects of relation names to arrays of tuples, or text with one `relation(a, b).` per line.
+The input relations are def(var, inst), use_var(var, inst), next(from, to), call(call, entry),
+wrap(wrapper, var), lock(inst, var) and release(inst, var).
+
+TRACE is a file of JSON objects, one per line, with a `thread`, an `event` of `acquire`, `release`,
+`start` or `join`, the `lock` acquired or released or the `child` thread started or joined, and
+optionally a source `location` and a `timestamp` to order events by. Cycles that guard locks or
+thread starts and joins rule out are not reported.
+
+OPTIONS:
+    --output <RELATIONS>     Comma separated relations to print, inputs or kill, in, out, deadlock,
+                             edge and path [default: deadlock]
+    --format <table|json>    How relations or the trace report are printed [default: table]
+    --symbol <TEXT>          Only print tuples mentioning a symbol containing TEXT, may be repeated
+    --input-format <FORMAT>  Read all priors as facts, json or dsl rather than by extension
+    --trace <TRACE>          Report the potential deadlocks of a lock trace instead
+    -h, --help               Prints this message";
+
+struct Args {
+    priors : Vec<PathBuf>,
+    outputs : Vec<String>,
+    json : bool,
+    symbols : Vec<String>,
+    input_format : Option<InputFormat>,
+    trace : Option<PathBuf>,
+    help : bool
+}
+
+impl Args {
+
+    fn parse(args : impl IntoIterator<Item = String>) -> Result<Self, anyhow::Error> {
+
+        let mut parsed = Args {
+            priors : Vec::new(),
+            outputs : vec!["deadlock".to_string()],
+            json : false,
+            symbols : Vec::new(),
+            input_format : None,
+            trace : None,
+            help : false
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg.clone(), None)
+            };
+            let mut value = || inline.clone().or_else(|| args.next()).ok_or(
+                anyhow::anyhow!("`{}` expects a value", flag)
+            );
+
+            match flag.as_str() {
+                "--output" => parsed.outputs = value()?.split(',').map(|relation| relation.trim().to_string()).collect(),
+                "--format" => parsed.json = match value()?.as_str() {
+                    "table" => false,
+                    "json" => true,
+                    format => anyhow::bail!("unknown format `{}`, expected `table` or `json`", format)
+                },
+                "--symbol" => parsed.symbols.push(value()?),
+                "--input-format" => parsed.input_format = Some(value()?.parse()?),
+                "--trace" => parsed.trace = Some(value()?.into()),
+                "-h" | "--help" => parsed.help = true,
+                _ if !arg.starts_with('-') => parsed.priors.push(arg.into()),
+                _ => anyhow::bail!("unexpected argument `{}`\n\n{}", arg, USAGE)
+            }
+
+        }
+
+        if parsed.priors.is_empty() && parsed.trace.is_none() && !parsed.help {
+            anyhow::bail!("no priors given\n\n{}", USAGE);
+        }
+        if !parsed.priors.is_empty() && parsed.trace.is_some() {
+            anyhow::bail!("priors cannot be given with `--trace`\n\n{}", USAGE);
+        }
+
+        Ok(parsed)
+
+    }
+
+}
+
+fn run(args : Args) -> Result<Vec<Table>, anyhow::Error> {
+
+    let mut program = Program::new();
+    for path in args.priors.iter() {
+        let format = args.input_format.unwrap_or_else(|| InputFormat::from_path(path));
+        io::read(&mut program, path, format)?;
+    }
+
+    io::tables(&program, &args.outputs, &args.symbols)
+
+}
+
+fn run_trace(trace : &Path) -> Result<Report, anyhow::Error> {
+    Ok(goodlock::analyze(&event::read(trace)?))
+}
+
 fn main() {
 
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let json = args.json;
+
+    if let Some(trace) = args.trace.as_deref() {
+        match run_trace(trace) {
+            Ok(report) if json => match serde_json::to_string_pretty(&report) {
+                Ok(json) => println!("{}", json),
+                Err(error) => {
+                    eprintln!("error: {}", error);
+                    std::process::exit(2);
+                }
+            },
+            Ok(report) => print!("{}", report),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
 
+    match run(args) {
+        Ok(tables) if json => match serde_json::to_string_pretty(&tables) {
+            Ok(json) => println!("{}", json),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                std::process::exit(2);
+            }
+        },
+        Ok(tables) => {
+            for table in tables {
+                println!("{}", table);
+            }
+        },
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    }
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,623 @@
+use std::collections::HashSet;
+use crepe::crepe;
+// shared with the reentrant program, so one import extends either
+pub use crate::reentrant_lock_detection::facts::Extendable;
+
+
+/// Offsets are clamped to this many bytes either side of an object's start, so pointers
+/// advanced in a loop cannot produce new facts forever.
+pub const MAX_OFFSET : i64 = 1 << 12;
+
+/// Pointers are at least this aligned on the 32- and 64-bit targets rustc supports.
+pub const SLOT : i64 = 4;
+
+fn shift(offset : i64, delta : i64) -> i64 {
+    offset.saturating_add(delta).clamp(-MAX_OFFSET, MAX_OFFSET)
+}
+
+fn slots(offset : i64, size : i64) -> impl Iterator<Item = i64> {
+    (0..size.clamp(1, MAX_OFFSET)).step_by(SLOT as usize).map(move |slot| shift(offset, slot))
+}
+
+crepe! {
+
+    // the variable holds the address of the object
+    @input
+    #[derive(Debug)]
+    pub struct Alloca(pub usize, pub usize);
+
+    // the variable is the other one advanced by a constant number of bytes
+    @input
+    #[derive(Debug)]
+    pub struct Gep(pub usize, pub usize, pub i64);
+
+    // the variable is the other one, reinterpreted
+    @input
+    #[derive(Debug)]
+    pub struct Bitcast(pub usize, pub usize);
+
+    // *pointer = value
+    @input
+    #[derive(Debug)]
+    pub struct Store(pub usize, pub usize);
+
+    // variable = *pointer
+    @input
+    #[derive(Debug)]
+    pub struct Load(pub usize, pub usize);
+
+    // *pointer holds value somewhere in its first bytes, for values of unknown layout
+    @input
+    #[derive(Debug)]
+    pub struct Hold(pub usize, pub usize, pub i64);
+
+    // everything from *source on is copied to *destination
+    @input
+    #[derive(Debug)]
+    pub struct Memcpy(pub usize, pub usize);
+
+    // call site, function
+    @input
+    #[derive(Debug)]
+    pub struct Call(pub usize, pub usize);
+
+    // call site, argument index, argument
+    @input
+    #[derive(Debug)]
+    pub struct Actual(pub usize, pub usize, pub usize);
+
+    // function, parameter index, parameter
+    @input
+    #[derive(Debug)]
+    pub struct Formal(pub usize, pub usize, pub usize);
+
+    // function, returned value
+    @input
+    #[derive(Debug)]
+    pub struct Ret(pub usize, pub usize);
+
+
+    // variable, object, offset into the object
+    @output
+    #[derive(Debug)]
+    pub struct PointsTo(pub usize, pub usize, pub i64);
+
+    // object, offset, object pointed to from there, offset into it
+    @output
+    #[derive(Debug)]
+    pub struct HeapPointsTo(pub usize, pub i64, pub usize, pub i64);
+
+    // Variables
+    PointsTo(var, obj, 0) <- Alloca(var, obj);
+    PointsTo(var, obj, shift(offset, delta)) <- Gep(var, base, delta), PointsTo(base, obj, offset);
+    PointsTo(var, obj, offset) <- Bitcast(var, src), PointsTo(src, obj, offset);
+    PointsTo(var, obj, offset) <-
+        Load(var, ptr), PointsTo(ptr, pointee, at), HeapPointsTo(pointee, at, obj, offset);
+
+    // Calls, arguments flow into parameters and returned values into the call site
+    PointsTo(param, obj, offset) <-
+        Call(call, function), Actual(call, index, arg), Formal(function, index, param), PointsTo(arg, obj, offset);
+    PointsTo(call, obj, offset) <- Call(call, function), Ret(function, value), PointsTo(value, obj, offset);
+
+    // Objects
+    HeapPointsTo(pointee, at, obj, offset) <- Store(ptr, value), PointsTo(ptr, pointee, at), PointsTo(value, obj, offset);
+    HeapPointsTo(pointee, slot, obj, offset) <-
+        Hold(ptr, value, size),
+        PointsTo(ptr, pointee, at),
+        PointsTo(value, obj, offset),
+        for slot in slots(at, size);
+    HeapPointsTo(destination, shift(to, at - from), obj, offset) <-
+        Memcpy(dst, src),
+        PointsTo(dst, destination, to),
+        PointsTo(src, source, from),
+        HeapPointsTo(source, at, obj, offset),
+        (at >= from);
+
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PointsToPriors {
+    pub allocas : HashSet<Alloca>,
+    pub geps : HashSet<Gep>,
+    pub bitcasts : HashSet<Bitcast>,
+    pub stores : HashSet<Store>,
+    pub loads : HashSet<Load>,
+    pub holds : HashSet<Hold>,
+    pub memcpys : HashSet<Memcpy>,
+    pub calls : HashSet<Call>,
+    pub actuals : HashSet<Actual>,
+    pub formals : HashSet<Formal>,
+    pub rets : HashSet<Ret>
+}
+
+impl PointsToPriors {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn compute(&self) -> PointsToPosts {
+
+        let mut runtime = Crepe::new();
+
+        runtime.extend(self.allocas.iter().cloned());
+        runtime.extend(self.geps.iter().cloned());
+        runtime.extend(self.bitcasts.iter().cloned());
+        runtime.extend(self.stores.iter().cloned());
+        runtime.extend(self.loads.iter().cloned());
+        runtime.extend(self.holds.iter().cloned());
+        runtime.extend(self.memcpys.iter().cloned());
+        runtime.extend(self.calls.iter().cloned());
+        runtime.extend(self.actuals.iter().cloned());
+        runtime.extend(self.formals.iter().cloned());
+        runtime.extend(self.rets.iter().cloned());
+
+        runtime.run().into()
+
+    }
+
+}
+
+impl Extendable<Alloca> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Alloca> {
+        self.allocas.extend(values);
+        self
+    }
+}
+
+impl Extendable<Gep> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Gep> {
+        self.geps.extend(values);
+        self
+    }
+}
+
+impl Extendable<Bitcast> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Bitcast> {
+        self.bitcasts.extend(values);
+        self
+    }
+}
+
+impl Extendable<Store> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Store> {
+        self.stores.extend(values);
+        self
+    }
+}
+
+impl Extendable<Load> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Load> {
+        self.loads.extend(values);
+        self
+    }
+}
+
+impl Extendable<Hold> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Hold> {
+        self.holds.extend(values);
+        self
+    }
+}
+
+impl Extendable<Memcpy> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Memcpy> {
+        self.memcpys.extend(values);
+        self
+    }
+}
+
+impl Extendable<Call> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Call> {
+        self.calls.extend(values);
+        self
+    }
+}
+
+impl Extendable<Actual> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Actual> {
+        self.actuals.extend(values);
+        self
+    }
+}
+
+impl Extendable<Formal> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Formal> {
+        self.formals.extend(values);
+        self
+    }
+}
+
+impl Extendable<Ret> for PointsToPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Ret> {
+        self.rets.extend(values);
+        self
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    pub fn test_points_to_through_locals() {
+
+        // let m = Mutex::new(..); let r = &m; let s = r; s.lock()
+        let mut facts = PointsToPriors::new();
+        facts.extend(vec![
+            Alloca(0, 100), // %m
+            Alloca(1, 101), // %r
+            Alloca(2, 102), // %s
+        ]);
+
+        facts.extend(vec![
+            Store(1, 0), // r = &m
+            Store(2, 3), // s = r
+        ]);
+
+        facts.extend(vec![
+            Load(3, 1),
+            Load(4, 2), // the receiver of `lock`
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        assert!(posts.points_to.contains(&PointsTo(4, 100, 0)));
+        assert!(posts.heap_points_to.contains(&HeapPointsTo(102, 0, 100, 0)));
+        assert!(!posts.points_to.contains(&PointsTo(4, 101, 0)));
+
+    }
+
+    #[test]
+    pub fn test_points_to_through_calls() {
+
+        // fn id(x : &Mutex) -> &Mutex { x }, called with &a and &b
+        let mut facts = PointsToPriors::new();
+        facts.extend(vec![
+            Alloca(0, 100),
+            Alloca(1, 101),
+        ]);
+
+        facts.extend(vec![
+            Call(10, 50),
+            Call(11, 50),
+        ]);
+
+        facts.extend(vec![
+            Actual(10, 0, 0),
+            Actual(11, 0, 1),
+        ]);
+
+        facts.extend(vec![
+            Formal(50, 0, 60),
+        ]);
+
+        facts.extend(vec![
+            Ret(50, 60),
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        // context insensitive, both calls may return either mutex
+        assert!(posts.points_to.contains(&PointsTo(60, 100, 0)));
+        assert!(posts.points_to.contains(&PointsTo(60, 101, 0)));
+        assert!(posts.points_to.contains(&PointsTo(10, 100, 0)));
+        assert!(posts.points_to.contains(&PointsTo(11, 101, 0)));
+
+    }
+
+    #[test]
+    pub fn test_points_to_keeps_fields_apart() {
+
+        // struct State { a : Mutex, b : Mutex }, locked through `&self.a` and `&self.b`
+        let mut facts = PointsToPriors::new();
+        facts.extend(vec![
+            Alloca(0, 100), // the state
+            Alloca(1, 101), // the slot of `&state`
+        ]);
+
+        facts.extend(vec![
+            Store(1, 0),
+        ]);
+
+        facts.extend(vec![
+            Load(2, 1),
+        ]);
+
+        facts.extend(vec![
+            Gep(3, 2, 8),  // self.a
+            Gep(4, 2, 16), // self.b
+            Gep(5, 0, 8),  // state.a, directly
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        assert!(posts.points_to.contains(&PointsTo(3, 100, 8)));
+        assert!(posts.points_to.contains(&PointsTo(4, 100, 16)));
+        assert!(posts.points_to.contains(&PointsTo(5, 100, 8)));
+        assert!(!posts.points_to.contains(&PointsTo(3, 100, 16)));
+
+    }
+
+    #[test]
+    pub fn test_points_to_unifies_gep_chains() {
+
+        // `&state.inner.c` as one GEP, as two, and through `&state.inner` passed around
+        let mut facts = PointsToPriors::new();
+        facts.extend(vec![
+            Alloca(0, 100), // the state
+            Alloca(4, 101), // the slot of `&state.inner`
+        ]);
+
+        facts.extend(vec![
+            Gep(1, 0, 20), // state.inner.c
+            Gep(2, 0, 16), // state.inner
+            Gep(3, 2, 4),  // .c
+            Gep(6, 5, 4),  // (*inner).c
+        ]);
+
+        facts.extend(vec![
+            Store(4, 2),
+        ]);
+
+        facts.extend(vec![
+            Load(5, 4),
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        assert!(posts.points_to.contains(&PointsTo(1, 100, 20)));
+        assert!(posts.points_to.contains(&PointsTo(3, 100, 20)));
+        assert!(posts.points_to.contains(&PointsTo(6, 100, 20)));
+        assert!(!posts.points_to.contains(&PointsTo(3, 100, 16)));
+
+    }
+
+    #[test]
+    pub fn test_points_to_memcpy_keeps_offsets() {
+
+        // a guard at offset 8 of a value moved into offset 32 of a future's state
+        let mut facts = PointsToPriors::new();
+        facts.extend(vec![
+            Alloca(0, 100), // the moved value
+            Alloca(1, 101), // the state
+            Alloca(2, 200), // the mutex
+        ]);
+
+        facts.extend(vec![
+            Gep(3, 0, 8),
+            Gep(4, 1, 32),
+            Gep(5, 1, 40),
+        ]);
+
+        facts.extend(vec![
+            Store(3, 2),
+        ]);
+
+        facts.extend(vec![
+            Memcpy(4, 0),
+        ]);
+
+        facts.extend(vec![
+            Load(6, 5),
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        assert!(posts.heap_points_to.contains(&HeapPointsTo(101, 40, 200, 0)));
+        assert!(posts.points_to.contains(&PointsTo(6, 200, 0)));
+        assert!(!posts.heap_points_to.contains(&HeapPointsTo(101, 32, 200, 0)));
+
+    }
+
+    #[test]
+    pub fn test_points_to_hold_covers_the_guard() {
+
+        // lock(sret %result, %m) where the guard sits after the discriminant of a 24 byte result
+        let mut facts = PointsToPriors::new();
+        facts.extend(vec![
+            Alloca(0, 100), // %result
+            Alloca(1, 200), // %m
+        ]);
+
+        facts.extend(vec![
+            Hold(0, 1, 24),
+        ]);
+
+        facts.extend(vec![
+            Gep(2, 0, 8),
+        ]);
+
+        facts.extend(vec![
+            Load(3, 2),
+        ]);
+
+        let posts = facts.compute();
+
+        println!("{:?}", posts);
+
+        assert!(posts.points_to.contains(&PointsTo(3, 200, 0)));
+        assert!(!posts.heap_points_to.contains(&HeapPointsTo(100, 24, 200, 0)));
+
+    }
+
+    #[test]
+    pub fn test_points_to_loops_terminate() {
+
+        // p = &buffer; loop { p = p + 8 }
+        let mut facts = PointsToPriors::new();
+        facts.extend(vec![
+            Alloca(0, 100),
+        ]);
+
+        facts.extend(vec![
+            Bitcast(1, 0),
+            Bitcast(1, 2),
+        ]);
+
+        facts.extend(vec![
+            Gep(2, 1, 8),
+        ]);
+
+        let posts = facts.compute();
+
+        assert!(posts.points_to.contains(&PointsTo(2, 100, MAX_OFFSET)));
+        assert!(posts.points_to.len() <= 2 * (MAX_OFFSET as usize / 8 + 2));
+
+    }
+
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PointsToPosts {
+    pub points_to : HashSet<PointsTo>,
+    pub heap_points_to : HashSet<HeapPointsTo>
+}
+
+impl PointsToPosts {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+}
+
+impl From<(HashSet<PointsTo>, HashSet<HeapPointsTo>)> for PointsToPosts {
+    fn from(outputs : (HashSet<PointsTo>, HashSet<HeapPointsTo>)) -> Self {
+        PointsToPosts {
+            points_to : outputs.0,
+            heap_points_to : outputs.1
+        }
+    }
+}
+
+pub mod generic {
+
+    use std::collections::HashSet;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Alloca<Symbol>(pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Gep<Symbol>(pub Symbol, pub Symbol, pub i64);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Bitcast<Symbol>(pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Store<Symbol>(pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Load<Symbol>(pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Hold<Symbol>(pub Symbol, pub Symbol, pub i64);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Memcpy<Symbol>(pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Call<Symbol>(pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Actual<Symbol>(pub Symbol, pub usize, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Formal<Symbol>(pub Symbol, pub usize, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Ret<Symbol>(pub Symbol, pub Symbol);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct PointsTo<Symbol>(pub Symbol, pub Symbol, pub i64);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct HeapPointsTo<Symbol>(pub Symbol, pub i64, pub Symbol, pub i64);
+
+    #[derive(Debug)]
+    pub struct PointsToPriors<Symbol> {
+        pub allocas : HashSet<Alloca<Symbol>>,
+        pub geps : HashSet<Gep<Symbol>>,
+        pub bitcasts : HashSet<Bitcast<Symbol>>,
+        pub stores : HashSet<Store<Symbol>>,
+        pub loads : HashSet<Load<Symbol>>,
+        pub holds : HashSet<Hold<Symbol>>,
+        pub memcpys : HashSet<Memcpy<Symbol>>,
+        pub calls : HashSet<Call<Symbol>>,
+        pub actuals : HashSet<Actual<Symbol>>,
+        pub formals : HashSet<Formal<Symbol>>,
+        pub rets : HashSet<Ret<Symbol>>
+    }
+
+    impl <Symbol> PointsToPriors<Symbol> {
+
+        pub fn new() -> Self {
+            Self {
+                allocas : HashSet::new(),
+                geps : HashSet::new(),
+                bitcasts : HashSet::new(),
+                stores : HashSet::new(),
+                loads : HashSet::new(),
+                holds : HashSet::new(),
+                memcpys : HashSet::new(),
+                calls : HashSet::new(),
+                actuals : HashSet::new(),
+                formals : HashSet::new(),
+                rets : HashSet::new()
+            }
+        }
+
+    }
+
+    impl <Symbol> Default for PointsToPriors<Symbol> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct PointsToPosts<Symbol> {
+        pub points_to : HashSet<PointsTo<Symbol>>,
+        pub heap_points_to : HashSet<HeapPointsTo<Symbol>>
+    }
+
+    impl <Symbol> PointsToPosts<Symbol> {
+
+        pub fn new() -> Self {
+            Self {
+                points_to : HashSet::new(),
+                heap_points_to : HashSet::new()
+            }
+        }
+
+    }
+
+    impl <Symbol> Default for PointsToPosts<Symbol> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+}
@@ -0,0 +1,499 @@
+use crate::util::insertion_order_map::InsertionOrderMap;
+use super::{facts, generic::*};
+
+#[derive(Clone, Debug)]
+pub struct Program<Symbol : std::cmp::Eq + std::hash::Hash> {
+
+    // we need a mapping of the symbols to numbers
+    pub symbol_mapping : InsertionOrderMap<Symbol>,
+
+    // now we want collections of all of the different types of facts
+    priors : facts::PointsToPriors
+
+}
+
+impl <Symbol> Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    pub fn new() -> Self {
+        Self {
+            symbol_mapping : InsertionOrderMap::new(),
+            priors : facts::PointsToPriors::new()
+        }
+    }
+
+    pub fn priors(&self) -> PointsToPriors<&Symbol> {
+
+        let mut priors = PointsToPriors::new();
+
+        for alloca in self.priors.allocas.iter() {
+            let alloca = Alloca(
+                self.symbol_mapping.unmap(alloca.0).unwrap(), 
+                self.symbol_mapping.unmap(alloca.1).unwrap()
+            );
+            priors.allocas.insert(alloca);
+        }
+
+        for gep in self.priors.geps.iter() {
+            let gep = Gep(
+                self.symbol_mapping.unmap(gep.0).unwrap(), 
+                self.symbol_mapping.unmap(gep.1).unwrap(), 
+                gep.2
+            );
+            priors.geps.insert(gep);
+        }
+
+        for bitcast in self.priors.bitcasts.iter() {
+            let bitcast = Bitcast(
+                self.symbol_mapping.unmap(bitcast.0).unwrap(), 
+                self.symbol_mapping.unmap(bitcast.1).unwrap()
+            );
+            priors.bitcasts.insert(bitcast);
+        }
+
+        for store in self.priors.stores.iter() {
+            let store = Store(
+                self.symbol_mapping.unmap(store.0).unwrap(), 
+                self.symbol_mapping.unmap(store.1).unwrap()
+            );
+            priors.stores.insert(store);
+        }
+
+        for load in self.priors.loads.iter() {
+            let load = Load(
+                self.symbol_mapping.unmap(load.0).unwrap(), 
+                self.symbol_mapping.unmap(load.1).unwrap()
+            );
+            priors.loads.insert(load);
+        }
+
+        for hold in self.priors.holds.iter() {
+            let hold = Hold(
+                self.symbol_mapping.unmap(hold.0).unwrap(), 
+                self.symbol_mapping.unmap(hold.1).unwrap(), 
+                hold.2
+            );
+            priors.holds.insert(hold);
+        }
+
+        for memcpy in self.priors.memcpys.iter() {
+            let memcpy = Memcpy(
+                self.symbol_mapping.unmap(memcpy.0).unwrap(), 
+                self.symbol_mapping.unmap(memcpy.1).unwrap()
+            );
+            priors.memcpys.insert(memcpy);
+        }
+
+        for call in self.priors.calls.iter() {
+            let call = Call(
+                self.symbol_mapping.unmap(call.0).unwrap(), 
+                self.symbol_mapping.unmap(call.1).unwrap()
+            );
+            priors.calls.insert(call);
+        }
+
+        for actual in self.priors.actuals.iter() {
+            let actual = Actual(
+                self.symbol_mapping.unmap(actual.0).unwrap(), 
+                actual.1, 
+                self.symbol_mapping.unmap(actual.2).unwrap()
+            );
+            priors.actuals.insert(actual);
+        }
+
+        for formal in self.priors.formals.iter() {
+            let formal = Formal(
+                self.symbol_mapping.unmap(formal.0).unwrap(), 
+                formal.1, 
+                self.symbol_mapping.unmap(formal.2).unwrap()
+            );
+            priors.formals.insert(formal);
+        }
+
+        for ret in self.priors.rets.iter() {
+            let ret = Ret(
+                self.symbol_mapping.unmap(ret.0).unwrap(), 
+                self.symbol_mapping.unmap(ret.1).unwrap()
+            );
+            priors.rets.insert(ret);
+        }
+
+        priors
+
+    }
+
+    pub fn compute(&self) -> PointsToPosts<&Symbol> {
+
+        let inner_posts = self.priors.compute();
+        let mut posts = PointsToPosts::new();
+
+        for points_to in inner_posts.points_to.iter() {
+            let points_to = PointsTo(
+                self.symbol_mapping.unmap(points_to.0).unwrap(), 
+                self.symbol_mapping.unmap(points_to.1).unwrap(), 
+                points_to.2
+            );
+            posts.points_to.insert(points_to);
+        }
+
+        for heap_points_to in inner_posts.heap_points_to.iter() {
+            let heap_points_to = HeapPointsTo(
+                self.symbol_mapping.unmap(heap_points_to.0).unwrap(), 
+                heap_points_to.1, 
+                self.symbol_mapping.unmap(heap_points_to.2).unwrap(), 
+                heap_points_to.3
+            );
+            posts.heap_points_to.insert(heap_points_to);
+        }
+
+        posts
+
+    }
+
+}
+
+impl <Symbol> Default for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <Symbol> facts::Extendable<Alloca<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Alloca<Symbol>>
+    {
+
+        for alloca in iter.into_iter() {
+            let alloca = facts::Alloca(
+                self.symbol_mapping.map(alloca.0), 
+                self.symbol_mapping.map(alloca.1)
+            );
+            self.priors.allocas.insert(alloca);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Gep<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Gep<Symbol>>
+    {
+
+        for gep in iter.into_iter() {
+            let gep = facts::Gep(
+                self.symbol_mapping.map(gep.0), 
+                self.symbol_mapping.map(gep.1), 
+                gep.2
+            );
+            self.priors.geps.insert(gep);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Bitcast<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Bitcast<Symbol>>
+    {
+
+        for bitcast in iter.into_iter() {
+            let bitcast = facts::Bitcast(
+                self.symbol_mapping.map(bitcast.0), 
+                self.symbol_mapping.map(bitcast.1)
+            );
+            self.priors.bitcasts.insert(bitcast);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Store<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Store<Symbol>>
+    {
+
+        for store in iter.into_iter() {
+            let store = facts::Store(
+                self.symbol_mapping.map(store.0), 
+                self.symbol_mapping.map(store.1)
+            );
+            self.priors.stores.insert(store);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Load<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Load<Symbol>>
+    {
+
+        for load in iter.into_iter() {
+            let load = facts::Load(
+                self.symbol_mapping.map(load.0), 
+                self.symbol_mapping.map(load.1)
+            );
+            self.priors.loads.insert(load);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Hold<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Hold<Symbol>>
+    {
+
+        for hold in iter.into_iter() {
+            let hold = facts::Hold(
+                self.symbol_mapping.map(hold.0), 
+                self.symbol_mapping.map(hold.1), 
+                hold.2
+            );
+            self.priors.holds.insert(hold);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Memcpy<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Memcpy<Symbol>>
+    {
+
+        for memcpy in iter.into_iter() {
+            let memcpy = facts::Memcpy(
+                self.symbol_mapping.map(memcpy.0), 
+                self.symbol_mapping.map(memcpy.1)
+            );
+            self.priors.memcpys.insert(memcpy);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Call<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Call<Symbol>>
+    {
+
+        for call in iter.into_iter() {
+            let call = facts::Call(
+                self.symbol_mapping.map(call.0), 
+                self.symbol_mapping.map(call.1)
+            );
+            self.priors.calls.insert(call);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Actual<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Actual<Symbol>>
+    {
+
+        for actual in iter.into_iter() {
+            let actual = facts::Actual(
+                self.symbol_mapping.map(actual.0), 
+                actual.1, 
+                self.symbol_mapping.map(actual.2)
+            );
+            self.priors.actuals.insert(actual);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Formal<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Formal<Symbol>>
+    {
+
+        for formal in iter.into_iter() {
+            let formal = facts::Formal(
+                self.symbol_mapping.map(formal.0), 
+                formal.1, 
+                self.symbol_mapping.map(formal.2)
+            );
+            self.priors.formals.insert(formal);
+        }
+        self
+    }
+
+}
+
+impl <Symbol> facts::Extendable<Ret<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Ret<Symbol>>
+    {
+
+        for ret in iter.into_iter() {
+            let ret = facts::Ret(
+                self.symbol_mapping.map(ret.0), 
+                self.symbol_mapping.map(ret.1)
+            );
+            self.priors.rets.insert(ret);
+        }
+        self
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use facts::Extendable;
+
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct MySymbol(String);
+
+    #[test]
+    fn test_maps_and_unmaps_guard_through_sret() {
+
+        let mut program : Program<MySymbol> = Program::new();
+
+        // lock(sret %_3, %m); store (load %_3 + 8) -> %guard; drop_in_place(%guard)
+        program.extend(vec![
+            Alloca(MySymbol("%m".to_string()), MySymbol("obj m".to_string())),
+            Alloca(MySymbol("%_3".to_string()), MySymbol("obj _3".to_string())),
+            Alloca(MySymbol("%guard".to_string()), MySymbol("obj guard".to_string())),
+        ]);
+
+        program.extend(vec![
+            Gep(MySymbol("%_3.1".to_string()), MySymbol("%_3".to_string()), 8),
+        ]);
+
+        program.extend(vec![
+            Hold(MySymbol("%_3".to_string()), MySymbol("%m".to_string()), 24),
+        ]);
+
+        program.extend(vec![
+            Store(MySymbol("%guard".to_string()), MySymbol("%t".to_string())),
+        ]);
+
+        program.extend(vec![
+            Load(MySymbol("%t".to_string()), MySymbol("%_3.1".to_string())),
+        ]);
+
+        let posts = program.compute();
+
+        println!("{:#?}", posts);
+
+        let guard = MySymbol("obj guard".to_string());
+        let m = MySymbol("obj m".to_string());
+        assert!(posts.heap_points_to.contains(&HeapPointsTo(&guard, 0, &m, 0)));
+
+    }
+
+    #[test]
+    fn test_maps_and_unmaps_call_indices() {
+
+        let mut program : Program<MySymbol> = Program::new();
+
+        program.extend(vec![
+            Alloca(MySymbol("%a".to_string()), MySymbol("obj a".to_string())),
+            Alloca(MySymbol("%b".to_string()), MySymbol("obj b".to_string())),
+        ]);
+
+        program.extend(vec![
+            Call(MySymbol("call".to_string()), MySymbol("@second".to_string())),
+        ]);
+
+        program.extend(vec![
+            Actual(MySymbol("call".to_string()), 0, MySymbol("%a".to_string())),
+            Actual(MySymbol("call".to_string()), 1, MySymbol("%b".to_string())),
+        ]);
+
+        program.extend(vec![
+            Formal(MySymbol("@second".to_string()), 1, MySymbol("%y".to_string())),
+        ]);
+
+        program.extend(vec![
+            Ret(MySymbol("@second".to_string()), MySymbol("%y".to_string())),
+        ]);
+
+        let posts = program.compute();
+
+        println!("{:#?}", posts);
+
+        let call = MySymbol("call".to_string());
+        let a = MySymbol("obj a".to_string());
+        let b = MySymbol("obj b".to_string());
+        assert!(posts.points_to.contains(&PointsTo(&call, &b, 0)));
+        assert!(!posts.points_to.contains(&PointsTo(&call, &a, 0)));
+
+    }
+
+}
\ No newline at end of file
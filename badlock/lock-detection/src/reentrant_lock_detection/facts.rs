@@ -16,6 +16,11 @@ crepe! {
     #[derive(Debug)]
     pub struct Next(pub usize, pub usize);
 
+    // a call, the first instruction of the callee it continues into
+    @input
+    #[derive(Debug)]
+    pub struct Call(pub usize, pub usize);
+
     @input
     #[derive(Debug)]
     pub struct Wrap(pub usize, pub usize);
@@ -58,6 +63,8 @@ crepe! {
     Out(inst, inst) <- Def(_, inst);
     Out(inst, def_inst) <- In(inst, def_inst), !Kill(inst, def_inst);
     In(inst, def_inst) <- Out(prev_inst, def_inst), Next(prev_inst, inst);
+    // the callee starts from what reaches the call, not from what the call itself defines
+    In(entry, def_inst) <- In(call_inst, def_inst), Call(call_inst, entry);
 
     // Deadlock taint
     Edge(from_inst, to_inst, var) <- Def(var, from_inst), UseVar(var, to_inst), In(to_inst, from_inst);
@@ -81,6 +88,7 @@ pub struct ReentrantDeadlockPriors {
     pub defs : HashSet<Def>,
     pub use_vars : HashSet<UseVar>,
     pub nexts : HashSet<Next>,
+    pub calls : HashSet<Call>,
     pub wraps : HashSet<Wrap>,
     pub locks : HashSet<Lock>,
     pub releases : HashSet<Release>
@@ -93,6 +101,7 @@ impl ReentrantDeadlockPriors {
             defs : HashSet::new(),
             use_vars : HashSet::new(),
             nexts : HashSet::new(),
+            calls : HashSet::new(),
             wraps : HashSet::new(),
             locks : HashSet::new(),
             releases : HashSet::new()
@@ -106,6 +115,7 @@ impl ReentrantDeadlockPriors {
         runtime.extend(self.defs.iter().cloned());
         runtime.extend(self.use_vars.iter().cloned());
         runtime.extend(self.nexts.iter().cloned());
+        runtime.extend(self.calls.iter().cloned());
         runtime.extend(self.wraps.iter().cloned());
         runtime.extend(self.locks.iter().cloned());
         runtime.extend(self.releases.iter().cloned());
@@ -142,6 +152,14 @@ impl Extendable<Next> for ReentrantDeadlockPriors {
     }
 }
 
+impl Extendable<Call> for ReentrantDeadlockPriors {
+    fn extend<Iter>(&mut self, values : Iter) -> &mut Self
+            where Iter : IntoIterator<Item = Call> {
+        self.calls.extend(values);
+        self
+    }
+}
+
 impl Extendable<Wrap> for ReentrantDeadlockPriors {
     fn extend<Iter>(&mut self, values : Iter) -> &mut Self
             where Iter : IntoIterator<Item = Wrap> {
@@ -208,6 +226,104 @@ pub mod test {
 
     }
 
+    #[test]
+    pub fn test_reentrant_deadlocks_along_call_chain() {
+
+        // fn caller() { let _g = M.lock(); callee(); }  fn callee() { M.lock(); }, M being 7,
+        // while 8 is locked at 20 and released at 21 before it is locked again at 30
+        let mut facts = ReentrantDeadlockPriors::new();
+
+        facts.extend(vec![
+            Def(7, 0),
+            Def(7, 10),
+            Def(8, 20),
+            Def(8, 21),
+            Def(8, 30),
+        ]);
+
+        facts.extend(vec![
+            UseVar(7, 0),
+            UseVar(7, 10),
+            UseVar(8, 20),
+            UseVar(8, 21),
+            UseVar(8, 30),
+        ]);
+
+        facts.extend(vec![
+            Next(0, 1),
+            Next(1, 2),
+            Next(1, 10), // the call continues into the callee
+            Next(10, 11),
+            Next(20, 21),
+            Next(21, 22),
+            Next(22, 23),
+            Next(22, 30),
+            Next(30, 31),
+        ]);
+
+        facts.extend(vec![
+            Lock(0, 7),
+            Lock(10, 7),
+            Lock(20, 8),
+            Lock(30, 8),
+        ]);
+
+        facts.extend(vec![
+            Release(21, 8),
+        ]);
+
+        let posts = facts.compute();
+
+        assert_eq!(posts.deadlock.len(), 1);
+        assert!(posts.deadlock.contains(&Deadlock(0, 7, 10)));
+
+    }
+
+    #[test]
+    pub fn test_callees_start_from_what_reaches_the_call() {
+
+        // fn state() -> Guard { M.lock() }  fn main() { let _a = state(); let _b = state(); }, M
+        // being 7, with each call to `state` at 0 and 1 returning it held
+        let mut facts = ReentrantDeadlockPriors::new();
+
+        facts.extend(vec![
+            Def(7, 0),
+            Def(7, 1),
+            Def(7, 10),
+        ]);
+
+        facts.extend(vec![
+            UseVar(7, 0),
+            UseVar(7, 1),
+            UseVar(7, 10),
+        ]);
+
+        facts.extend(vec![
+            Next(0, 1),
+            Next(1, 2),
+            Next(10, 11),
+        ]);
+
+        facts.extend(vec![
+            Call(0, 10),
+            Call(1, 10),
+        ]);
+
+        facts.extend(vec![
+            Lock(0, 7),
+            Lock(1, 7),
+            Lock(10, 7),
+        ]);
+
+        let posts = facts.compute();
+
+        // the second call locks M again in `state`, but no call holds the guard it returns meanwhile
+        assert!(posts.deadlock.contains(&Deadlock(0, 7, 10)));
+        assert!(!posts.deadlock.contains(&Deadlock(1, 7, 10)));
+        assert!(!posts.in_.contains(&In(10, 1)));
+
+    }
+
     #[test]
     pub fn test_reentrant_deadlocks_simple_lock_and_release() {
 
@@ -343,6 +459,9 @@ pub mod generic {
     #[derive(Debug, Clone, PartialEq, Eq, Hash)]
     pub struct Next<Symbol>(pub Symbol, pub Symbol);
 
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Call<Symbol>(pub Symbol, pub Symbol);
+
     #[derive(Debug, Clone, PartialEq, Eq, Hash)]
     pub struct Wrap<Symbol>(pub Symbol, pub Symbol);
 
@@ -375,6 +494,7 @@ pub mod generic {
         pub defs : HashSet<Def<Symbol>>,
         pub use_vars : HashSet<UseVar<Symbol>>,
         pub nexts : HashSet<Next<Symbol>>,
+        pub calls : HashSet<Call<Symbol>>,
         pub wraps : HashSet<Wrap<Symbol>>,
         pub locks : HashSet<Lock<Symbol>>,
         pub releases : HashSet<Release<Symbol>>
@@ -387,6 +507,7 @@ pub mod generic {
                 defs : HashSet::new(),
                 use_vars : HashSet::new(),
                 nexts : HashSet::new(),
+                calls : HashSet::new(),
                 wraps : HashSet::new(),
                 locks : HashSet::new(),
                 releases : HashSet::new()
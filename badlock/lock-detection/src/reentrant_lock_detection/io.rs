@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::Serialize;
+use super::facts::Extendable;
+use super::generic::*;
+use super::program::Program;
+
+/// The relations of the reentrant deadlock program, by name, with the names of their columns.
+pub const INPUTS : [(&str, &[&str]); 7] = [
+    ("def", &["var", "inst"]),
+    ("use_var", &["var", "inst"]),
+    ("next", &["from", "to"]),
+    ("call", &["call", "entry"]),
+    ("wrap", &["wrapper", "var"]),
+    ("lock", &["inst", "var"]),
+    ("release", &["inst", "var"])
+];
+
+pub const OUTPUTS : [(&str, &[&str]); 6] = [
+    ("kill", &["inst", "killed"]),
+    ("in", &["inst", "def"]),
+    ("out", &["inst", "def"]),
+    ("deadlock", &["first", "var", "second"]),
+    ("edge", &["from", "to", "var"]),
+    ("path", &["from", "to", "var"])
+];
+
+/// How priors are written down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    // one `<relation>.facts` file of tab separated tuples per relation, as Soufflé reads them
+    Facts,
+    // an object of relation names to arrays of tuples
+    Json,
+    // `relation(a, b).` per line
+    Dsl
+}
+
+impl InputFormat {
+
+    /// Directories and `.facts` files are facts, `.json` files JSON and anything else the DSL.
+    pub fn from_path(path : &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            _ if path.is_dir() => InputFormat::Facts,
+            Some("facts") => InputFormat::Facts,
+            Some("json") => InputFormat::Json,
+            _ => InputFormat::Dsl
+        }
+    }
+
+}
+
+impl std::str::FromStr for InputFormat {
+
+    type Err = anyhow::Error;
+
+    fn from_str(format : &str) -> Result<Self, Self::Err> {
+        match format {
+            "facts" => Ok(InputFormat::Facts),
+            "json" => Ok(InputFormat::Json),
+            "dsl" => Ok(InputFormat::Dsl),
+            _ => Err(anyhow::anyhow!("unknown input format `{}`, expected `facts`, `json` or `dsl`", format))
+        }
+    }
+
+}
+
+/// The relation a name refers to, accepting e.g. `UseVar`, `use_var` and `usevar` alike.
+pub fn relation(name : &str) -> Option<&'static str> {
+
+    let normalised = name.to_lowercase().replace('_', "");
+    INPUTS.iter().chain(OUTPUTS.iter())
+    .map(|(relation, _)| *relation)
+    .find(|relation| relation.replace('_', "") == normalised)
+
+}
+
+fn columns(relation : &str) -> &'static [&'static str] {
+    INPUTS.iter().chain(OUTPUTS.iter())
+    .find(|(name, _)| *name == relation)
+    .map(|(_, columns)| *columns)
+    .unwrap_or(&[])
+}
+
+/// Adds a tuple of an input relation to the program.
+pub fn add(program : &mut Program<String>, name : &str, tuple : Vec<String>) -> Result<(), anyhow::Error> {
+
+    let relation = relation(name).ok_or(anyhow::anyhow!("unknown relation `{}`", name))?;
+    if !INPUTS.iter().any(|(input, _)| *input == relation) {
+        anyhow::bail!("`{}` is computed by the program and cannot be given", relation);
+    }
+    if tuple.len() != columns(relation).len() {
+        anyhow::bail!("`{}` takes {} symbols but got {}", relation, columns(relation).len(), tuple.len());
+    }
+
+    let mut tuple = tuple.into_iter();
+    let (a, b) = (tuple.next().unwrap(), tuple.next().unwrap());
+
+    match relation {
+        "def" => program.extend(vec![Def(a, b)]),
+        "use_var" => program.extend(vec![UseVar(a, b)]),
+        "next" => program.extend(vec![Next(a, b)]),
+        "call" => program.extend(vec![Call(a, b)]),
+        "wrap" => program.extend(vec![Wrap(a, b)]),
+        "lock" => program.extend(vec![Lock(a, b)]),
+        "release" => program.extend(vec![Release(a, b)]),
+        _ => unreachable!("all input relations are handled")
+    };
+
+    Ok(())
+
+}
+
+/// Reads tab separated tuples of one relation.
+pub fn read_tsv(program : &mut Program<String>, name : &str, text : &str) -> Result<(), anyhow::Error> {
+
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let tuple = line.split('\t').map(|symbol| symbol.to_string()).collect();
+        add(program, name, tuple).map_err(|error| anyhow::anyhow!("line {}: {}", number + 1, error))?;
+    }
+
+    Ok(())
+
+}
+
+pub fn read_json(program : &mut Program<String>, text : &str) -> Result<(), anyhow::Error> {
+
+    let relations : BTreeMap<String, Vec<Vec<String>>> = serde_json::from_str(text)?;
+    for (name, tuples) in relations {
+        for tuple in tuples {
+            add(program, &name, tuple)?;
+        }
+    }
+
+    Ok(())
+
+}
+
+/// Reads `relation(a, b).` lines. Symbols may be quoted to hold commas, and `#` or `//` start a
+/// comment outside of a tuple.
+pub fn read_dsl(program : &mut Program<String>, text : &str) -> Result<(), anyhow::Error> {
+
+    for (number, line) in text.lines().enumerate() {
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        let error = |message : &str| anyhow::anyhow!("line {}: {}", number + 1, message);
+
+        let (name, rest) = line.split_once('(').ok_or(error("expected `relation(a, b)`"))?;
+
+        let mut tuple = Vec::new();
+        let mut symbol = String::new();
+        let mut quoted = false;
+        let mut closed = None;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => tuple.push(std::mem::take(&mut symbol).trim().to_string()),
+                ')' if !quoted => {
+                    tuple.push(std::mem::take(&mut symbol).trim().to_string());
+                    closed = Some(i);
+                    break;
+                },
+                _ => symbol.push(c)
+            }
+        }
+
+        let rest = &rest[closed.ok_or(error("expected a closing `)`"))? + 1..];
+        let rest = rest.trim_start().strip_prefix('.').unwrap_or(rest).trim();
+        if !(rest.is_empty() || rest.starts_with('#') || rest.starts_with("//")) {
+            return Err(error(&format!("unexpected `{}` after the tuple", rest)));
+        }
+
+        add(program, name.trim(), tuple).map_err(|message| error(&message.to_string()))?;
+
+    }
+
+    Ok(())
+
+}
+
+/// Reads priors from a file or, for facts, a directory of `<relation>.facts` files.
+pub fn read(program : &mut Program<String>, path : &Path, format : InputFormat) -> Result<(), anyhow::Error> {
+
+    let read_file = |path : &Path| std::fs::read_to_string(path)
+    .map_err(|error| anyhow::anyhow!("Could not read {}: {}", path.display(), error));
+
+    let result = match format {
+        InputFormat::Facts if path.is_dir() => {
+            let mut entries : Vec<_> = std::fs::read_dir(path)?.collect::<Result<_, _>>()?;
+            entries.sort_by_key(|entry| entry.path());
+            for entry in entries {
+                let path = entry.path();
+                if path.extension().and_then(|extension| extension.to_str()) == Some("facts") {
+                    read(program, &path, format)?;
+                }
+            }
+            Ok(())
+        },
+        InputFormat::Facts => {
+            let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            read_tsv(program, name, &read_file(path)?)
+        },
+        InputFormat::Json => read_json(program, &read_file(path)?),
+        InputFormat::Dsl => read_dsl(program, &read_file(path)?)
+    };
+
+    result.map_err(|error| anyhow::anyhow!("{}: {}", path.display(), error))
+
+}
+
+/// The tuples of one relation, sorted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Table {
+    pub relation : String,
+    pub columns : Vec<String>,
+    pub rows : Vec<Vec<String>>
+}
+
+impl std::fmt::Display for Table {
+
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        let mut widths : Vec<usize> = self.columns.iter().map(|column| column.len()).collect();
+        for row in self.rows.iter() {
+            for (width, symbol) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(symbol.len());
+            }
+        }
+
+        writeln!(f, "{} ({})", self.relation, self.rows.len())?;
+        for row in std::iter::once(&self.columns).chain(self.rows.iter()) {
+            let cells : Vec<_> = row.iter().zip(widths.iter())
+            .map(|(symbol, width)| format!("{:width$}", symbol, width = width))
+            .collect();
+            writeln!(f, "    {}", cells.join("  ").trim_end())?;
+        }
+
+        Ok(())
+
+    }
+
+}
+
+/// Tables of the named relations, input or output, keeping only tuples that mention a symbol
+/// containing one of `symbols` when any are given.
+pub fn tables(program : &Program<String>, relations : &[String], symbols : &[String]) -> Result<Vec<Table>, anyhow::Error> {
+
+    let priors = program.priors();
+    let posts = program.compute();
+
+    let pair = |a : &String, b : &String| vec![a.clone(), b.clone()];
+    let triple = |a : &String, b : &String, c : &String| vec![a.clone(), b.clone(), c.clone()];
+
+    let mut tables = Vec::new();
+    for name in relations {
+
+        let relation = relation(name).ok_or(anyhow::anyhow!("unknown relation `{}`", name))?;
+        let mut rows : Vec<Vec<String>> = match relation {
+            "def" => priors.defs.iter().map(|t| pair(t.0, t.1)).collect(),
+            "use_var" => priors.use_vars.iter().map(|t| pair(t.0, t.1)).collect(),
+            "next" => priors.nexts.iter().map(|t| pair(t.0, t.1)).collect(),
+            "call" => priors.calls.iter().map(|t| pair(t.0, t.1)).collect(),
+            "wrap" => priors.wraps.iter().map(|t| pair(t.0, t.1)).collect(),
+            "lock" => priors.locks.iter().map(|t| pair(t.0, t.1)).collect(),
+            "release" => priors.releases.iter().map(|t| pair(t.0, t.1)).collect(),
+            "kill" => posts.kill.iter().map(|t| pair(t.0, t.1)).collect(),
+            "in" => posts.in_.iter().map(|t| pair(t.0, t.1)).collect(),
+            "out" => posts.out.iter().map(|t| pair(t.0, t.1)).collect(),
+            "deadlock" => posts.deadlock.iter().map(|t| triple(t.0, t.1, t.2)).collect(),
+            "edge" => posts.edge.iter().map(|t| triple(t.0, t.1, t.2)).collect(),
+            "path" => posts.path.iter().map(|t| triple(t.0, t.1, t.2)).collect(),
+            _ => unreachable!("all relations are handled")
+        };
+
+        if !symbols.is_empty() {
+            rows.retain(|row| row.iter().any(|symbol| symbols.iter().any(|filter| symbol.contains(filter.as_str()))));
+        }
+        rows.sort();
+
+        tables.push(Table {
+            relation : relation.to_string(),
+            columns : columns(relation).iter().map(|column| column.to_string()).collect(),
+            rows
+        });
+
+    }
+
+    Ok(tables)
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub const DSL : &str = r#"
+        # x is locked at inst-0 and again at inst-2
+        def(x, inst-0).
+        def(x, inst-2).
+        UseVar(x, inst-0).
+        use_var(x, inst-2). // the second lock
+        next(inst-0, inst-1).
+        next(inst-1, inst-2).
+        lock(inst-0, x).
+        lock(inst-2, x).
+        next("main bb1 #2", "main, bb2 #0"). # symbols as the pass writes them
+    "#;
+
+    #[test]
+    pub fn test_reads_dsl_and_prints_tables() -> Result<(), anyhow::Error> {
+
+        let mut program = Program::new();
+        read_dsl(&mut program, DSL)?;
+
+        let printed = tables(&program, &["deadlock".to_string(), "Lock".to_string()], &[])?;
+        assert_eq!(printed[0].rows, vec![vec!["inst-0", "x", "inst-2"]]);
+        assert_eq!(printed[1].relation, "lock");
+        assert_eq!(printed[1].rows.len(), 2);
+
+        let next = tables(&program, &["next".to_string()], &["main".to_string()])?;
+        assert_eq!(next[0].rows, vec![vec!["main bb1 #2", "main, bb2 #0"]]);
+
+        assert_eq!(
+            printed[0].to_string(),
+            "deadlock (1)\n    first   var  second\n    inst-0  x    inst-2\n"
+        );
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_reads_tsv_and_json_alike() -> Result<(), anyhow::Error> {
+
+        let mut from_tsv = Program::new();
+        read_tsv(&mut from_tsv, "Def", "x\tinst-0\nx\tinst-2\n")?;
+        read_tsv(&mut from_tsv, "UseVar", "x\tinst-0\nx\tinst-2\n")?;
+        read_tsv(&mut from_tsv, "Next", "inst-0\tinst-1\ninst-1\tinst-2\n")?;
+        read_tsv(&mut from_tsv, "Lock", "inst-0\tx\ninst-2\tx\n")?;
+
+        let mut from_json = Program::new();
+        read_json(&mut from_json, r#"{
+            "def" : [["x", "inst-0"], ["x", "inst-2"]],
+            "use_var" : [["x", "inst-0"], ["x", "inst-2"]],
+            "next" : [["inst-0", "inst-1"], ["inst-1", "inst-2"]],
+            "lock" : [["inst-0", "x"], ["inst-2", "x"]]
+        }"#)?;
+
+        let relations = vec!["deadlock".to_string(), "path".to_string(), "in".to_string()];
+        assert_eq!(tables(&from_tsv, &relations, &[])?, tables(&from_json, &relations, &[])?);
+
+        // only tuples mentioning inst-1
+        let filtered = tables(&from_tsv, &["in".to_string()], &["inst-1".to_string()])?;
+        assert!(filtered[0].rows.iter().all(|row| row.iter().any(|symbol| symbol == "inst-1")));
+        assert!(!filtered[0].rows.is_empty());
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_rejects_malformed_priors() {
+
+        let mut program = Program::new();
+        assert!(read_dsl(&mut program, "lock(inst-0)").is_err());
+        assert!(read_dsl(&mut program, "deadlock(a, b, c).").is_err());
+        assert!(read_dsl(&mut program, "unknown(a, b).").is_err());
+        assert!(read_dsl(&mut program, "next(a, b) next(b, c)").is_err());
+        assert!(read_tsv(&mut program, "next", "a\tb\tc").is_err());
+
+    }
+
+}
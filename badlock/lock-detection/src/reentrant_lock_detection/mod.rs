@@ -1,4 +1,5 @@
 pub mod facts;
+pub mod io;
 pub mod program;
 pub use facts::generic;
 pub use facts::generic::*;
@@ -52,6 +52,14 @@ impl <Symbol> Program<Symbol>
             priors.nexts.insert(next);
         }
 
+        for call in self.priors.calls.iter() {
+            let call = Call(
+                self.symbol_mapping.unmap(call.0).unwrap(), 
+                self.symbol_mapping.unmap(call.1).unwrap()
+            );
+            priors.calls.insert(call);
+        }
+
         for wrap in self.priors.wraps.iter() {
             let wrap = Wrap(
                 self.symbol_mapping.unmap(wrap.0).unwrap(), 
@@ -208,6 +216,28 @@ impl <Symbol> facts::Extendable<Next<Symbol>> for Program<Symbol>
 
 }
 
+impl <Symbol> facts::Extendable<Call<Symbol>> for Program<Symbol>
+    where 
+        Symbol : std::cmp::Eq + std::hash::Hash + Clone
+{
+
+    fn extend<Iter>(&mut self, iter : Iter) -> &mut Self
+        where 
+            Iter : IntoIterator<Item = Call<Symbol>>
+    {
+
+        for call in iter.into_iter() {
+            let call = facts::Call(
+                self.symbol_mapping.map(call.0), 
+                self.symbol_mapping.map(call.1)
+            );
+            self.priors.calls.insert(call);
+        }
+        self
+    }
+
+}
+
 impl <Symbol> facts::Extendable<Wrap<Symbol>> for Program<Symbol>
     where 
         Symbol : std::cmp::Eq + std::hash::Hash + Clone
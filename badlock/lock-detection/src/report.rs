@@ -0,0 +1,565 @@
+use serde::{Deserialize, Serialize};
+
+/// Marks the end of a report, anything printed after it is not part of the analysis.
+pub const END_ANALYSIS : &str = "END ANALYSIS";
+
+const MAY_DEADLOCK : &str = "MAY DEADLOCK!";
+const NO_DEADLOCK : &str = "DID NOT FIND A DEADLOCK";
+const SEPARATOR : &str = "__________";
+const FIRST_LOCK : &str = "\tFIRST LOCK";
+const RESOURCE : &str = "\n\n\tRESOURCE";
+const SECOND_LOCK : &str = "\n\n\tSECOND_LOCK";
+const INVERSION : &str = "LOCK ORDER INVERSION #";
+const FIRST_HELD : &str = "\tFIRST HELD";
+const FIRST_RESOURCE : &str = "\n\n\tFIRST RESOURCE";
+const FIRST_ACQUIRED : &str = "\n\n\tFIRST ACQUIRED";
+const SECOND_HELD : &str = "\n\n\tSECOND HELD";
+const SECOND_RESOURCE : &str = "\n\n\tSECOND RESOURCE";
+const SECOND_ACQUIRED : &str = "\n\n\tSECOND ACQUIRED";
+const CYCLE : &str = "LOCK ORDER CYCLE #";
+const HELD : &str = "\tHELD";
+const NEXT_HELD : &str = "\n\n\tHELD";
+const ACQUIRED : &str = "\n\n\tACQUIRED";
+
+/// How sure a finding is to deadlock, ordered so that thresholds compare with `>=`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // deadlocks only under some interleaving of threads
+    Warning,
+    // deadlocks whenever the path is taken
+    Error
+}
+
+impl std::str::FromStr for Severity {
+
+    type Err = anyhow::Error;
+
+    fn from_str(severity : &str) -> Result<Self, Self::Err> {
+        match severity {
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(anyhow::anyhow!("unknown severity `{}`, expected `warning` or `error`", severity))
+        }
+    }
+
+}
+
+impl std::fmt::Display for Severity {
+
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error")
+        }
+    }
+
+}
+
+/// A single `Deadlock(first, var, second)`, with each symbol already rendered for display.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Finding {
+    pub first_lock : String,
+    pub resource : String,
+    pub second_lock : String,
+    // the crates the sites belong to, known when several crates are analysed together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_lock_crate : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_crate : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub second_lock_crate : Option<String>
+}
+
+impl Finding {
+
+    /// Whether the sites of the finding belong to more than one crate.
+    pub fn crosses_crates(&self) -> bool {
+        let crates : Vec<_> = [&self.first_lock_crate, &self.resource_crate, &self.second_lock_crate]
+            .into_iter().flatten().collect();
+        crates.windows(2).any(|pair| pair[0] != pair[1])
+    }
+
+    /// A thread locking what it already holds blocks on itself.
+    pub fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    pub fn sites(&self) -> Vec<&str> {
+        vec![&self.first_lock, &self.second_lock]
+    }
+
+}
+
+/// Two locks taken in both orders, which deadlocks when two threads each hold one of them:
+/// the first resource is held while the second is acquired, and the other way round.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Inversion {
+    pub first_held : String,
+    pub first_resource : String,
+    pub first_acquired : String,
+    pub second_held : String,
+    pub second_resource : String,
+    pub second_acquired : String
+}
+
+impl Inversion {
+
+    /// Two threads have to interleave for the locks to deadlock.
+    pub fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    pub fn sites(&self) -> Vec<&str> {
+        vec![&self.first_held, &self.first_acquired, &self.second_held, &self.second_acquired]
+    }
+
+}
+
+/// One lock of a cycle: `resource` is held, since `held`, while the next lock is acquired at `acquired`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Step {
+    pub held : String,
+    pub resource : String,
+    pub acquired : String
+}
+
+/// Locks taken in a circular order by as many threads, which deadlocks when each holds the lock
+/// of its step. Cycles of two are reported as `Inversion`s.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Cycle {
+    pub steps : Vec<Step>
+}
+
+impl Cycle {
+
+    /// The threads have to interleave for the locks to deadlock.
+    pub fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    pub fn sites(&self) -> Vec<&str> {
+        self.steps.iter().flat_map(|step| [step.held.as_str(), step.acquired.as_str()]).collect()
+    }
+
+}
+
+/// Splits `LABEL (crate): text<rest>` at the next label, e.g. `\tFIRST LOCK (storage): ...`.
+fn labelled<'a>(block : &'a str, label : &str, next : Option<&str>) -> Result<(Option<String>, &'a str, &'a str), anyhow::Error> {
+
+    let block = block.strip_prefix(label).ok_or(
+        anyhow::anyhow!("deadlock block without `{}`", label.trim())
+    )?;
+    let (head, block) = block.split_once(": ").ok_or(
+        anyhow::anyhow!("deadlock block without `{}`", label.trim())
+    )?;
+
+    let crate_name = match head.strip_prefix(" (").and_then(|head| head.strip_suffix(')')) {
+        Some(crate_name) => Some(crate_name.to_string()),
+        None if head.is_empty() => None,
+        None => anyhow::bail!("unexpected `{}` after `{}`", head, label.trim())
+    };
+
+    match next {
+        Some(next) => {
+            let end = block.find(next).ok_or(
+                anyhow::anyhow!("deadlock block without `{}`", next.trim())
+            )?;
+            Ok((crate_name, &block[..end], &block[end..]))
+        },
+        None => Ok((crate_name, block, ""))
+    }
+
+}
+
+fn label(label : &str, crate_name : &Option<String>) -> String {
+    match crate_name {
+        Some(crate_name) => format!("{} ({}): ", label, crate_name),
+        None => format!("{}: ", label)
+    }
+}
+
+fn parse_finding(block : &str) -> Result<Finding, anyhow::Error> {
+
+    let first = block.find(FIRST_LOCK).ok_or(
+        anyhow::anyhow!("deadlock block without a first lock")
+    )?;
+    let (first_lock_crate, first_lock, block) = labelled(&block[first..], FIRST_LOCK, Some(RESOURCE))?;
+    let (resource_crate, resource, block) = labelled(block, RESOURCE, Some(SECOND_LOCK))?;
+    let (second_lock_crate, second_lock, _) = labelled(block, SECOND_LOCK, None)?;
+
+    Ok(Finding {
+        first_lock : first_lock.to_string(),
+        resource : resource.to_string(),
+        second_lock : second_lock.to_string(),
+        first_lock_crate,
+        resource_crate,
+        second_lock_crate
+    })
+
+}
+
+fn parse_inversion(block : &str) -> Result<Inversion, anyhow::Error> {
+
+    let first = block.find(FIRST_HELD).ok_or(
+        anyhow::anyhow!("inversion block without a first held lock")
+    )?;
+    let (_, first_held, block) = labelled(&block[first..], FIRST_HELD, Some(FIRST_RESOURCE))?;
+    let (_, first_resource, block) = labelled(block, FIRST_RESOURCE, Some(FIRST_ACQUIRED))?;
+    let (_, first_acquired, block) = labelled(block, FIRST_ACQUIRED, Some(SECOND_HELD))?;
+    let (_, second_held, block) = labelled(block, SECOND_HELD, Some(SECOND_RESOURCE))?;
+    let (_, second_resource, block) = labelled(block, SECOND_RESOURCE, Some(SECOND_ACQUIRED))?;
+    let (_, second_acquired, _) = labelled(block, SECOND_ACQUIRED, None)?;
+
+    Ok(Inversion {
+        first_held : first_held.to_string(),
+        first_resource : first_resource.to_string(),
+        first_acquired : first_acquired.to_string(),
+        second_held : second_held.to_string(),
+        second_resource : second_resource.to_string(),
+        second_acquired : second_acquired.to_string()
+    })
+
+}
+
+fn parse_cycle(block : &str) -> Result<Cycle, anyhow::Error> {
+
+    let first = block.find(HELD).ok_or(
+        anyhow::anyhow!("cycle block without a held lock")
+    )?;
+
+    let mut steps = Vec::new();
+    let mut block = &block[first..];
+
+    loop {
+
+        let (_, held, rest) = labelled(block, HELD, Some(RESOURCE))?;
+        let (_, resource, rest) = labelled(rest, RESOURCE, Some(ACQUIRED))?;
+        let (acquired, rest) = match rest.find(NEXT_HELD) {
+            Some(_) => {
+                let (_, acquired, rest) = labelled(rest, ACQUIRED, Some(NEXT_HELD))?;
+                (acquired, Some(&rest[2..]))
+            },
+            None => (labelled(rest, ACQUIRED, None)?.1, None)
+        };
+
+        steps.push(Step {
+            held : held.to_string(),
+            resource : resource.to_string(),
+            acquired : acquired.to_string()
+        });
+
+        match rest {
+            Some(rest) => block = rest,
+            None => break
+        }
+
+    }
+
+    Ok(Cycle { steps })
+
+}
+
+/// The findings of one analysis run, printed and parsed in the format `opt` users see.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub findings : Vec<Finding>,
+    // only computed when the lock order analysis runs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inversions : Vec<Inversion>,
+    // cycles of more than two locks, only found in traces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cycles : Vec<Cycle>
+}
+
+impl Report {
+
+    pub fn new(findings : Vec<Finding>) -> Self {
+        Self { findings, inversions : Vec::new(), cycles : Vec::new() }
+    }
+
+    pub fn with_inversions(self, inversions : Vec<Inversion>) -> Self {
+        Self { inversions, ..self }
+    }
+
+    pub fn with_cycles(self, cycles : Vec<Cycle>) -> Self {
+        Self { cycles, ..self }
+    }
+
+    pub fn may_deadlock(&self) -> bool {
+        !self.findings.is_empty() || !self.inversions.is_empty() || !self.cycles.is_empty()
+    }
+
+    /// The most severe of the findings, inversions and cycles, if there are any.
+    pub fn severity(&self) -> Option<Severity> {
+        self.findings.iter().map(Finding::severity)
+        .chain(self.inversions.iter().map(Inversion::severity))
+        .chain(self.cycles.iter().map(Cycle::severity))
+        .max()
+    }
+
+    /// Keeps the findings, inversions and cycles whose lock sites the predicate accepts.
+    pub fn retain(&mut self, mut keep : impl FnMut(&[&str]) -> bool) {
+        self.findings.retain(|finding| keep(&finding.sites()));
+        self.inversions.retain(|inversion| keep(&inversion.sites()));
+        self.cycles.retain(|cycle| keep(&cycle.sites()));
+    }
+
+    /// Parses the report printed by the `reentrant-lock-detection` pass, ignoring any output before it.
+    pub fn parse(output : &str) -> Result<Self, anyhow::Error> {
+
+        let end = output.find(END_ANALYSIS).ok_or(
+            anyhow::anyhow!("the output does not contain a complete analysis")
+        )?;
+        let output = &output[..end];
+
+        if output.contains(NO_DEADLOCK) && !output.contains(MAY_DEADLOCK) {
+            return Ok(Self::default());
+        }
+
+        let start = output.find(MAY_DEADLOCK).ok_or(
+            anyhow::anyhow!("the output does not contain a report")
+        )?;
+
+        let mut report = Self::default();
+        let mut rest = &output[start + MAY_DEADLOCK.len()..];
+
+        // each block reads "__________\nDEADLOCK #i\n\tFIRST LOCK: ..." or
+        // "__________\nLOCK ORDER INVERSION #i\n\tFIRST HELD: ..." up to its closing separator
+        while let Some(open) = rest.find(&format!("{}\n", SEPARATOR)) {
+
+            let block = &rest[open + SEPARATOR.len() + 1..];
+            let close = block.find(&format!("\n{}", SEPARATOR)).ok_or(
+                anyhow::anyhow!("unterminated deadlock block")
+            )?;
+            rest = &block[close + SEPARATOR.len() + 1..];
+            let block = &block[..close];
+
+            if block.starts_with("DEADLOCK #") {
+                report.findings.push(parse_finding(block)?);
+            } else if block.starts_with(INVERSION) {
+                report.inversions.push(parse_inversion(block)?);
+            } else if block.starts_with(CYCLE) {
+                report.cycles.push(parse_cycle(block)?);
+            } else {
+                anyhow::bail!("unknown block `{}`", block.lines().next().unwrap_or_default());
+            }
+
+        }
+
+        if !report.may_deadlock() {
+            anyhow::bail!("the report may deadlock but lists no deadlocks");
+        }
+
+        Ok(report)
+
+    }
+
+}
+
+impl std::fmt::Display for Report {
+
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        if !self.may_deadlock() {
+            return writeln!(f, "{}", NO_DEADLOCK);
+        }
+
+        writeln!(f, "{}", MAY_DEADLOCK)?;
+        for (i, finding) in self.findings.iter().enumerate() {
+            writeln!(
+                f,
+                "{}\nDEADLOCK #{}\n{}{}{}{}{}{}\n{}",
+                SEPARATOR, i,
+                label(FIRST_LOCK, &finding.first_lock_crate), finding.first_lock,
+                label(RESOURCE, &finding.resource_crate), finding.resource,
+                label(SECOND_LOCK, &finding.second_lock_crate), finding.second_lock,
+                SEPARATOR
+            )?;
+        }
+        for (i, inversion) in self.inversions.iter().enumerate() {
+            writeln!(
+                f,
+                "{}\n{}{}\n{}: {}{}: {}{}: {}{}: {}{}: {}{}: {}\n{}",
+                SEPARATOR, INVERSION, i,
+                FIRST_HELD, inversion.first_held,
+                FIRST_RESOURCE, inversion.first_resource,
+                FIRST_ACQUIRED, inversion.first_acquired,
+                SECOND_HELD, inversion.second_held,
+                SECOND_RESOURCE, inversion.second_resource,
+                SECOND_ACQUIRED, inversion.second_acquired,
+                SEPARATOR
+            )?;
+        }
+        for (i, cycle) in self.cycles.iter().enumerate() {
+            write!(f, "{}\n{}{}\n", SEPARATOR, CYCLE, i)?;
+            for (j, step) in cycle.steps.iter().enumerate() {
+                let held = if j == 0 { HELD } else { NEXT_HELD };
+                write!(f, "{}: {}{}: {}{}: {}", held, step.held, RESOURCE, step.resource, ACQUIRED, step.acquired)?;
+            }
+            writeln!(f, "\n{}", SEPARATOR)?;
+        }
+
+        Ok(())
+
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub fn finding(i : usize) -> Finding {
+        Finding {
+            first_lock : format!("main bb1 #{} `call void @lock(ptr %x)`", i),
+            resource : "x: Mutex<i32> in main".to_string(),
+            second_lock : format!("main bb9 #{} `invoke void @lock(ptr %x)\n          to label %bb10 unwind label %cleanup`", i),
+            ..Finding::default()
+        }
+    }
+
+    #[test]
+    pub fn test_report_round_trips_through_display() -> Result<(), anyhow::Error> {
+
+        let report = Report::new(vec![finding(0), finding(1)]);
+        let output = format!("{}{}\n", report, END_ANALYSIS);
+
+        assert!(output.starts_with("MAY DEADLOCK!\n__________\nDEADLOCK #0\n\tFIRST LOCK: main bb1 #0"));
+        assert_eq!(Report::parse(&output)?, report);
+
+        let empty = format!("{}{}\n", Report::default(), END_ANALYSIS);
+        assert_eq!(empty, "DID NOT FIND A DEADLOCK\nEND ANALYSIS\n");
+        assert!(!Report::parse(&empty)?.may_deadlock());
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_report_parse_ignores_surrounding_output() -> Result<(), anyhow::Error> {
+
+        let output = format!(
+            "warning: something unrelated\n{}{}\ntrailing",
+            Report::new(vec![finding(0)]), END_ANALYSIS
+        );
+        assert_eq!(Report::parse(&output)?.findings, vec![finding(0)]);
+
+        assert!(Report::parse("MAY DEADLOCK!\n__________\nDEADLOCK #0\n").is_err());
+        assert!(Report::parse("MAY DEADLOCK!\n__________\nDEADLOCK #0\n\tFIRST LOCK: a\n__________\nEND ANALYSIS").is_err());
+        assert!(Report::parse("MAY DEADLOCK!\nEND ANALYSIS").is_err());
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_report_names_the_crates_of_sites() -> Result<(), anyhow::Error> {
+
+        let cross = Finding {
+            first_lock_crate : Some("service".to_string()),
+            resource_crate : Some("service".to_string()),
+            second_lock_crate : Some("storage".to_string()),
+            ..finding(0)
+        };
+        assert!(cross.crosses_crates());
+        assert!(!finding(0).crosses_crates());
+
+        let report = Report::new(vec![cross, finding(1)]);
+        let output = format!("{}{}", report, END_ANALYSIS);
+        assert!(output.contains("\tFIRST LOCK (service): main bb1 #0"));
+        assert!(output.contains("\tSECOND_LOCK (storage): main bb9 #0"));
+        assert!(output.contains("\tFIRST LOCK: main bb1 #1"));
+        assert_eq!(Report::parse(&output)?, report);
+
+        Ok(())
+
+    }
+
+    pub fn inversion(i : usize) -> Inversion {
+        Inversion {
+            first_held : format!("f bb0 #{} `call void @lock(ptr %a)`", i),
+            first_resource : "a: Mutex<i32> in f".to_string(),
+            first_acquired : format!("f bb1 #{} `call void @lock(ptr %b)`", i),
+            second_held : format!("g bb0 #{} `call void @lock(ptr %b)`", i),
+            second_resource : "b: Mutex<i32> in g".to_string(),
+            second_acquired : format!("g bb1 #{} `call void @lock(ptr %a)`", i)
+        }
+    }
+
+    #[test]
+    pub fn test_report_round_trips_inversions() -> Result<(), anyhow::Error> {
+
+        let report = Report::new(vec![finding(0)]).with_inversions(vec![inversion(0), inversion(1)]);
+        let output = format!("{}{}\n", report, END_ANALYSIS);
+
+        assert!(output.contains("__________\nLOCK ORDER INVERSION #1\n\tFIRST HELD: f bb0 #1"));
+        assert_eq!(Report::parse(&output)?, report);
+
+        // inversions alone may deadlock, and are left out of JSON when there are none
+        let inversions = Report::default().with_inversions(vec![inversion(0)]);
+        assert!(inversions.may_deadlock());
+        assert_eq!(Report::parse(&format!("{}{}", inversions, END_ANALYSIS))?, inversions);
+        assert_eq!(serde_json::to_string(&Report::default())?, r#"{"findings":[]}"#);
+
+        // a reentrant finding outweighs inversions
+        assert_eq!(inversions.severity(), Some(Severity::Warning));
+        assert_eq!(report.severity(), Some(Severity::Error));
+        assert_eq!(Report::default().severity(), None);
+        assert!(Severity::Error >= "warning".parse()?);
+
+        Ok(())
+
+    }
+
+    pub fn cycle(locks : &[&str]) -> Cycle {
+        Cycle {
+            steps : locks.iter().enumerate().map(|(i, lock)| Step {
+                held : format!("src/worker.rs:{}:9 (thread {})", 10 * i, i),
+                resource : lock.to_string(),
+                acquired : format!("src/worker.rs:{}:9 (thread {})", 10 * i + 1, i)
+            }).collect()
+        }
+    }
+
+    #[test]
+    pub fn test_report_round_trips_cycles() -> Result<(), anyhow::Error> {
+
+        let report = Report::default()
+        .with_inversions(vec![inversion(0)])
+        .with_cycles(vec![cycle(&["a", "b", "c"]), cycle(&["d", "e", "f", "g"])]);
+        let output = format!("{}{}\n", report, END_ANALYSIS);
+
+        assert!(output.contains(
+            "__________\nLOCK ORDER CYCLE #0\n\tHELD: src/worker.rs:0:9 (thread 0)\n\n\tRESOURCE: a\n\n\tACQUIRED: src/worker.rs:1:9 (thread 0)\n\n\tHELD: src/worker.rs:10:9"
+        ));
+        assert_eq!(Report::parse(&output)?, report);
+
+        let cycles = Report::default().with_cycles(vec![cycle(&["a", "b", "c"])]);
+        assert!(cycles.may_deadlock());
+        assert_eq!(cycles.severity(), Some(Severity::Warning));
+        assert!(!serde_json::to_string(&Report::default())?.contains("cycles"));
+        assert_eq!(serde_json::from_str::<Report>(&serde_json::to_string(&cycles)?)?, cycles);
+
+        Ok(())
+
+    }
+
+    #[test]
+    pub fn test_report_retains_by_sites() {
+
+        let mut report = Report::new(vec![finding(0), finding(1)])
+        .with_inversions(vec![inversion(0), inversion(1)])
+        .with_cycles(vec![cycle(&["a", "b", "c"]), cycle(&["d", "e"])]);
+
+        // every kind of finding is filtered, cycles by the sites of all their steps, here the third
+        report.retain(|sites| !sites.iter().any(|site| site.contains("#1 ") || site.starts_with("src/worker.rs:20:9")));
+
+        assert_eq!(report.findings, vec![finding(0)]);
+        assert_eq!(report.inversions, vec![inversion(0)]);
+        assert_eq!(report.cycles, vec![cycle(&["d", "e"])]);
+
+    }
+
+}
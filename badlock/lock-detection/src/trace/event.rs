@@ -0,0 +1,125 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+/// A thread or lock, named by a number or a string as the logger wrote it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(u64),
+    Name(String)
+}
+
+impl std::fmt::Display for Id {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Id::Number(id) => write!(f, "{}", id),
+            Id::Name(id) => write!(f, "{}", id)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    // the thread holds `lock` from now on
+    Acquire,
+    Release,
+    // the thread spawns `child`
+    Start,
+    // the thread waits for `child` to finish
+    Join
+}
+
+/// One line of a trace, e.g. `{"thread": 1, "event": "acquire", "lock": "0x7f10", "location": "src/db.rs:42:13", "timestamp": 1037}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub thread : Id,
+    pub event : EventKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock : Option<Id>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub child : Option<Id>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp : Option<u64>
+}
+
+impl Event {
+
+    /// Where the event happened, as reported.
+    pub fn site(&self) -> String {
+        format!("{} (thread {})", self.location.as_deref().unwrap_or("<unknown>"), self.thread)
+    }
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        match self.event {
+            EventKind::Acquire | EventKind::Release if self.lock.is_none() => anyhow::bail!("`{:?}` without a lock", self.event),
+            EventKind::Start | EventKind::Join if self.child.is_none() => anyhow::bail!("`{:?}` without a child", self.event),
+            _ => Ok(())
+        }
+    }
+
+}
+
+/// Reads one event per line, skipping blank ones. When every event has a timestamp, events are
+/// ordered by it, otherwise by line, as traces merged from several logs are seldom in order.
+pub fn parse(text : &str) -> Result<Vec<Event>, anyhow::Error> {
+
+    let mut events = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event : Event = serde_json::from_str(line)
+        .map_err(|error| anyhow::anyhow!("line {}: {}", number + 1, error))?;
+        event.validate().map_err(|error| anyhow::anyhow!("line {}: {}", number + 1, error))?;
+        events.push(event);
+    }
+
+    if events.iter().all(|event| event.timestamp.is_some()) {
+        events.sort_by_key(|event| event.timestamp);
+    }
+
+    Ok(events)
+
+}
+
+pub fn read(path : &Path) -> Result<Vec<Event>, anyhow::Error> {
+
+    let text = std::fs::read_to_string(path)
+    .map_err(|error| anyhow::anyhow!("Could not read {}: {}", path.display(), error))?;
+
+    parse(&text).map_err(|error| anyhow::anyhow!("{}: {}", path.display(), error))
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    #[test]
+    pub fn test_parses_events_in_timestamp_order() -> Result<(), anyhow::Error> {
+
+        let events = parse(concat!(
+            "{\"thread\": \"main\", \"event\": \"release\", \"lock\": 7, \"timestamp\": 20}\n",
+            "\n",
+            "{\"thread\": \"main\", \"event\": \"acquire\", \"lock\": 7, \"location\": \"src/db.rs:42:13\", \"timestamp\": 10}\n"
+        ))?;
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, EventKind::Acquire);
+        assert_eq!(events[0].lock, Some(Id::Number(7)));
+        assert_eq!(events[0].site(), "src/db.rs:42:13 (thread main)");
+
+        let error = parse("{\"thread\": 1, \"event\": \"join\"}").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: `Join` without a child");
+        assert!(parse("\n{\"thread\": 1, \"event\": \"wait\"}").unwrap_err().to_string().starts_with("line 2: "));
+
+        Ok(())
+
+    }
+
+}
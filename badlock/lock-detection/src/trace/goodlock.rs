@@ -0,0 +1,380 @@
+//! Goodlock over a recorded trace: every lock taken while others are held adds a dependency, and
+//! dependencies whose locks form a cycle may deadlock once their threads interleave differently.
+//! A cycle is only reported when nothing in the trace keeps it from happening, that is when its
+//! dependencies are of distinct threads, no lock guards two of them, and no thread segment of one
+//! happens before that of another through thread starts and joins.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::report::{Cycle, Inversion, Report, Step};
+use crate::util::lock_graph::LockGraph;
+use super::event::{Event, EventKind, Id};
+
+/// `held` was held, since `held_at`, while `acquired` was taken at `acquired_at`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    pub thread : Id,
+    pub held : Id,
+    pub held_at : String,
+    pub acquired : Id,
+    pub acquired_at : String,
+    // the other locks the thread held, no other thread can take the same dependency meanwhile
+    pub guards : BTreeSet<Id>,
+    // the segment of the thread between two of its starts or joins
+    pub segment : usize
+}
+
+/// The state of a thread at the current event.
+#[derive(Clone, Debug, Default)]
+struct Thread {
+    // the locks held with the sites they were acquired at, latest last
+    held : Vec<(Id, String)>,
+    segment : usize
+}
+
+/// The dependencies of a trace, fed one event at a time.
+#[derive(Clone, Debug, Default)]
+pub struct Goodlock {
+    dependencies : Vec<Dependency>,
+    seen : HashSet<(Id, Id, Id, BTreeSet<Id>, usize)>,
+    // the order between locks, each edge with the dependencies taken in it
+    graph : LockGraph<Id, Vec<usize>>,
+    threads : HashMap<Id, Thread>,
+    // per segment, the segments that happen before it
+    before : Vec<BTreeSet<usize>>
+}
+
+impl Goodlock {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_events<'a>(events : impl IntoIterator<Item = &'a Event>) -> Self {
+        let mut goodlock = Self::new();
+        for event in events {
+            goodlock.add(event);
+        }
+        goodlock
+    }
+
+    /// A new segment after `predecessors`.
+    fn segment(&mut self, predecessors : &[usize]) -> usize {
+
+        let mut before = BTreeSet::new();
+        for predecessor in predecessors {
+            before.insert(*predecessor);
+            before.extend(self.before[*predecessor].iter().copied());
+        }
+
+        self.before.push(before);
+        self.before.len() - 1
+
+    }
+
+    fn thread(&mut self, id : &Id) -> &mut Thread {
+
+        if !self.threads.contains_key(id) {
+            let segment = self.segment(&[]);
+            self.threads.insert(id.clone(), Thread { held : Vec::new(), segment });
+        }
+
+        self.threads.get_mut(id).unwrap()
+
+    }
+
+    pub fn add(&mut self, event : &Event) {
+
+        let segment = self.thread(&event.thread).segment;
+
+        match (event.event, &event.lock, &event.child) {
+            (EventKind::Acquire, Some(lock), _) => {
+
+                let thread = self.thread(&event.thread);
+                let held = thread.held.clone();
+                thread.held.push((lock.clone(), event.site()));
+
+                // taking a lock again is not an ordering between locks
+                if held.iter().any(|(other, _)| other == lock) {
+                    return;
+                }
+
+                for (other, held_at) in held.iter() {
+
+                    let guards : BTreeSet<Id> = held.iter()
+                    .map(|(guard, _)| guard.clone())
+                    .filter(|guard| guard != other)
+                    .collect();
+
+                    let key = (event.thread.clone(), other.clone(), lock.clone(), guards.clone(), segment);
+                    if !self.seen.insert(key) {
+                        continue;
+                    }
+
+                    let index = self.dependencies.len();
+                    match self.graph.get_mut(other, lock) {
+                        Some(dependencies) => dependencies.push(index),
+                        None => {
+                            self.graph.insert(other.clone(), lock.clone(), vec![index]);
+                        }
+                    }
+
+                    self.dependencies.push(Dependency {
+                        thread : event.thread.clone(),
+                        held : other.clone(),
+                        held_at : held_at.clone(),
+                        acquired : lock.clone(),
+                        acquired_at : event.site(),
+                        guards,
+                        segment
+                    });
+
+                }
+
+            },
+            (EventKind::Release, Some(lock), _) => {
+                let held = &mut self.thread(&event.thread).held;
+                if let Some(index) = held.iter().rposition(|(other, _)| other == lock) {
+                    held.remove(index);
+                }
+            },
+            (EventKind::Start, _, Some(child)) => {
+                // the child's segment follows what the parent did so far, as does the parent's
+                let (parent, child_segment) = (self.segment(&[segment]), self.segment(&[segment]));
+                self.thread(&event.thread).segment = parent;
+                self.thread(child).segment = child_segment;
+            },
+            (EventKind::Join, _, Some(child)) => {
+                let child = self.thread(child).segment;
+                let parent = self.segment(&[segment, child]);
+                self.thread(&event.thread).segment = parent;
+            },
+            _ => ()
+        }
+
+    }
+
+    pub fn dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    pub fn happens_before(&self, first : usize, second : usize) -> bool {
+        self.before[second].contains(&first)
+    }
+
+    /// Whether the dependencies could be taken at the same time.
+    fn concurrent(&self, first : &Dependency, second : &Dependency) -> bool {
+        first.thread != second.thread
+        && first.held != second.held
+        && first.guards.is_disjoint(&second.guards)
+        && !self.happens_before(first.segment, second.segment)
+        && !self.happens_before(second.segment, first.segment)
+    }
+
+    /// Extends `chain` towards cycles through its first dependency, which has the lowest index of
+    /// them so that each cycle is only found from one of its dependencies.
+    fn extend(&self, chain : &mut Vec<usize>, cycles : &mut Vec<Vec<usize>>) {
+
+        let first = &self.dependencies[chain[0]];
+        let last = &self.dependencies[*chain.last().unwrap()];
+
+        // the later dependencies taken while holding the last lock, in the order of the trace
+        let mut following : Vec<usize> = self.graph.successors(&last.acquired)
+        .flat_map(|(_, dependencies)| dependencies.iter().copied())
+        .filter(|index| *index > chain[0])
+        .collect();
+        following.sort_unstable();
+
+        for index in following {
+
+            let next = &self.dependencies[index];
+            if !chain.iter().all(|other| self.concurrent(&self.dependencies[*other], next)) {
+                continue;
+            }
+
+            chain.push(index);
+            if next.acquired == first.held {
+                cycles.push(chain.clone());
+            } else {
+                self.extend(chain, cycles);
+            }
+            chain.pop();
+
+        }
+
+    }
+
+    /// The cycles of dependencies, each as indices into `dependencies`. Only the dependencies on
+    /// a cycle of the lock order are searched from.
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+
+        let mut cycles = Vec::new();
+        for (index, dependency) in self.dependencies.iter().enumerate() {
+            if self.graph.cyclic(&dependency.held, &dependency.acquired) {
+                self.extend(&mut vec![index], &mut cycles);
+            }
+        }
+
+        cycles
+
+    }
+
+    /// The cycles of two locks as inversions and longer ones as cycles, each pair of sites once.
+    pub fn report(&self) -> Report {
+
+        let mut inversions = Vec::new();
+        let mut cycles = Vec::new();
+
+        for cycle in self.cycles() {
+            match cycle.as_slice() {
+                [first, second] => {
+                    let (first, second) = (&self.dependencies[*first], &self.dependencies[*second]);
+                    let inversion = Inversion {
+                        first_held : first.held_at.clone(),
+                        first_resource : first.held.to_string(),
+                        first_acquired : first.acquired_at.clone(),
+                        second_held : second.held_at.clone(),
+                        second_resource : second.held.to_string(),
+                        second_acquired : second.acquired_at.clone()
+                    };
+                    if !inversions.contains(&inversion) {
+                        inversions.push(inversion);
+                    }
+                },
+                steps => {
+                    let cycle = Cycle {
+                        steps : steps.iter().map(|step| &self.dependencies[*step]).map(|step| Step {
+                            held : step.held_at.clone(),
+                            resource : step.held.to_string(),
+                            acquired : step.acquired_at.clone()
+                        }).collect()
+                    };
+                    if !cycles.contains(&cycle) {
+                        cycles.push(cycle);
+                    }
+                }
+            }
+        }
+
+        Report::default().with_inversions(inversions).with_cycles(cycles)
+
+    }
+
+}
+
+/// The potential deadlocks of a trace.
+pub fn analyze(events : &[Event]) -> Report {
+    Goodlock::from_events(events).report()
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    pub fn event(thread : u64, event : EventKind, lock : &str, line : u32) -> Event {
+        Event {
+            thread : Id::Number(thread),
+            event,
+            lock : Some(Id::Name(lock.to_string())),
+            child : None,
+            location : Some(format!("src/worker.rs:{}:9", line)),
+            timestamp : None
+        }
+    }
+
+    pub fn thread(parent : u64, event : EventKind, child : u64) -> Event {
+        Event {
+            thread : Id::Number(parent),
+            event,
+            lock : None,
+            child : Some(Id::Number(child)),
+            location : None,
+            timestamp : None
+        }
+    }
+
+    /// `thread` takes the locks in order and releases them again.
+    pub fn nested(thread : u64, locks : &[&str], line : u32) -> Vec<Event> {
+        locks.iter().enumerate().map(|(i, lock)| event(thread, EventKind::Acquire, lock, line + i as u32))
+        .chain(locks.iter().rev().map(|lock| event(thread, EventKind::Release, lock, line + 10)))
+        .collect()
+    }
+
+    #[test]
+    pub fn test_reports_inversions_between_threads() {
+
+        let events : Vec<Event> = nested(1, &["a", "b"], 10).into_iter()
+        .chain(nested(2, &["b", "a"], 20))
+        .chain(nested(2, &["b", "a"], 20))
+        .collect();
+
+        let report = analyze(&events);
+        assert!(report.cycles.is_empty());
+        assert_eq!(report.inversions, vec![Inversion {
+            first_held : "src/worker.rs:10:9 (thread 1)".to_string(),
+            first_resource : "a".to_string(),
+            first_acquired : "src/worker.rs:11:9 (thread 1)".to_string(),
+            second_held : "src/worker.rs:20:9 (thread 2)".to_string(),
+            second_resource : "b".to_string(),
+            second_acquired : "src/worker.rs:21:9 (thread 2)".to_string()
+        }]);
+
+        // a single thread cannot deadlock with itself
+        let events : Vec<Event> = nested(1, &["a", "b"], 10).into_iter().chain(nested(1, &["b", "a"], 20)).collect();
+        assert!(!analyze(&events).may_deadlock());
+
+    }
+
+    #[test]
+    pub fn test_guard_locks_prune_cycles() {
+
+        // both threads take `g` first, so only one of them can be in between
+        let events : Vec<Event> = nested(1, &["g", "a", "b"], 10).into_iter()
+        .chain(nested(2, &["g", "b", "a"], 20))
+        .collect();
+
+        let goodlock = Goodlock::from_events(&events);
+        assert_eq!(goodlock.dependencies().len(), 6);
+        assert!(goodlock.cycles().is_empty());
+
+    }
+
+    #[test]
+    pub fn test_thread_segments_prune_cycles() {
+
+        // thread 2 runs between its start and join, after thread 1 took its locks and before it takes them again
+        let events : Vec<Event> = nested(1, &["a", "b"], 10).into_iter()
+        .chain(std::iter::once(thread(1, EventKind::Start, 2)))
+        .chain(nested(2, &["b", "a"], 20))
+        .chain(std::iter::once(thread(1, EventKind::Join, 2)))
+        .chain(nested(1, &["a", "b"], 30))
+        .collect();
+        assert!(!analyze(&events).may_deadlock());
+
+        // without the join, thread 1 may take its locks again while thread 2 still runs
+        let events : Vec<Event> = events.into_iter().filter(|event| event.event != EventKind::Join).collect();
+        let report = analyze(&events);
+        assert_eq!(report.inversions.len(), 1);
+        assert!(report.inversions[0].first_held.starts_with("src/worker.rs:20:9"));
+
+    }
+
+    #[test]
+    pub fn test_reports_longer_cycles() {
+
+        let events : Vec<Event> = nested(1, &["a", "b"], 10).into_iter()
+        .chain(nested(2, &["b", "c"], 20))
+        .chain(nested(3, &["c", "a"], 30))
+        .collect();
+
+        let report = analyze(&events);
+        assert!(report.inversions.is_empty());
+        assert_eq!(report.cycles.len(), 1);
+
+        let resources : Vec<&str> = report.cycles[0].steps.iter().map(|step| step.resource.as_str()).collect();
+        assert_eq!(resources, vec!["a", "b", "c"]);
+        assert_eq!(report.cycles[0].steps[2].acquired, "src/worker.rs:31:9 (thread 3)");
+
+    }
+
+}
@@ -0,0 +1,4 @@
+pub mod event;
+pub mod goodlock;
+pub use event::{Event, EventKind, Id};
+pub use goodlock::Goodlock;
@@ -0,0 +1,160 @@
+//! A lock order graph built one dependency at a time: an edge from `held` to `acquired` says that
+//! `acquired` was taken while `held` was held. A new edge closes a cycle when the graph already
+//! leads from `acquired` back to `held`, which is checked as the edge is added.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::hash::Hash;
+
+/// Per lock, the locks taken while it was held, each with what the dependency carries.
+#[derive(Clone, Debug)]
+pub struct LockGraph<Lock : Clone + Ord + Hash, Edge> {
+    edges : HashMap<Lock, BTreeMap<Lock, Edge>>,
+    // per lock, the locks it was taken while holding, so that forgetting it finds its edges
+    predecessors : HashMap<Lock, BTreeSet<Lock>>
+}
+
+impl <Lock : Clone + Ord + Hash, Edge> Default for LockGraph<Lock, Edge> {
+    fn default() -> Self {
+        Self {
+            edges : HashMap::new(),
+            predecessors : HashMap::new()
+        }
+    }
+}
+
+impl <Lock : Clone + Ord + Hash, Edge> LockGraph<Lock, Edge> {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, held : &Lock, acquired : &Lock) -> Option<&Edge> {
+        self.edges.get(held).and_then(|edges| edges.get(acquired))
+    }
+
+    pub fn get_mut(&mut self, held : &Lock, acquired : &Lock) -> Option<&mut Edge> {
+        self.edges.get_mut(held).and_then(|edges| edges.get_mut(acquired))
+    }
+
+    /// The locks taken while `held` was held, in order.
+    pub fn successors(&self, held : &Lock) -> impl Iterator<Item = (&Lock, &Edge)> {
+        self.edges.get(held).into_iter().flatten()
+    }
+
+    /// The edges along a shortest path from `from` to `to`, none when `to` cannot be reached.
+    pub fn path(&self, from : &Lock, to : &Lock) -> Option<Vec<(Lock, Lock)>> {
+
+        // per lock reached, the lock it was reached from
+        let mut reached : HashMap<&Lock, &Lock> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(lock) = queue.pop_front() {
+
+            if lock == to {
+                let mut path = Vec::new();
+                let mut lock = to;
+                while lock != from {
+                    let previous = reached[lock];
+                    path.push((previous.clone(), lock.clone()));
+                    lock = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for (next, _) in self.successors(lock) {
+                if next != from && !reached.contains_key(next) {
+                    reached.insert(next, lock);
+                    queue.push_back(next);
+                }
+            }
+
+        }
+
+        None
+
+    }
+
+    /// Whether the edge from `held` to `acquired` is part of a cycle.
+    pub fn cyclic(&self, held : &Lock, acquired : &Lock) -> bool {
+        self.get(held, acquired).is_some() && self.path(acquired, held).is_some()
+    }
+
+    /// Adds the edge unless the graph has it already. Returns the way back from `acquired` to
+    /// `held` when the new edge closes a cycle with it.
+    pub fn insert(&mut self, held : Lock, acquired : Lock, edge : Edge) -> Option<Vec<(Lock, Lock)>> {
+
+        if self.get(&held, &acquired).is_some() {
+            return None;
+        }
+
+        // looked for before the edge is added, which cannot be part of the way back
+        let path = self.path(&acquired, &held);
+        self.predecessors.entry(acquired.clone()).or_default().insert(held.clone());
+        self.edges.entry(held).or_default().insert(acquired, edge);
+
+        path
+
+    }
+
+    /// Removes the lock and all of its edges, once it can no longer be taken.
+    pub fn remove(&mut self, lock : &Lock) {
+
+        for acquired in self.edges.remove(lock).into_iter().flat_map(BTreeMap::into_keys) {
+            if let Some(predecessors) = self.predecessors.get_mut(&acquired) {
+                predecessors.remove(lock);
+                if predecessors.is_empty() {
+                    self.predecessors.remove(&acquired);
+                }
+            }
+        }
+
+        for held in self.predecessors.remove(lock).into_iter().flatten() {
+            if let Some(edges) = self.edges.get_mut(&held) {
+                edges.remove(lock);
+                if edges.is_empty() {
+                    self.edges.remove(&held);
+                }
+            }
+        }
+
+    }
+
+    /// Whether the graph has no edges.
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    #[test]
+    pub fn test_insert_finds_the_cycle_an_edge_closes() {
+
+        use super::*;
+
+        let mut graph : LockGraph<char, usize> = LockGraph::new();
+        assert_eq!(graph.insert('a', 'b', 0), None);
+        assert_eq!(graph.insert('b', 'c', 1), None);
+        assert_eq!(graph.insert('a', 'b', 2), None);
+        assert_eq!(graph.get(&'a', &'b'), Some(&0));
+        assert!(!graph.cyclic(&'a', &'b'));
+
+        assert_eq!(graph.insert('c', 'a', 3), Some(vec![('a', 'b'), ('b', 'c')]));
+        assert!(graph.cyclic(&'a', &'b'));
+
+        // forgetting a lock takes its edges with it
+        graph.remove(&'b');
+        assert!(!graph.cyclic(&'c', &'a'));
+        assert_eq!(graph.successors(&'a').count(), 0);
+        assert_eq!(graph.insert('b', 'c', 4), None);
+
+        graph.remove(&'a');
+        graph.remove(&'b');
+        assert!(graph.is_empty());
+
+    }
+
+}
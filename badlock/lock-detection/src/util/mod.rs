@@ -1 +1,2 @@
-pub mod insertion_order_map;
\ No newline at end of file
+pub mod insertion_order_map;
+pub mod lock_graph;
\ No newline at end of file
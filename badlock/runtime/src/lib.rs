@@ -0,0 +1,203 @@
+//! The runtime `reentrant-lock-instrumentation` calls into. Binaries built from instrumented
+//! modules link it with `use badlock_runtime as _;`, so the symbols below are kept.
+//!
+//! A thread about to take a lock it still holds at a reported deadlock prints where it took it
+//! and panics, rather than hanging.
+//!
+//! Each thread keeps a stack of the locks it acquired at instrumented sites. Resources are the
+//! ids the pass gave the lock resources of its findings, hashes of their names that agree across
+//! modules, as releases only know which resource a guard holds, not where the lock lives.
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::Mutex;
+
+/// A lock this thread acquired and has not released yet.
+#[derive(Debug, Clone, Copy)]
+struct Held {
+    resource : u64,
+    // the address of the lock, null (unknown) when the site returned a guard rather than locked it
+    lock : usize,
+    // the acquiring site, a string constant of the instrumented module
+    site : *const c_char
+}
+
+/// A reported deadlock a thread was about to run into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    pub resource : u64,
+    pub message : String,
+    // where the thread acquired the lock it was about to take again
+    pub acquired_at : String,
+    pub thread : Option<String>
+}
+
+impl std::fmt::Display for Hit {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f, "badlock: {}, while thread {} still holds it since {}",
+            self.message, self.thread.as_deref().unwrap_or("<unnamed>"), self.acquired_at
+        )
+    }
+}
+
+thread_local! {
+    static HELD : RefCell<Vec<Held>> = const { RefCell::new(Vec::new()) };
+}
+
+static HITS : Mutex<Vec<Hit>> = Mutex::new(Vec::new());
+
+/// The deadlocks hit so far, by any thread.
+pub fn hits() -> Vec<Hit> {
+    HITS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+unsafe fn text(text : *const c_char) -> String {
+
+    if text.is_null() {
+        return String::from("<unknown>");
+    }
+
+    CStr::from_ptr(text).to_string_lossy().into_owned()
+
+}
+
+/// Called before a site acquires `lock`, a lock of `resource`.
+#[no_mangle]
+pub extern "C" fn badlock_acquired(resource : u64, lock : *const u8, site : *const c_char) {
+    // none of the calls unwind, not even while the thread's locals are torn down
+    let _ = HELD.try_with(|held| held.borrow_mut().push(Held {
+        resource,
+        lock : lock as usize,
+        site
+    }));
+}
+
+/// Called before a site releases a lock of `resource`, the latest one this thread acquired.
+#[no_mangle]
+pub extern "C" fn badlock_released(resource : u64) {
+    let _ = HELD.try_with(|held| {
+        let mut held = held.borrow_mut();
+        if let Some(index) = held.iter().rposition(|entry| entry.resource == resource) {
+            held.remove(index);
+        }
+    });
+}
+
+/// Whether this thread still holds `lock`, a lock of `resource`, recording the hit if it does.
+/// A null lock is of unknown address and matches no other, as a resource may stand for many locks.
+///
+/// # Safety
+///
+/// `message` is null or a nul terminated string, as are the sites passed to `badlock_acquired`.
+pub unsafe fn check(resource : u64, lock : *const u8, message : *const c_char) -> Option<Hit> {
+
+    let lock = lock as usize;
+    let held = HELD.try_with(|held| held.borrow().iter().rev()
+        .find(|entry| entry.resource == resource && entry.lock == lock && lock != 0)
+        .copied()
+    ).ok().flatten()?;
+
+    let hit = Hit {
+        resource,
+        message : text(message),
+        acquired_at : text(held.site),
+        thread : std::thread::current().name().map(String::from)
+    };
+    HITS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(hit.clone());
+
+    Some(hit)
+
+}
+
+/// Called before a site a finding reports locks `lock` again. When this thread still holds it,
+/// prints the hit and panics, as the thread would never get the lock. The pass declares the call
+/// with the `C-unwind` ABI, so the panic reaches the test harness, which sees the hit in `hits`.
+///
+/// # Safety
+///
+/// See `check`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn badlock_check(resource : u64, lock : *const u8, message : *const c_char) {
+
+    if let Some(hit) = check(resource, lock, message) {
+        eprintln!("{}", hit);
+        panic!("{}", hit);
+    }
+
+}
+
+#[cfg(test)]
+pub mod test {
+
+    pub fn hits_of(resource : u64) -> Vec<super::Hit> {
+        super::hits().into_iter().filter(|hit| hit.resource == resource).collect()
+    }
+
+    #[test]
+    pub fn test_check_finds_held_lock() {
+
+        use super::*;
+
+        let lock = 0x1000 as *const u8;
+        let site = b"main bb0 #1\0".as_ptr() as *const c_char;
+        let message = b"m is locked again at main bb0 #2\0".as_ptr() as *const c_char;
+
+        badlock_acquired(1, lock, site);
+
+        // another lock of the same resource is not held
+        unsafe { badlock_check(1, 0x2000 as *const u8, message) };
+        assert!(hits_of(1).is_empty());
+
+        let hit = unsafe { check(1, lock, message) }.unwrap();
+        assert_eq!(
+            hit.to_string(),
+            "badlock: m is locked again at main bb0 #2, while thread test::test_check_finds_held_lock still holds it since main bb0 #1"
+        );
+
+        let hits = hits_of(1);
+        assert_eq!(hits, vec![hit]);
+        assert_eq!(hits[0].acquired_at, "main bb0 #1");
+
+        // a hit unwinds out of the check
+        let panic = std::panic::catch_unwind(|| unsafe { badlock_check(1, lock, message) }).unwrap_err();
+        assert_eq!(panic.downcast_ref::<String>(), Some(&hits[0].to_string()));
+        assert_eq!(hits_of(1).len(), 2);
+
+        badlock_released(1);
+        unsafe { badlock_check(1, lock, message) };
+        assert_eq!(hits_of(1).len(), 2);
+
+    }
+
+    #[test]
+    pub fn test_releases_are_per_thread_and_resource() {
+
+        use super::*;
+
+        let lock = 0x3000 as *const u8;
+        let site = b"f bb0 #0\0".as_ptr() as *const c_char;
+
+        badlock_acquired(2, lock, site);
+        badlock_acquired(3, std::ptr::null(), site);
+        badlock_released(2);
+
+        // held by this thread only
+        std::thread::spawn(|| unsafe { badlock_check(3, 0x3000 as *const u8, std::ptr::null()) }).join().unwrap();
+        assert!(hits_of(3).is_empty());
+
+        // the guard returned at the site may hold any lock of the resource, so it is not told to
+        // be `lock`, nor is an unknown lock told to be held
+        assert!(unsafe { check(3, lock, std::ptr::null()) }.is_none());
+        assert!(unsafe { check(3, std::ptr::null(), std::ptr::null()) }.is_none());
+        unsafe { badlock_check(2, lock, std::ptr::null()) };
+
+        badlock_acquired(3, lock, site);
+        assert_eq!(unsafe { check(3, lock, std::ptr::null()) }.map(|hit| hit.message), Some(String::from("<unknown>")));
+        assert_eq!(hits_of(3).len(), 1);
+        assert!(hits_of(2).is_empty());
+
+    }
+
+}
@@ -0,0 +1,26 @@
+use std::sync::{Arc, Mutex};
+
+fn bump(counter : &Arc<Mutex<i32>>) {
+
+    let _guard = counter.lock().unwrap();
+    let mut deadlock = counter.lock().unwrap();
+    *deadlock += 1;
+
+}
+
+fn main() {
+
+    let counter = Arc::new(Mutex::new(0));
+    let shared = counter.clone();
+
+    let mut guard = counter.lock().unwrap();
+    *guard += 1;
+    println!("Here is fine x: {}", *guard);
+
+    let mut deadlock = shared.lock().unwrap();
+    *deadlock += 1;
+    println!("Should never get here x: {}", *deadlock);
+
+    bump(&shared);
+
+}
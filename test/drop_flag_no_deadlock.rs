@@ -0,0 +1,20 @@
+use std::sync::Mutex;
+
+fn main() {
+
+    let safe_x = Mutex::new(64);
+    let conditional = std::env::args().count() > 1;
+
+    {
+        let guard = safe_x.lock().unwrap();
+        if conditional {
+            drop(guard);
+        }
+        // `guard` is dropped here only if it was not moved, as its drop flag records
+    }
+
+    let mut again = safe_x.lock().unwrap();
+    *again += 1;
+    println!("Here is fine x: {}", *again);
+
+}
@@ -0,0 +1,22 @@
+use std::sync::{Mutex, MutexGuard};
+
+fn consume(guard : MutexGuard<i32>) {
+    println!("Consumed x: {}", *guard);
+}
+
+fn main() {
+
+    let safe_x = Mutex::new(64);
+
+    let mut guard = safe_x.lock().unwrap();
+    *guard += 1;
+    drop(guard);
+
+    let mut again = safe_x.lock().unwrap();
+    *again += 1;
+    consume(again);
+
+    let last = safe_x.lock().unwrap();
+    println!("Here is fine x: {}", *last);
+
+}
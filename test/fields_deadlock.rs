@@ -0,0 +1,32 @@
+use std::sync::Mutex;
+
+struct State {
+    a : Mutex<i32>,
+    b : Mutex<i32>
+}
+
+impl State {
+
+    // different fields, no deadlock
+    fn both(&self) {
+        let _a = self.a.lock().unwrap();
+        let _b = self.b.lock().unwrap();
+    }
+
+}
+
+fn main() {
+
+    let state = State { a : Mutex::new(0), b : Mutex::new(1) };
+    state.both();
+
+    let first = &state.b;
+    let mut guard = first.lock().unwrap();
+    *guard += 1;
+    println!("Here is fine x: {}", *guard);
+
+    let mut deadlock = state.b.lock().unwrap();
+    *deadlock += 1;
+    println!("Should never get here x: {}", *deadlock);
+
+}
@@ -0,0 +1,30 @@
+use std::sync::{Mutex, OnceLock};
+
+static COUNTER : Mutex<i32> = Mutex::new(0);
+
+fn registry() -> &'static Mutex<Vec<i32>> {
+    static REGISTRY : OnceLock<Mutex<Vec<i32>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn bump() {
+    let mut deadlock = COUNTER.lock().unwrap();
+    *deadlock += 1;
+    println!("Should never get here x: {}", *deadlock);
+}
+
+fn register(value : i32) {
+    registry().lock().unwrap().push(value);
+}
+
+fn main() {
+
+    let mut guard = COUNTER.lock().unwrap();
+    *guard += 1;
+    println!("Here is fine x: {}", *guard);
+    bump();
+
+    let _registered = registry().lock().unwrap();
+    register(1);
+
+}
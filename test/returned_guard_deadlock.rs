@@ -0,0 +1,37 @@
+use std::sync::{Mutex, MutexGuard};
+
+struct Store {
+    state : Mutex<i32>
+}
+
+struct Holder<'a> {
+    id : u64,
+    guard : Option<MutexGuard<'a, i32>>
+}
+
+impl Store {
+
+    fn state(&self) -> MutexGuard<'_, i32> {
+        self.state.lock().unwrap()
+    }
+
+}
+
+fn main() {
+
+    let store = Store { state : Mutex::new(64) };
+
+    // the holder releases the lock when it is dropped
+    let holder = Holder { id : 1, guard : Some(store.state()) };
+    println!("Holder {} is fine", holder.id);
+    drop(holder);
+
+    let mut guard = store.state();
+    *guard += 1;
+    println!("Here is fine x: {}", *guard);
+
+    let mut deadlock = store.state();
+    *deadlock += 1;
+    println!("Should never get here x: {}", *deadlock);
+
+}